@@ -0,0 +1,32 @@
+//! Flag parsing shared by the subcommands.
+
+/// Values of every `--flag value` and `--flag=value` occurrence in `args`.
+pub fn flag_values(args: &[String], flag: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == flag {
+            values.extend(iter.next().cloned());
+        } else if let Some(value) = arg
+            .strip_prefix(flag)
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            values.push(value.to_string());
+        }
+    }
+    values
+}
+
+/// Arguments that are neither flags nor the values of `value_flags`.
+pub fn positional_args<'a>(args: &'a [String], value_flags: &[&str]) -> Vec<&'a String> {
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if value_flags.contains(&arg.as_str()) {
+            iter.next();
+        } else if !arg.starts_with('-') {
+            positional.push(arg);
+        }
+    }
+    positional
+}
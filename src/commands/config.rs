@@ -0,0 +1,165 @@
+use crate::args::flag_values;
+use stack_core::config::{
+    REPO_CONFIG_FILE, setting_all, trunk, user_config_path, write_toml_setting,
+};
+use stack_core::error::{StackError, StackResult, err};
+use stack_core::forge::{authenticated_forge, get_forge};
+use stack_core::git::{branch_exists, git_config, repo_root};
+use stack_core::info;
+use stack_core::metadata::{adopt, get_parent, graphite_parents};
+
+/// Adopt an existing stack: parents come from Graphite's metadata refs when
+/// there are any (or with `--from graphite`), otherwise from the base
+/// branches of open PRs (`--from prs`). Branches that already have a parent
+/// are left alone unless `--force` is given.
+pub fn cmd_onboard(args: &[String]) -> StackResult<()> {
+    let force = args.iter().any(|a| a == "--force");
+    let source = flag_values(args, "--from").pop();
+
+    let parents = match source.as_deref() {
+        Some("graphite") => graphite_parents()?,
+        Some("prs") => Vec::new(),
+        Some(other) => {
+            return Err(StackError::Usage(format!(
+                "Unknown source '{}' (expected graphite or prs)",
+                other
+            )));
+        }
+        None => graphite_parents()?,
+    };
+    let (parents, from) = if parents.is_empty() && source.as_deref() != Some("graphite") {
+        let bases = get_forge()?
+            .open_pr_bases()?
+            .into_iter()
+            .map(|(head, base)| (head, base, None))
+            .collect();
+        (bases, "open PRs")
+    } else {
+        (parents, "Graphite metadata")
+    };
+
+    let mut adopted = 0;
+    for (branch, parent, base) in parents {
+        if !branch_exists(&branch)? || !branch_exists(&parent)? || branch == parent {
+            continue;
+        }
+        if get_parent(&branch).is_some() && !force {
+            info!("  {} already has a parent; skipping", branch);
+            continue;
+        }
+
+        adopt(&branch, &parent, base)?;
+        info!("  {} -> {}", branch, parent);
+        adopted += 1;
+    }
+
+    info!("Onboarded {} branch(es) from {}.", adopted, from);
+    Ok(())
+}
+
+/// `stack fix --from-prs`: rebuild lost or stale `stack-parent` links from
+/// the bases of open PRs, which on GitHub point at each branch's parent.
+/// Unlike `onboard`, parents that disagree with the PR are corrected too.
+/// `--dry-run` only lists what would change.
+pub fn cmd_fix(args: &[String]) -> StackResult<()> {
+    if !args.iter().any(|a| a == "--from-prs") {
+        return Err(StackError::Usage(
+            "Usage: stack fix --from-prs [--dry-run]".to_string(),
+        ));
+    }
+    let dry_run = args.iter().any(|a| a == "--dry-run" || a == "-n");
+    let trunk = trunk();
+
+    let mut bases = authenticated_forge()?.open_pr_bases()?;
+    bases.sort();
+    let mut fixed = 0;
+    for (branch, base) in bases {
+        // Someone else's PR
+        if !branch_exists(&branch)? || branch == base {
+            continue;
+        }
+        if base != trunk && !branch_exists(&base)? {
+            eprintln!(
+                "Warning: skipping {}: its PR is based on {}, which isn't a local branch",
+                branch, base
+            );
+            continue;
+        }
+        let was = match get_parent(&branch) {
+            Some(parent) if parent == base => continue,
+            Some(parent) => format!("was {}", parent),
+            None => "had no parent".to_string(),
+        };
+
+        let verb = if dry_run { "Would set" } else { "Setting" };
+        println!("{} {} -> {} ({})", verb, branch, base, was);
+        if !dry_run {
+            adopt(&branch, &base, None)?;
+        }
+        fixed += 1;
+    }
+
+    if fixed == 0 {
+        println!("Every branch with an open PR already has the PR's base as its parent.");
+    } else if !dry_run {
+        println!(
+            "Fixed {} branch(es). Run `stack log` to check the result.",
+            fixed
+        );
+    }
+    Ok(())
+}
+
+pub fn cmd_config(args: &[String]) -> StackResult<()> {
+    let usage = || {
+        StackError::Usage(
+            "Usage: stack config get <key> | stack config set [--user] <key> <value>..."
+                .to_string(),
+        )
+    };
+
+    match args.first().map(String::as_str) {
+        Some("get") => {
+            let key = args.get(1).ok_or_else(usage)?;
+            let values = setting_all(key);
+            if values.is_empty() {
+                return Err(err(&format!("{} is not set", key)));
+            }
+            for value in values {
+                println!("{}", value);
+            }
+            Ok(())
+        }
+        Some("set") => {
+            let user = args.iter().any(|a| a == "--user");
+            let rest: Vec<String> = args[1..]
+                .iter()
+                .filter(|a| *a != "--user")
+                .cloned()
+                .collect();
+            let [key, values @ ..] = rest.as_slice() else {
+                return Err(usage());
+            };
+            if values.is_empty() {
+                return Err(usage());
+            }
+
+            let path = if user {
+                user_config_path().ok_or_else(|| err("Cannot find the user config directory"))?
+            } else {
+                repo_root()?.join(REPO_CONFIG_FILE)
+            };
+            write_toml_setting(&path, key, values)?;
+            info!("Set {} in {}", key, path.display());
+
+            if git_config(&format!("stack.{}", key)).is_some() {
+                println!(
+                    "Note: git config stack.{} is also set and takes precedence",
+                    key
+                );
+            }
+            Ok(())
+        }
+        _ => Err(usage()),
+    }
+}
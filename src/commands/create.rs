@@ -0,0 +1,179 @@
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::args::{flag_values, positional_args};
+use stack_core::config::setting;
+use stack_core::engine::{RestackPlan, Stack};
+use stack_core::error::{StackError, StackResult, err};
+use stack_core::git::{
+    branch_exists, git, git_passthrough, has_staged_changes, require_current_branch, set_config,
+};
+use stack_core::info;
+use stack_core::metadata::{set_base, set_frozen, set_note, set_order};
+use stack_core::naming::{branch_name, templated_branch_name};
+use stack_core::ui::prompt;
+
+/// Create a branch stacked on the current one (or on `--parent`) and return
+/// its name.
+///
+/// The argument is used as the name unless it contains whitespace, in which
+/// case it is a description turned into a name by `templated_branch_name`.
+/// With no argument and staged changes, stack asks for a description, names
+/// the branch after it and commits the changes with it as the message.
+/// `--commit` commits staged changes onto the new branch, with `-m` as the
+/// message or in the editor; `-m` alone implies `--commit`.
+fn create_branch(args: &[String], command: &str) -> StackResult<String> {
+    let mut messages = flag_values(args, "-m");
+    messages.extend(flag_values(args, "--message"));
+    let commit = !messages.is_empty() || args.iter().any(|a| a == "--commit");
+    if commit && !has_staged_changes()? {
+        return Err(err("Nothing staged to commit"));
+    }
+
+    let positional = positional_args(args, &["-m", "--message", "--parent"]);
+    let name = match positional.first() {
+        Some(arg) if arg.chars().any(char::is_whitespace) => templated_branch_name(arg)?,
+        Some(arg) => branch_name(arg),
+        None if messages.len() == 1 => templated_branch_name(&messages[0])?,
+        None if messages.is_empty() && has_staged_changes()? => {
+            let description = prompt("Describe the change: ")?;
+            let name = templated_branch_name(&description)?;
+            messages.push(description);
+            name
+        }
+        None => {
+            return Err(StackError::Usage(format!(
+                "Usage: stack {} <branch-name|\"description\"> [--parent <branch>] [--commit] [-m <message>]",
+                command
+            )));
+        }
+    };
+
+    let parent = match flag_values(args, "--parent").pop() {
+        Some(parent) if !branch_exists(&parent)? => {
+            return Err(err(&format!("Parent branch '{}' does not exist", parent)));
+        }
+        Some(parent) => parent,
+        None => require_current_branch(command)?,
+    };
+    info!("Creating branch '{}' tracking parent '{}'", name, parent);
+
+    git(&["checkout", "-b", &name, &parent])?;
+    set_config(&format!("branch.{}.stack-parent", name), &parent)?;
+    set_base(&name, "HEAD")?;
+    // New siblings go after the ones already there. Milliseconds, so
+    // branches created in quick succession keep their order
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    set_order(&name, created)?;
+
+    if !messages.is_empty() || commit {
+        let mut commit_args = vec!["commit"];
+        for message in &messages {
+            commit_args.extend_from_slice(&["-m", message]);
+        }
+        git_passthrough(&commit_args)?;
+    }
+    Ok(name)
+}
+
+pub fn cmd_new(args: &[String]) -> StackResult<()> {
+    create_branch(args, "new")?;
+    Ok(())
+}
+
+pub fn cmd_insert(args: &[String]) -> StackResult<()> {
+    let parent = match flag_values(args, "--parent").pop() {
+        Some(parent) => parent,
+        None => require_current_branch("insert")?,
+    };
+    let children = Stack::load()?.children(&parent).to_vec();
+    let name = &create_branch(args, "insert")?;
+
+    for child in &children {
+        info!("Moving {} onto {}", child, name);
+        set_config(&format!("branch.{}.stack-parent", child), name)?;
+    }
+
+    RestackPlan::above(&Stack::load()?, name, &HashSet::new())?.execute(name)?;
+    git(&["checkout", name])?;
+    Ok(())
+}
+
+/// Copy a branch (the current one unless named) and everything stacked on
+/// it to new names, to try another approach without touching the original.
+/// Each copy is named with `--prefix` and `--suffix` (or `stack.clone-prefix`
+/// and `stack.clone-suffix`; `-alt` when neither is set) and keeps the
+/// original's stack metadata. PRs stay with the originals; the copies get
+/// their own once submitted.
+pub fn cmd_clone_stack(args: &[String]) -> StackResult<()> {
+    let branch = match positional_args(args, &["--prefix", "--suffix"]).as_slice() {
+        [] => require_current_branch("clone-stack")?,
+        [branch] => branch.to_string(),
+        _ => {
+            return Err(StackError::Usage(
+                "Usage: stack clone-stack [<branch>] [--prefix <prefix>] [--suffix <suffix>]"
+                    .to_string(),
+            ));
+        }
+    };
+    let stack = Stack::load()?;
+    let Some(parent) = stack.parent(&branch) else {
+        return Err(err(&format!("{} is not stacked on anything", branch)));
+    };
+
+    let prefix = flag_values(args, "--prefix")
+        .pop()
+        .or_else(|| setting("clone-prefix"))
+        .unwrap_or_default();
+    let suffix = flag_values(args, "--suffix")
+        .pop()
+        .or_else(|| setting("clone-suffix"))
+        .unwrap_or_else(|| if prefix.is_empty() { "-alt" } else { "" }.to_string());
+    if prefix.is_empty() && suffix.is_empty() {
+        return Err(StackError::Usage(
+            "clone-stack needs a --prefix or --suffix to name the copies".to_string(),
+        ));
+    }
+    let copy_of = |name: &str| format!("{}{}{}", prefix, name, suffix);
+
+    let mut originals = vec![branch.clone()];
+    originals.extend(stack.descendants(&branch));
+    for original in &originals {
+        if branch_exists(&copy_of(original))? {
+            return Err(err(&format!(
+                "Branch '{}' already exists",
+                copy_of(original)
+            )));
+        }
+    }
+
+    for original in &originals {
+        let copy = copy_of(original);
+        let copy_parent = match stack.parent(original) {
+            Some(p) if *original != branch => copy_of(p),
+            _ => parent.to_string(),
+        };
+        git(&["branch", "--quiet", &copy, original])?;
+        set_config(&format!("branch.{}.stack-parent", copy), &copy_parent)?;
+        if let Some(meta) = stack.branch(original) {
+            if let Some(base) = &meta.base {
+                set_base(&copy, base)?;
+            }
+            if let Some(order) = meta.order {
+                set_order(&copy, order)?;
+            }
+            if meta.frozen {
+                set_frozen(&copy, true)?;
+            }
+            set_note(&copy, meta.note.as_deref())?;
+        }
+        info!("Copied {} to {}", original, copy);
+    }
+    info!(
+        "Run `stack switch {}` to work on the copy.",
+        copy_of(&branch)
+    );
+    Ok(())
+}
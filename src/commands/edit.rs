@@ -0,0 +1,404 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::process::Command;
+
+use git2::Oid;
+
+use crate::args::flag_values;
+use crate::commands::restack::cmd_restack;
+use stack_core::absorb::{StagedHunk, splice_hunks, staged_hunks};
+use stack_core::config::ensure_unprotected;
+use stack_core::engine::{RestackPlan, Stack};
+use stack_core::error::{StackError, StackResult, err};
+use stack_core::git::{
+    commit_ids, commit_messages, ensure_clean_worktree, git, git_passthrough,
+    git_supports_update_refs, has_staged_changes, is_fixup, open_repo, require_current_branch,
+    rev_parse, trace, try_command,
+};
+use stack_core::info;
+use stack_core::metadata::{get_parent, own_commits_base, require_parent, set_base};
+
+/// Amend the current commit, then restack the branches above it. Takes
+/// git's `-m`, `-a`/`--all` and `-e`/`--edit`; `--no-restack` stops after
+/// the amend. With `--insert` the changes go into a new `fixup!` commit
+/// instead, so reviewers see them on their own and review state survives
+/// the push; `stack autosquash` folds them in before landing.
+pub fn cmd_amend(args: &[String]) -> StackResult<()> {
+    let has = |flags: &[&str]| args.iter().any(|a| flags.contains(&a.as_str()));
+    ensure_unprotected(&require_current_branch("amend")?, "amend")?;
+    let mut messages = flag_values(args, "-m");
+    messages.extend(flag_values(args, "--message"));
+    let insert = has(&["--insert"]);
+
+    let mut commit_args = vec!["commit", if insert { "--fixup=HEAD" } else { "--amend" }];
+    if has(&["-a", "--all"]) {
+        commit_args.push("--all");
+    }
+    for message in &messages {
+        commit_args.extend_from_slice(&["-m", message]);
+    }
+    if has(&["-e", "--edit"]) {
+        commit_args.push("--edit");
+    } else if messages.is_empty() && !insert {
+        commit_args.push("--no-edit");
+    }
+
+    info!(
+        "{}",
+        if insert {
+            "Adding a fixup commit..."
+        } else {
+            "Amending..."
+        }
+    );
+    git_passthrough(&commit_args)?;
+
+    if has(&["--no-restack"]) {
+        info!("Skipping restack; run `stack restack` when you're done.");
+        return Ok(());
+    }
+    cmd_restack(&[])
+}
+
+/// Fold the `fixup!`, `squash!` and `amend!` commits in the stack through
+/// the current branch into the commits they name, as `git rebase
+/// --autosquash` does, moving every branch in the stack with them. Meant
+/// for right before `stack land`.
+pub fn cmd_autosquash() -> StackResult<()> {
+    let current = require_current_branch("autosquash")?;
+    require_parent(&current)?;
+    let stack = Stack::load()?.path_to_trunk(&current);
+    let mut fixups = 0;
+    for branch in &stack {
+        let count = commit_messages(&own_commits_base(branch), branch)?
+            .iter()
+            .filter(|m| is_fixup(m))
+            .count();
+        if count > 0 {
+            ensure_unprotected(branch, "autosquash")?;
+        }
+        fixups += count;
+    }
+    if fixups == 0 {
+        info!("No fixup commits to squash.");
+        return Ok(());
+    }
+    if stack.len() > 1 && !git_supports_update_refs() {
+        return Err(err("stack autosquash needs git 2.38 or newer"));
+    }
+    ensure_clean_worktree("autosquashing")?;
+
+    info!("Squashing {} fixup(s) into the stack...", fixups);
+    autosquash(&stack, &current, &own_commits_base(&stack[0]), "")?;
+    info!("Done.");
+    Ok(())
+}
+
+/// Autosquash `stack` (bottom-up, ending at `current`) from `base`, with
+/// `--update-refs` so every branch moves along, then record the new bases,
+/// restack whatever else hangs off the stack and return to `current`.
+/// `hint` is added to conflict messages.
+fn autosquash(stack: &[String], current: &str, base: &str, hint: &str) -> StackResult<()> {
+    let mut args = vec!["rebase", "-i", "--autosquash"];
+    if stack.len() > 1 {
+        args.push("--update-refs");
+    }
+    args.push(base);
+    trace("git", &args);
+    let status = Command::new("git")
+        .args(&args)
+        .env("GIT_SEQUENCE_EDITOR", "true")
+        .status()?;
+    if !status.success() {
+        return Err(StackError::Conflict(format!(
+            "Rebase failed. Resolve the conflicts and run `stack continue`.{}",
+            hint
+        )));
+    }
+
+    // Branches in the stack moved together; record their new bases and
+    // restack everything else that hangs off them.
+    let tree = Stack::load()?;
+    for branch in stack {
+        if let Some(parent) = get_parent(branch)
+            && stack.contains(&parent)
+        {
+            set_base(branch, &parent)?;
+        }
+    }
+    let restack = || -> StackResult<()> {
+        let mut plan = RestackPlan::new(Vec::new(), HashSet::new());
+        for branch in stack {
+            for child in tree.children(branch) {
+                if !stack.contains(child) {
+                    plan.steps
+                        .extend(RestackPlan::including(&tree, child, &HashSet::new())?.steps);
+                }
+            }
+        }
+        plan.execute(current)?;
+        git(&["checkout", current])?;
+        Ok(())
+    };
+    restack().map_err(|e| match e {
+        StackError::Conflict(message) => StackError::Conflict(message + hint),
+        e => e,
+    })
+}
+
+/// Squash the current branch's own commits into one, keeping the first
+/// commit's author, date and (without `-m`) message, then restack children.
+/// The commit runs the usual commit hooks unless `--no-verify`.
+pub fn cmd_squash(args: &[String]) -> StackResult<()> {
+    let current = require_current_branch("squash")?;
+    ensure_unprotected(&current, "squash")?;
+    require_parent(&current)?;
+    let upstream = own_commits_base(&current);
+
+    let commits = commit_ids(&upstream, &current)?;
+    if commits.len() < 2 {
+        info!(
+            "Nothing to squash: {} has {} commit(s).",
+            current,
+            commits.len()
+        );
+        return Ok(());
+    }
+    if has_staged_changes()? {
+        return Err(StackError::DirtyTree(
+            "You have staged changes; commit or unstage them first".to_string(),
+        ));
+    }
+
+    let repo = open_repo()?;
+    let first = repo.find_commit(commits[0])?;
+    let author = first.author();
+    let author_arg = format!("{} <{}>", author.name()?, author.email()?);
+    let when = author.when();
+    let offset = when.offset_minutes();
+    let date_arg = format!(
+        "@{} {}{:02}{:02}",
+        when.seconds(),
+        if offset < 0 { '-' } else { '+' },
+        offset.abs() / 60,
+        offset.abs() % 60
+    );
+    let message = match flag_values(args, "-m").pop() {
+        Some(message) => message,
+        None => first.message()?.to_string(),
+    };
+
+    info!("Squashing {} commits on {}...", commits.len(), current);
+    let tip = rev_parse(&current)?;
+    git(&["reset", "--soft", &upstream])?;
+    let mut commit = vec![
+        "commit",
+        "--author",
+        &author_arg,
+        "--date",
+        &date_arg,
+        "-m",
+        &message,
+    ];
+    if args.iter().any(|a| a == "--no-verify") {
+        commit.push("--no-verify");
+    }
+    if let Err(e) = git(&commit) {
+        // A hook said no: put the commits back as they were
+        git(&["reset", "--quiet", "--soft", &tip])?;
+        return Err(e);
+    }
+
+    info!("Restacking children of {}...", current);
+    RestackPlan::above(&Stack::load()?, &current, &HashSet::new())?.execute(&current)?;
+    git(&["checkout", &current])?;
+    Ok(())
+}
+
+/// Fold staged changes into the stack commits that last touched the same
+/// lines: each hunk becomes a `fixup!` commit for its target, then the stack
+/// is autosquashed with `--update-refs` so every branch moves with it. Hunks
+/// with no single target in the stack stay staged. The fixup commits run the
+/// usual commit hooks unless `--no-verify`.
+pub fn cmd_absorb(args: &[String]) -> StackResult<()> {
+    if !git_supports_update_refs() {
+        return Err(err("stack absorb needs git 2.38 or newer"));
+    }
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let current = require_current_branch("absorb")?;
+    ensure_unprotected(&current, "absorb")?;
+    let stack = Stack::load()?.path_to_trunk(&current);
+
+    // Which branch each commit in the stack belongs to
+    let mut owner: HashMap<Oid, String> = HashMap::new();
+    let mut order = Vec::new();
+    for branch in &stack {
+        for oid in commit_ids(&own_commits_base(branch), branch)? {
+            owner.insert(oid, branch.clone());
+            order.push(oid);
+        }
+    }
+
+    let repo = open_repo()?;
+    let head = repo.head()?.peel_to_commit()?;
+    let staged_id = repo.index()?.write_tree()?;
+    let staged = repo.find_tree(staged_id)?;
+    let hunks = staged_hunks(&repo, &head.tree()?, &staged)?;
+    if hunks.is_empty() {
+        return Err(err("Nothing staged to absorb"));
+    }
+
+    let mut blames = HashMap::new();
+    let mut targets = Vec::new();
+    for hunk in &hunks {
+        if !hunk.modified {
+            println!(
+                "  {} stays staged (new, deleted or renamed file)",
+                hunk.path.display()
+            );
+            targets.push(None);
+            continue;
+        }
+        if !blames.contains_key(&hunk.path) {
+            let mut opts = git2::BlameOptions::new();
+            opts.newest_commit(head.id());
+            blames.insert(
+                hunk.path.clone(),
+                repo.blame_file(&hunk.path, Some(&mut opts)).ok(),
+            );
+        }
+
+        // Replaced lines must all come from one stack commit. Pure additions
+        // go with the lines around them.
+        let lines: Vec<u32> = if hunk.old_lines > 0 {
+            (hunk.old_start..hunk.old_start + hunk.old_lines).collect()
+        } else {
+            vec![hunk.old_start, hunk.old_start + 1]
+        };
+        let commits: HashSet<Oid> = match &blames[&hunk.path] {
+            Some(blame) => lines
+                .iter()
+                .filter_map(|&line| blame.get_line(line as usize))
+                .map(|b| b.final_commit_id())
+                .collect(),
+            None => HashSet::new(),
+        };
+        let owned: Vec<&Oid> = commits.iter().filter(|c| owner.contains_key(c)).collect();
+        let target = match owned.as_slice() {
+            [only] if hunk.old_lines == 0 || owned.len() == commits.len() => Some(**only),
+            _ => None,
+        };
+
+        let line = hunk.old_start.max(1);
+        match target {
+            Some(oid) => println!(
+                "  {}:{} -> {} ({} {})",
+                hunk.path.display(),
+                line,
+                owner[&oid],
+                &oid.to_string()[..7],
+                repo.find_commit(oid)?.summary()?.unwrap_or_default()
+            ),
+            None => println!(
+                "  {}:{} stays staged (no single target in the stack)",
+                hunk.path.display(),
+                line
+            ),
+        }
+        targets.push(target);
+    }
+
+    let mut fixups: Vec<Oid> = order
+        .iter()
+        .filter(|o| targets.contains(&Some(**o)))
+        .copied()
+        .collect();
+    if fixups.is_empty() {
+        info!("Nothing to absorb.");
+        return Ok(());
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    // One fixup commit per target, built straight from HEAD's blobs: the
+    // k-th fixup's tree has the hunks of the first k targets spliced in.
+    let head_tree = head.tree()?;
+    let mut absorbed: Vec<&StagedHunk> = Vec::new();
+    for &target in &fixups {
+        absorbed.extend(
+            hunks
+                .iter()
+                .zip(&targets)
+                .filter(|(_, t)| **t == Some(target))
+                .map(|(h, _)| h),
+        );
+
+        let mut update = git2::build::TreeUpdateBuilder::new();
+        let paths: HashSet<&PathBuf> = absorbed.iter().map(|h| &h.path).collect();
+        for path in paths {
+            let entry = head_tree.get_path(path)?;
+            let blob = repo.find_blob(entry.id())?;
+            let file_hunks: Vec<&StagedHunk> = absorbed
+                .iter()
+                .filter(|h| h.path == *path)
+                .copied()
+                .collect();
+            let content = splice_hunks(blob.content(), &file_hunks);
+            let mode = match entry.filemode() {
+                0o100755 => git2::FileMode::BlobExecutable,
+                _ => git2::FileMode::Blob,
+            };
+            update.upsert(path, repo.blob(&content)?, mode);
+        }
+        let tree = repo.find_tree(update.create_updated(&repo, &head_tree)?)?;
+
+        let mut index = repo.index()?;
+        index.read_tree(&tree)?;
+        index.write()?;
+        let message = format!("fixup! {}", target);
+        let mut commit = vec!["commit", "-m", &message];
+        if args.iter().any(|a| a == "--no-verify") {
+            commit.push("--no-verify");
+        }
+        if let Err(e) = git(&commit) {
+            // A hook said no: drop the fixups made so far, staged changes intact
+            git(&["reset", "--quiet", "--soft", &head.id().to_string()])?;
+            restore_index(staged_id)?;
+            return Err(e);
+        }
+    }
+
+    // Whatever wasn't absorbed goes back into the index
+    restore_index(staged_id)?;
+
+    // Set leftovers aside, staged state included, while branches are rebased
+    let stashed = try_command("git", &["diff", "--quiet", "HEAD"]).is_none();
+    if stashed {
+        git(&["stash", "push", "--quiet", "-m", "stack absorb"])?;
+    }
+    let unstash_hint = if stashed {
+        " Your remaining changes are stashed; restore them with `git stash pop --index`."
+    } else {
+        ""
+    };
+
+    info!("Absorbing {} fixup(s) into the stack...", fixups.len());
+    let oldest = fixups.remove(0);
+    autosquash(&stack, &current, &format!("{}^", oldest), unstash_hint)?;
+
+    if stashed {
+        git(&["stash", "pop", "--quiet", "--index"])?;
+    }
+    info!("Done.");
+    Ok(())
+}
+
+/// Make the index `tree` again.
+fn restore_index(tree: Oid) -> StackResult<()> {
+    let repo = open_repo()?;
+    let mut index = repo.index()?;
+    index.read_tree(&repo.find_tree(tree)?)?;
+    index.write()?;
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use std::process::Command;
+
+use stack_core::engine::Stack;
+use stack_core::error::{StackError, StackResult, err};
+use stack_core::git::{ensure_clean_worktree, git, require_current_branch, rev_parse};
+use stack_core::lock::LOCK_HELD_ENV;
+use stack_core::process::shell_command;
+use stack_core::test_results::{cached_result, record_result, test_command};
+
+/// Run a command on each branch of the current stack, bottom-up. One
+/// argument runs through `sh -c`; several are run as-is. Stops at the first
+/// failure unless `--continue-on-error`.
+pub fn cmd_foreach(args: &[String]) -> StackResult<()> {
+    let (flags, command) = match args.iter().position(|a| a == "--") {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => (&[][..], args),
+    };
+    let keep_going = flags.iter().any(|a| a == "--continue-on-error");
+    if command.is_empty() {
+        return Err(StackError::Usage(
+            "Usage: stack foreach [--continue-on-error] -- <command>".to_string(),
+        ));
+    }
+
+    ensure_clean_worktree("running foreach")?;
+    let start_branch = require_current_branch("foreach")?;
+    let stack = Stack::load()?;
+    let mut branches = stack.path_to_trunk(&start_branch);
+    branches.extend(stack.descendants(&start_branch));
+
+    let mut results: Vec<(String, String)> = Vec::new();
+    let mut failed = 0;
+    for branch in &branches {
+        if failed > 0 && !keep_going {
+            results.push((branch.clone(), "skipped".to_string()));
+            continue;
+        }
+
+        println!("==> {}", branch);
+        git(&["checkout", "--quiet", branch])?;
+        let result = match run_on(command, branch) {
+            Ok(()) => "ok".to_string(),
+            Err(failure) => {
+                failed += 1;
+                format!("FAILED ({})", failure)
+            }
+        };
+        results.push((branch.clone(), result));
+    }
+
+    git(&["checkout", "--quiet", &start_branch])?;
+    report(&results, failed)
+}
+
+/// Run the test command (`stack.test-command`, or the one after `--`) on each
+/// branch of the current stack, like `foreach`, skipping branches whose head
+/// already passed it. `--force` ignores those cached passes.
+pub fn cmd_test(args: &[String]) -> StackResult<()> {
+    let (flags, command) = match args.iter().position(|a| a == "--") {
+        Some(i) => (&args[..i], args[i + 1..].to_vec()),
+        None => (args, test_command().into_iter().collect()),
+    };
+    let keep_going = flags.iter().any(|a| a == "--continue-on-error");
+    let force = flags.iter().any(|a| a == "--force" || a == "-f");
+    if command.is_empty() {
+        return Err(StackError::Usage(
+            "Usage: stack test [--force] [--continue-on-error] [-- <command>] (or set stack.test-command)"
+                .to_string(),
+        ));
+    }
+    let key = command.join(" ");
+
+    ensure_clean_worktree("running tests")?;
+    let start_branch = require_current_branch("test")?;
+    let stack = Stack::load()?;
+    let mut branches = stack.path_to_trunk(&start_branch);
+    branches.extend(stack.descendants(&start_branch));
+
+    let mut results: Vec<(String, String)> = Vec::new();
+    let mut failed = 0;
+    for branch in &branches {
+        let sha = rev_parse(branch)?;
+        if !force && cached_result(&sha, &key) == Some(true) {
+            results.push((branch.clone(), "ok (cached)".to_string()));
+            continue;
+        }
+        if failed > 0 && !keep_going {
+            results.push((branch.clone(), "skipped".to_string()));
+            continue;
+        }
+
+        println!("==> {}", branch);
+        git(&["checkout", "--quiet", branch])?;
+        let outcome = run_on(&command, branch);
+        record_result(&sha, &key, outcome.is_ok())?;
+        let result = match outcome {
+            Ok(()) => "ok".to_string(),
+            Err(failure) => {
+                failed += 1;
+                format!("FAILED ({})", failure)
+            }
+        };
+        results.push((branch.clone(), result));
+    }
+
+    git(&["checkout", "--quiet", &start_branch])?;
+    report(&results, failed)
+}
+
+/// Run `command` with `branch` checked out: one argument through `sh -c`,
+/// several as they are. A failure comes back as what went wrong.
+fn run_on(command: &[String], branch: &str) -> Result<(), String> {
+    let mut cmd = if command.len() == 1 {
+        shell_command(&command[0], "sh")
+    } else {
+        let mut cmd = Command::new(&command[0]);
+        cmd.args(&command[1..]);
+        cmd
+    };
+    match cmd
+        .env("STACK_BRANCH", branch)
+        .env(LOCK_HELD_ENV, "1")
+        .status()
+    {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(match status.code() {
+            Some(code) => format!("exit {}", code),
+            None => "killed".to_string(),
+        }),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// The per-branch summary table, then an error if any branch failed.
+fn report(results: &[(String, String)], failed: usize) -> StackResult<()> {
+    let width = results.iter().map(|(b, _)| b.len()).max().unwrap_or(0);
+    println!();
+    for (branch, result) in results {
+        println!("  {:<width$}  {}", branch, result, width = width);
+    }
+
+    if failed > 0 {
+        return Err(err(&format!("{} branch(es) failed", failed)));
+    }
+    Ok(())
+}
@@ -0,0 +1,780 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::args::{flag_values, positional_args};
+use stack_core::config::{LandStrategy, land_strategy, setting, setting_all, trunk};
+use stack_core::engine::Stack;
+use stack_core::error::{StackError, StackResult, err};
+use stack_core::events::{emit, set_output_format};
+use stack_core::footer::refresh_footers;
+use stack_core::forge::{Forge, authenticated_forge};
+use stack_core::git::{
+    absolute_hooks_path, branch_exists, commit_message, commit_messages, ensure_clean_worktree,
+    get_current_branch, get_remote, git, git_passthrough, git_streamed, is_ancestor, is_fixup,
+    require_current_branch, set_config, stack_dir, unset_config,
+};
+use stack_core::hooks::run_hook;
+use stack_core::land_plan::LandPlan;
+use stack_core::metadata::{delete_meta, get_base, own_commits_base, set_base};
+use stack_core::pr::{PrInfo, invalidate_pr_cache};
+use stack_core::test_results::require_passing_tests;
+use stack_core::ui::{Spinner, confirm, edit_text};
+use stack_core::{info, report};
+
+/// Land a stack bottom-up into trunk: `stack land [<branch>]` lands
+/// everything from trunk up to `<branch>` (default: the current branch), and
+/// `--from X --to Y` also checks that the range starts at `X`. When the
+/// current branch isn't being landed, the merges happen in a temporary
+/// worktree and the checkout is left alone. With `--edit`, squash commit
+/// messages open in the editor before anything lands. With `--wait`, it
+/// waits for every PR's checks to pass first, for up to `--timeout` (`90s`,
+/// `20m`, `1h`; 30 minutes by default).
+///
+/// Landed commits are made by git, so `commit.gpgsign` signs them.
+/// `--signoff` (or `stack.land-signoff = true`) adds a DCO sign-off, and
+/// each `stack.land-trailer` adds a trailer to squash commits; a bare
+/// `Reviewed-by` there names the PR's approvers. `stack.land-message` sets
+/// a template for squash commit messages (see `land_message`).
+///
+/// Branches left stacked on a landed one move onto trunk, PRs included.
+/// Landed branches are deleted along with their stack metadata, locally and
+/// on the remote. `--no-delete` keeps the local branches and metadata, and
+/// `--keep-remote` the remote branches.
+///
+/// The commits landing makes run git's commit hooks (from `core.hooksPath`
+/// too, worktree or not) with the terminal attached; `--no-verify` skips
+/// them along with stack's own hooks and checks.
+///
+/// `--merge-queue` (or `stack.land-merge-queue = true`) lands through the
+/// forge's merge queue instead, one PR at a time in stack order: it queues
+/// the bottom PR, waits up to `--timeout` for it to merge, then rebases the
+/// next branch onto the new trunk, pushes it, retargets its PR, and queues
+/// that one.
+///
+/// A land that stops partway is picked up with `--continue`, which follows
+/// the saved plan rather than the half-landed stack, or dropped with
+/// `--abort`. `--continue --no-verify` gets past a hook that keeps failing.
+///
+/// `--output json` reports each landed branch, push and rebase as a JSON
+/// line on stdout, with the rest of the output on stderr.
+pub fn cmd_land(args: &[String]) -> StackResult<()> {
+    set_output_format(flag_values(args, "--output").pop().as_deref())?;
+    if args.iter().any(|a| a == "--continue") {
+        return continue_land(args);
+    }
+    if args.iter().any(|a| a == "--abort") {
+        return abort_land();
+    }
+    if let Some(plan) = LandPlan::load()? {
+        return Err(err(&format!(
+            "A land of {} is in progress. Run `stack land --continue` to finish it, or `stack land --abort` to drop it.",
+            plan.branches.join(", ")
+        )));
+    }
+
+    let verify = !args.iter().any(|a| a == "--no-verify");
+    let edit = args.iter().any(|a| a == "--edit" || a == "-e");
+    let delete_local = !args.iter().any(|a| a == "--no-delete");
+    let delete_remote = !args.iter().any(|a| a == "--keep-remote");
+    let signoff = args.iter().any(|a| a == "--signoff" || a == "-s")
+        || setting("land-signoff").as_deref() == Some("true");
+    let current = get_current_branch()?;
+    let trunk = trunk();
+    let strategy = land_strategy()?;
+    let wait = args.iter().any(|a| a == "--wait");
+    let merge_queue = args.iter().any(|a| a == "--merge-queue")
+        || setting("land-merge-queue").as_deref() == Some("true");
+    if merge_queue && edit {
+        return Err(StackError::Usage(
+            "--edit doesn't apply to --merge-queue: the queue writes the commits".to_string(),
+        ));
+    }
+    let timeout = match flag_values(args, "--timeout").pop() {
+        Some(value) => parse_timeout(&value)?,
+        None => Duration::from_secs(30 * 60),
+    };
+
+    let from = flag_values(args, "--from").pop();
+    let to = match (
+        positional_args(args, &["--from", "--to", "--timeout", "--output"]).first(),
+        flag_values(args, "--to").pop(),
+    ) {
+        (Some(_), Some(_)) => {
+            return Err(StackError::Usage(
+                "Usage: stack land [<branch> | --from <branch> --to <branch>]".to_string(),
+            ));
+        }
+        (Some(branch), None) => branch.to_string(),
+        (None, Some(branch)) => branch,
+        (None, None) => require_current_branch("land")?,
+    };
+    for branch in from.iter().chain([&to]) {
+        if !branch_exists(branch)? {
+            return Err(err(&format!("Branch '{}' does not exist", branch)));
+        }
+    }
+    if to == trunk {
+        return Err(err("Nothing to land"));
+    }
+
+    // Build the stack from the top back to trunk, in memory against one fetch
+    let tree = Stack::load()?;
+    tree.check_cycles()?;
+    let remote = get_remote(&trunk);
+    let _ = git(&["fetch", &remote, &trunk]);
+    let remote_trunk = format!("{}/{}", remote, trunk);
+    let mut stack = vec![to.clone()];
+    let mut branch = to.as_str();
+
+    while let Some(parent) = tree.parent(branch) {
+        if parent == trunk {
+            break;
+        }
+        // Only add if branch exists AND hasn't been merged into trunk yet
+        if tree.exists(parent) && !is_ancestor(parent, &remote_trunk).unwrap_or(false) {
+            stack.push(parent.to_string());
+        }
+        branch = parent;
+    }
+
+    // Reverse so we merge bottom-up (closest to trunk first)
+    stack.reverse();
+
+    if let Some(from) = &from {
+        match stack.iter().position(|b| b == from) {
+            Some(0) => {}
+            Some(i) => {
+                return Err(err(&format!(
+                    "{} sits on {}, which hasn't landed. Land from {} instead.",
+                    from,
+                    stack[i - 1],
+                    stack[0]
+                )));
+            }
+            None => {
+                return Err(err(&format!("{} is not below {} in the stack", from, to)));
+            }
+        }
+    }
+
+    // Merging needs trunk checked out. That's only worth disturbing the
+    // current checkout for when it is being landed anyway.
+    let in_place = current == trunk || current.is_empty() || stack.contains(&current);
+    if in_place {
+        ensure_clean_worktree("landing")?;
+    }
+
+    if verify {
+        require_passing_tests(&stack, "land")?;
+    }
+    let forge = authenticated_forge()?;
+
+    report!("Will land the following branches into {}:", trunk);
+    for b in &stack {
+        report!("  - {}", b);
+    }
+    // Squashing folds fixups in anyway; other strategies would land them
+    if strategy != LandStrategy::Squash {
+        for b in &stack {
+            if commit_messages(&own_commits_base(b), b)?
+                .iter()
+                .any(|m| is_fixup(m))
+            {
+                eprintln!(
+                    "Warning: {} has fixup commits that would land as they are; run `stack autosquash` first",
+                    b
+                );
+            }
+        }
+    }
+
+    if !confirm("Proceed? [y/N] ")? {
+        report!("Aborted.");
+        return Ok(());
+    }
+
+    if verify {
+        run_hook("pre-land", &stack)?;
+    }
+
+    // Worked out while every branch's parent still exists
+    let mut messages = Vec::new();
+    if strategy == LandStrategy::Squash && !merge_queue {
+        let template = setting("land-message");
+        let prs = match template {
+            Some(_) => forge.review_status(),
+            None => HashMap::new(),
+        };
+        for branch in &stack {
+            let message = with_trailers(
+                &land_message(template.as_deref(), forge.as_ref(), &prs, branch)?,
+                &land_trailers(forge.as_ref(), branch),
+            );
+            messages.push(if edit {
+                let edited = edit_text(&message)?;
+                if edited.is_empty() {
+                    return Err(err("Aborting land: empty commit message"));
+                }
+                edited
+            } else {
+                message
+            });
+        }
+    }
+
+    if wait {
+        wait_for_checks(forge.as_ref(), &stack, timeout)?;
+    }
+    let mut plan = LandPlan {
+        branches: stack.clone(),
+        messages,
+        strategy,
+        in_place,
+        delete_local,
+        delete_remote,
+        signoff,
+        verify,
+        merge_queue: merge_queue.then_some(timeout),
+        children: stack
+            .iter()
+            .map(|b| (b.clone(), tree.children(b).to_vec()))
+            .collect(),
+        merged: Vec::new(),
+        done: Vec::new(),
+    };
+    plan.save()?;
+    run_plan(&mut plan, forge.as_ref())
+}
+
+/// `land --continue`: finish the land that stopped, from the step it
+/// stopped at.
+fn continue_land(args: &[String]) -> StackResult<()> {
+    let Some(mut plan) = LandPlan::load()? else {
+        return Err(err("No land to continue"));
+    };
+    if args.iter().any(|a| a == "--no-verify") {
+        plan.verify = false;
+    }
+    if plan.in_place {
+        ensure_clean_worktree("landing")?;
+    }
+    let forge = authenticated_forge()?;
+    info!("Continuing the land of {}", plan.branches.join(", "));
+    run_plan(&mut plan, forge.as_ref())
+}
+
+/// `land --abort`: forget the land that stopped. What already landed stays
+/// landed.
+fn abort_land() -> StackResult<()> {
+    let Some(plan) = LandPlan::load()? else {
+        return Err(err("No land in progress"));
+    };
+    LandPlan::clear()?;
+    if plan.merged.is_empty() {
+        report!("Dropped the land; nothing had landed.");
+    } else {
+        report!(
+            "Dropped the land. Already merged into {}: {}",
+            trunk(),
+            plan.merged.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Carry out `plan` from wherever it got to, then forget it and tidy up the
+/// stacks left behind.
+fn run_plan(plan: &mut LandPlan, forge: &dyn Forge) -> StackResult<()> {
+    let trunk = trunk();
+    let landed = if plan.in_place {
+        git(&["checkout", &trunk]).and_then(|_| land_branches(plan, forge, &trunk, None))
+    } else {
+        let dir = stack_dir()?.join("land");
+        let dir_arg = dir.to_string_lossy();
+        let _ = git(&["worktree", "remove", "--force", &dir_arg]);
+        git(&["worktree", "add", "--quiet", &dir_arg, &trunk])?;
+        let landed = land_branches(plan, forge, &trunk, Some(&dir));
+        let _ = git(&["worktree", "remove", "--force", &dir_arg]);
+        landed
+    };
+    if let Err(e) = landed {
+        eprintln!(
+            "Landing stopped. Once the problem is fixed, run `stack land --continue` to pick up where it left off."
+        );
+        return Err(e);
+    }
+    LandPlan::clear()?;
+
+    report!("Done! Landed {} branch(es).", plan.branches.len());
+
+    // The stacks left behind lost their bottom PRs
+    let after = Stack::load()?;
+    let mut refreshed: Vec<String> = Vec::new();
+    for branch in &plan.branches {
+        for child in plan.children(branch) {
+            if plan.branches.contains(child) || refreshed.contains(child) {
+                continue;
+            }
+            let chain = after.linear_chain(child);
+            if let Err(e) = refresh_footers(forge, &chain) {
+                eprintln!("Warning: could not update the stack footers: {}", e);
+            }
+            refreshed.extend(chain);
+        }
+    }
+
+    if plan.verify {
+        run_hook("post-land", &plan.branches)?;
+    }
+    Ok(())
+}
+
+/// `--timeout` as a duration: seconds, minutes, or hours with an `s`, `m`,
+/// or `h` suffix, and minutes without one.
+fn parse_timeout(value: &str) -> StackResult<Duration> {
+    let (number, unit) = match value.char_indices().last() {
+        Some((i, 's')) => (&value[..i], 1),
+        Some((i, 'm')) => (&value[..i], 60),
+        Some((i, 'h')) => (&value[..i], 3600),
+        _ => (value, 60),
+    };
+    number
+        .parse::<u64>()
+        .map(|n| Duration::from_secs(n * unit))
+        .map_err(|_| {
+            StackError::Usage(format!(
+                "Invalid --timeout '{}' (expected e.g. 90s, 20m, or 1h)",
+                value
+            ))
+        })
+}
+
+/// `stack.ci-poll-interval`: the seconds between PR status polls (default
+/// 15).
+fn poll_interval() -> Duration {
+    setting("ci-poll-interval")
+        .and_then(|s| s.parse().ok())
+        .map_or(Duration::from_secs(15), Duration::from_secs)
+}
+
+/// Poll until the checks on every branch's PR have passed, failing as soon
+/// as one fails or once `timeout` is up. PRs without checks don't wait.
+fn wait_for_checks(forge: &dyn Forge, branches: &[String], timeout: Duration) -> StackResult<()> {
+    let interval = poll_interval();
+    let start = Instant::now();
+    let mut last_report = String::new();
+
+    loop {
+        invalidate_pr_cache();
+        let prs = forge.review_status();
+        let mut waiting = Vec::new();
+        for branch in branches {
+            let Some(pr) = prs.get(branch) else {
+                continue;
+            };
+            match pr.ci_status() {
+                Some("failing") => {
+                    return Err(err(&format!(
+                        "Checks failed on {} ({}). Not landing.",
+                        branch, pr.url
+                    )));
+                }
+                Some("pending") => waiting.push(format!(
+                    "{} ({} of {} checks done)",
+                    branch,
+                    pr.finished_checks(),
+                    pr.checks.len()
+                )),
+                _ => {}
+            }
+        }
+        if waiting.is_empty() {
+            return Ok(());
+        }
+
+        let report = format!("Waiting for checks: {}", waiting.join(", "));
+        if report != last_report {
+            info!("{}", report);
+            last_report = report;
+        }
+        if start.elapsed() >= timeout {
+            return Err(err(&format!(
+                "Timed out after {}s waiting for checks: {}",
+                timeout.as_secs(),
+                waiting.join(", ")
+            )));
+        }
+        let _spinner = Spinner::start("Waiting for CI");
+        thread::sleep(interval.min(timeout.saturating_sub(start.elapsed())));
+    }
+}
+
+/// Land `branch` through the merge queue. Once the branch below it has
+/// merged (`restack`), it is rebased onto the new trunk, pushed, and its PR
+/// pointed at trunk; then the PR is queued and watched until it merges.
+/// A PR that already merged is left alone, for `--continue`.
+fn land_through_queue(
+    plan: &LandPlan,
+    forge: &dyn Forge,
+    branch: &str,
+    restack: bool,
+    trunk: &str,
+    at: &dyn Fn(&[&str]) -> StackResult<String>,
+) -> StackResult<()> {
+    invalidate_pr_cache();
+    if forge
+        .review_status()
+        .get(branch)
+        .is_some_and(|pr| pr.state == "MERGED")
+    {
+        return Ok(());
+    }
+
+    if restack {
+        let upstream = get_base(branch)
+            .filter(|b| is_ancestor(b, branch).unwrap_or(false))
+            .ok_or_else(|| {
+                err(&format!(
+                    "The base commit of {} is unknown, so it can't be moved onto {}. Run `stack restack`, then `stack land --continue`.",
+                    branch, trunk
+                ))
+            })?;
+        info!("   -> Rebase {} onto {}", branch, trunk);
+        if at(&["rebase", "--onto", trunk, &upstream, branch]).is_err() {
+            let _ = at(&["rebase", "--abort"]);
+            return Err(err(&format!(
+                "{} doesn't rebase cleanly onto {}. Run `stack restack`, resolve the conflicts, then `stack land --continue`.",
+                branch, trunk
+            )));
+        }
+        at(&["checkout", "--quiet", trunk])?;
+        set_base(branch, trunk)?;
+        set_config(&format!("branch.{}.stack-parent", branch), trunk)?;
+        emit("rebased", json!({ "branch": branch, "onto": trunk }));
+        let remote = get_remote(branch);
+        git_streamed(&["push", "--force-with-lease", &remote, branch])?;
+        emit("pushed", json!({ "branch": branch, "remote": remote }));
+        forge.set_pr_base(branch, trunk)?;
+    }
+
+    info!("Adding the PR for {} to the merge queue...", branch);
+    forge.enqueue(branch, plan.strategy)?;
+    wait_for_merge(forge, branch, plan.merge_queue.unwrap_or_default())
+}
+
+/// Poll until `branch`'s PR merges, failing if it is closed, its checks
+/// fail (which drops it from the queue), or `timeout` is up.
+fn wait_for_merge(forge: &dyn Forge, branch: &str, timeout: Duration) -> StackResult<()> {
+    let interval = poll_interval();
+    let start = Instant::now();
+    let mut last_report = String::new();
+
+    loop {
+        invalidate_pr_cache();
+        let prs = forge.review_status();
+        let Some(pr) = prs.get(branch) else {
+            return Err(err(&format!("No PR found for {}", branch)));
+        };
+        match pr.state.as_str() {
+            "MERGED" => return Ok(()),
+            "CLOSED" => {
+                return Err(err(&format!(
+                    "The PR for {} was closed without merging ({})",
+                    branch, pr.url
+                )));
+            }
+            _ => {}
+        }
+        if pr.ci_status() == Some("failing") {
+            return Err(err(&format!(
+                "Checks failed on {} in the merge queue ({}). Once they pass, run `stack land --continue`.",
+                branch, pr.url
+            )));
+        }
+
+        let report = match pr.checks.len() {
+            0 => format!("Waiting for {} to merge", branch),
+            n => format!(
+                "Waiting for {} to merge ({} of {} checks done)",
+                branch,
+                pr.finished_checks(),
+                n
+            ),
+        };
+        if report != last_report {
+            info!("{}", report);
+            last_report = report;
+        }
+        if start.elapsed() >= timeout {
+            return Err(err(&format!(
+                "Timed out after {}s waiting for {} to merge. Run `stack land --continue` to keep waiting.",
+                timeout.as_secs(),
+                branch
+            )));
+        }
+        let _spinner = Spinner::start("Waiting for the merge queue");
+        thread::sleep(interval.min(timeout.saturating_sub(start.elapsed())));
+    }
+}
+
+/// The `stack.land-trailer` trailers for `branch`'s squash commit. Entries
+/// with a value (`Change-Type: feature`) are used as they are; a bare key
+/// (`Reviewed-by`) becomes one trailer per approver of the branch's PR.
+fn land_trailers(forge: &dyn Forge, branch: &str) -> Vec<String> {
+    let mut approvers = None;
+    let mut trailers = Vec::new();
+    for entry in setting_all("land-trailer") {
+        let entry = entry.trim();
+        if entry.contains(':') {
+            trailers.push(entry.to_string());
+            continue;
+        }
+        let approvers = approvers.get_or_insert_with(|| {
+            forge
+                .pr_reviewers(branch)
+                .iter()
+                .filter_map(|r| r.strip_suffix(" (approved)").map(str::to_string))
+                .collect::<Vec<_>>()
+        });
+        for approver in approvers.iter() {
+            trailers.push(format!("{}: {}", entry, approver));
+        }
+    }
+    trailers
+}
+
+/// `message` with `trailers` added to its trailer block (started if it has
+/// none), skipping any it already has.
+fn with_trailers(message: &str, trailers: &[String]) -> String {
+    let message = message.trim_end();
+    let existing: Vec<&str> = message.lines().collect();
+    let new: Vec<&String> = trailers
+        .iter()
+        .filter(|t| !existing.contains(&t.as_str()))
+        .collect();
+    if new.is_empty() {
+        return message.to_string();
+    }
+
+    let is_trailer = |line: &str| {
+        line.split_once(": ").is_some_and(|(key, _)| {
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+    };
+    let last_paragraph = message.rsplit("\n\n").next().unwrap_or_default();
+    let has_block = message.contains("\n\n") && last_paragraph.lines().all(is_trailer);
+
+    let mut out = message.to_string();
+    out.push_str(if has_block { "\n" } else { "\n\n" });
+    out.push_str(
+        &new.iter()
+            .map(|t| t.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+    );
+    out
+}
+
+/// The squash commit message for `branch`, built like GitHub's: a single
+/// commit keeps its message, several get the first subject as a title and
+/// every message as a bullet below it.
+fn squash_message(branch: &str) -> StackResult<String> {
+    let messages = commit_messages(&own_commits_base(branch), branch)?;
+    match messages.as_slice() {
+        [] => commit_message(branch),
+        [only] => Ok(only.trim().to_string()),
+        all => {
+            let title = all[0].lines().next().unwrap_or_default();
+            let bullets: Vec<String> = all.iter().map(|m| format!("* {}", m.trim())).collect();
+            Ok(format!("{}\n\n{}", title, bullets.join("\n\n")))
+        }
+    }
+}
+
+/// The squash commit message for `branch`: `squash_message`, or the
+/// `stack.land-message` template filled in, such as `{pr_title}
+/// (#{pr_number})\n\n{body}` for what GitHub's squash button writes.
+/// `{pr_title}`, `{pr_number}`, `{pr_url}` and `{body}` (the description)
+/// come from the PR, `{branch}`, `{subject}` (the first commit's) and
+/// `{commits}` (`squash_message`) from the branch. A branch with no PR for
+/// the template to use gets `squash_message`.
+fn land_message(
+    template: Option<&str>,
+    forge: &dyn Forge,
+    prs: &HashMap<String, PrInfo>,
+    branch: &str,
+) -> StackResult<String> {
+    let commits = squash_message(branch)?;
+    let Some(template) = template else {
+        return Ok(commits);
+    };
+    let mut message = template.replace("{branch}", branch);
+    if ["{pr_title}", "{pr_number}", "{pr_url}", "{body}"]
+        .iter()
+        .any(|p| message.contains(p))
+    {
+        let Some(pr) = prs.get(branch) else {
+            eprintln!(
+                "Warning: {} has no PR for stack.land-message; landing it with its commit messages",
+                branch
+            );
+            return Ok(commits);
+        };
+        let (title, body) = forge.pr_description(branch)?;
+        message = message
+            .replace("{pr_title}", title.trim())
+            .replace("{pr_number}", &pr.number.to_string())
+            .replace("{pr_url}", &pr.url)
+            .replace("{body}", body.trim());
+    }
+    let message = message
+        .replace("{subject}", commits.lines().next().unwrap_or_default())
+        .replace("{commits}", &commits);
+
+    // An empty placeholder shouldn't leave a gap of blank lines
+    let mut out: Vec<&str> = Vec::new();
+    for line in message.trim().lines() {
+        if !(line.trim().is_empty() && out.last().is_some_and(|l| l.trim().is_empty())) {
+            out.push(line);
+        }
+    }
+    Ok(out.join("\n"))
+}
+
+/// Merge each branch of `plan` not yet landed into trunk, which is checked
+/// out in `dir` (the current worktree if `None`), then push trunk and clean
+/// up the branches. Progress is saved after every step, so a failed step is
+/// the first one `--continue` retries.
+fn land_branches(
+    plan: &mut LandPlan,
+    forge: &dyn Forge,
+    trunk: &str,
+    dir: Option<&Path>,
+) -> StackResult<()> {
+    let dir_arg = dir.map(|d| d.to_string_lossy().into_owned());
+    let at = |args: &[&str]| -> StackResult<String> {
+        let mut full: Vec<&str> = match &dir_arg {
+            Some(dir) => vec!["-C", dir],
+            None => Vec::new(),
+        };
+        full.extend_from_slice(args);
+        git_streamed(&full)
+    };
+    // Commits run hooks, which may want the terminal, so git gets ours
+    let hooks_path = match dir {
+        Some(_) => absolute_hooks_path()?.map(|p| format!("core.hooksPath={}", p)),
+        None => None,
+    };
+    let commit = |branch: &str, args: &[&str], merging: Option<&str>| -> StackResult<String> {
+        let mut full: Vec<&str> = match &dir_arg {
+            Some(dir) => vec!["-C", dir],
+            None => Vec::new(),
+        };
+        if let Some(hooks_path) = &hooks_path {
+            full.extend_from_slice(&["-c", hooks_path]);
+        }
+        full.extend_from_slice(args);
+        if !plan.verify {
+            full.push("--no-verify");
+        }
+        if plan.signoff {
+            full.push("--signoff");
+        }
+        full.extend(merging);
+        git_passthrough(&full).map(|()| String::new()).map_err(|_| {
+            err(&format!(
+                "Committing {} into {} failed. If a hook rejected it, fix that and run `stack land --continue` (`--no-verify` skips hooks).",
+                branch, trunk
+            ))
+        })
+    };
+
+    // Pull latest trunk first
+    let remote = get_remote(trunk);
+    at(&["pull", &remote, trunk])?;
+
+    for (i, branch) in plan.branches.clone().iter().enumerate() {
+        if plan.done.contains(branch) {
+            continue;
+        }
+        // Resolve before deleting the branch, which drops its config section
+        let branch_remote = get_remote(branch);
+
+        if !plan.merged.contains(branch) {
+            info!("Merging {}...", branch);
+            if plan.merge_queue.is_some() {
+                land_through_queue(plan, forge, branch, i > 0, trunk, &at)?;
+                at(&["pull", &remote, trunk])?;
+            } else if forge.merge(branch, plan.strategy)? {
+                // Merged on the server; bring local trunk up to date
+                at(&["pull", &remote, trunk])?;
+            } else {
+                let merged = match plan.strategy {
+                    LandStrategy::Squash => at(&["merge", "--squash", branch]).and_then(|_| {
+                        commit(
+                            branch,
+                            &["commit", "--quiet", "-m", &plan.messages[i]],
+                            None,
+                        )
+                    }),
+                    LandStrategy::Merge => {
+                        commit(branch, &["merge", "--no-ff", "--no-edit"], Some(branch))
+                    }
+                    LandStrategy::Rebase => at(&["merge", "--ff-only", branch]).map_err(|_| {
+                        err(&format!(
+                            "{} is not on top of {}. Run `stack restack` and try again.",
+                            branch, trunk
+                        ))
+                    }),
+                };
+                // Leave trunk as it was, for the retry
+                if let Err(e) = merged {
+                    let _ = at(&["reset", "--quiet", "--merge"]);
+                    return Err(e);
+                }
+            }
+            plan.merged.push(branch.clone());
+            plan.save()?;
+            emit("landed", json!({ "branch": branch, "into": trunk }));
+        }
+
+        // Before the branch goes: deleting a PR's base closes the PR
+        let mut retargeted = true;
+        for child in plan.children(branch) {
+            if plan.branches.contains(child) {
+                continue;
+            }
+            info!("Moving {} onto {}", child, trunk);
+            set_config(&format!("branch.{}.stack-parent", child), trunk)?;
+            if let Err(e) = forge.set_pr_base(child, trunk) {
+                eprintln!("Warning: could not retarget the PR for {}: {}", child, e);
+                retargeted = false;
+            }
+        }
+
+        if plan.delete_remote && retargeted {
+            let _ = git(&["push", &branch_remote, "--delete", branch]); // Ignore if remote doesn't exist
+        }
+        if plan.delete_local && branch_exists(branch)? {
+            git(&["branch", "-D", branch])?;
+
+            // Clean up the stack-parent config
+            let _ = unset_config(&format!("branch.{}.stack-parent", branch));
+            delete_meta(branch);
+        }
+        plan.done.push(branch.clone());
+        plan.save()?;
+    }
+
+    // The queue merged everything on the server already
+    if plan.merge_queue.is_none() {
+        info!("Pushing {}...", trunk);
+        git_streamed(&["push", &remote, trunk])?;
+        emit("pushed", json!({ "branch": trunk, "remote": remote }));
+    }
+    Ok(())
+}
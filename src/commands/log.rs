@@ -0,0 +1,513 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::args::flag_values;
+use stack_core::engine::Stack;
+use stack_core::error::{StackError, StackResult};
+use stack_core::forge::{get_forge, submit_target};
+use stack_core::git::{
+    DiffStat, ahead_behind, branch_exists, commit_summary, diff_stats, first_commit_times,
+    get_current_branch, git_passthrough, require_current_branch, tracking_branch, worktree_changes,
+};
+use stack_core::info;
+use stack_core::lock::Lock;
+use stack_core::metadata::{adopt, get_note, get_parent, own_commits_base, require_parent};
+use stack_core::pr::{PrInfo, unix_now, use_cached_prs};
+use stack_core::ui::{Paint, interactive, paint, prompt};
+
+/// PR state for `log`, `status` and `stats` comes from the cache left by
+/// the last command that fetched it, however old, so they never ask the
+/// forge; `--refresh` fetches it anew.
+fn read_cached_prs(args: &[String]) {
+    if !args.iter().any(|a| a == "--refresh") {
+        use_cached_prs();
+    }
+}
+
+pub fn cmd_status(args: &[String]) -> StackResult<()> {
+    read_cached_prs(args);
+    let branch = get_current_branch()?;
+    if branch.is_empty() {
+        println!("Branch:   (detached HEAD)");
+        return Ok(());
+    }
+    println!("Branch:   {}", branch);
+    if let Some(note) = get_note(&branch) {
+        println!("Note:     {}", note);
+    }
+
+    match get_parent(&branch) {
+        Some(parent) => {
+            let state = match ahead_behind(&branch, &parent) {
+                Ok((_, 0)) => "up to date".to_string(),
+                Ok((_, behind)) => paint(
+                    Paint::Restack,
+                    &format!("needs restack, {} commit(s) behind", behind),
+                ),
+                Err(_) => "missing".to_string(),
+            };
+            println!("Parent:   {} ({})", parent, state);
+        }
+        None => println!("Parent:   none (not tracked by stack)"),
+    }
+
+    let (changed, untracked) = worktree_changes()?;
+    let worktree = match (changed, untracked) {
+        (0, 0) => "clean".to_string(),
+        (0, u) => format!("clean, {} untracked", u),
+        (c, 0) => format!("dirty, {} changed", c),
+        (c, u) => format!("dirty, {} changed, {} untracked", c, u),
+    };
+    println!("Worktree: {}", worktree);
+
+    let remote_ref = tracking_branch(&branch, &submit_target(&branch)?.push_remote);
+    let remote = match ahead_behind(&branch, &remote_ref) {
+        Ok((0, 0)) => "in sync".to_string(),
+        Ok((ahead, 0)) => format!("{} commit(s) not pushed", ahead),
+        Ok((0, behind)) => format!("{} commit(s) behind", behind),
+        Ok((ahead, behind)) => format!("diverged, +{}/-{}", ahead, behind),
+        Err(_) => "not pushed".to_string(),
+    };
+    println!("Remote:   {} ({})", remote_ref, remote);
+
+    match get_forge()?.review_status().get(&branch) {
+        Some(pr) => println!("PR:       {}  {}", pr.annotation(), pr.url),
+        None => println!("PR:       none"),
+    }
+    Ok(())
+}
+
+/// A health summary of every stack in the repo: how many there are and how
+/// deep, which branches wait on review or a restack, and the oldest branch
+/// still to land, aged by its first commit.
+pub fn cmd_stats(args: &[String]) -> StackResult<()> {
+    read_cached_prs(args);
+    let stack = Stack::load()?;
+    stack.check_cycles()?;
+    let prs = get_forge()?.review_status();
+    let landed = |b: &str| prs.get(b).is_some_and(|pr| pr.state == "MERGED");
+
+    fn depth(stack: &Stack, branch: &str) -> usize {
+        1 + stack
+            .children(branch)
+            .iter()
+            .map(|c| depth(stack, c))
+            .max()
+            .unwrap_or(0)
+    }
+    let depths: Vec<usize> = stack
+        .roots()
+        .iter()
+        .flat_map(|root| stack.children(root))
+        .map(|b| depth(&stack, b))
+        .collect();
+
+    let mut branches = 0;
+    let mut awaiting_review = Vec::new();
+    let mut needs_restack = Vec::new();
+    let mut unlanded: Vec<&str> = stack
+        .branches()
+        .map(|b| b.name.as_str())
+        .filter(|b| stack.exists(b) && !landed(b))
+        .collect();
+    unlanded.sort();
+    let ranges: Vec<(String, String)> = unlanded
+        .iter()
+        .map(|b| (own_commits_base(b), b.to_string()))
+        .collect();
+    let mut oldest: Option<(String, u64)> = None;
+    for (branch, started) in unlanded.iter().copied().zip(first_commit_times(&ranges)?) {
+        branches += 1;
+        if let Some(pr) = prs.get(branch)
+            && pr.state == "OPEN"
+            && !pr.draft
+            && !matches!(pr.review.as_str(), "APPROVED" | "CHANGES_REQUESTED")
+        {
+            awaiting_review.push(branch.to_string());
+        }
+        if let Some(parent) = stack.parent(branch)
+            && matches!(ahead_behind(branch, parent), Ok((_, behind)) if behind > 0)
+        {
+            needs_restack.push(branch.to_string());
+        }
+        if let Some(started) = started
+            && oldest
+                .as_ref()
+                .is_none_or(|(b, t)| (started, branch) < (*t, b.as_str()))
+        {
+            oldest = Some((branch.to_string(), started));
+        }
+    }
+    awaiting_review.sort();
+    needs_restack.sort();
+
+    let listed = |names: &[String]| match names.len() {
+        0 => "0".to_string(),
+        n => format!("{} ({})", n, names.join(", ")),
+    };
+    let stacks = match depths.iter().max() {
+        Some(deepest) => format!(
+            "{} (average depth {:.1}, deepest {})",
+            depths.len(),
+            depths.iter().sum::<usize>() as f64 / depths.len() as f64,
+            deepest
+        ),
+        None => "0".to_string(),
+    };
+    println!("Stacks:          {}", stacks);
+    println!("Branches:        {} unlanded", branches);
+    println!("Awaiting review: {}", listed(&awaiting_review));
+    println!("Needs restack:   {}", listed(&needs_restack));
+    match oldest {
+        Some((branch, started)) => {
+            let days = unix_now().saturating_sub(started) / 86_400;
+            let pr = match prs.get(&branch) {
+                Some(pr) => format!(", {}", pr.annotation()),
+                None => String::new(),
+            };
+            println!("Oldest unlanded: {} ({} days{})", branch, days, pr);
+        }
+        None => println!("Oldest unlanded: none"),
+    }
+    Ok(())
+}
+
+/// `git diff` of a branch (default: current) against its stack parent.
+/// Other arguments, such as `--stat` or paths, go to `git diff`.
+pub fn cmd_diff(args: &[String]) -> StackResult<()> {
+    let (branch, rest) = match args.first() {
+        Some(arg) if !arg.starts_with('-') && branch_exists(arg)? => (arg.clone(), &args[1..]),
+        _ => (require_current_branch("diff")?, args),
+    };
+    require_parent(&branch)?;
+
+    let base = own_commits_base(&branch);
+    let mut diff_args = vec!["diff", base.as_str(), branch.as_str()];
+    diff_args.extend(rest.iter().map(String::as_str));
+    git_passthrough(&diff_args)
+}
+
+/// Print the stack as a tree, or with `--format mermaid` / `--format dot` as
+/// a graph to paste into documents, with PR links where there are PRs. On
+/// detached HEAD or a branch outside any stack, every stack is shown.
+/// `--stat` adds each branch's diff statistics against its parent. Like
+/// every read-only command it never fetches, `auto-fetch-meta` or not, and
+/// shows PR state as last fetched unless `--refresh`.
+///
+/// A branch that has stacked children but no parent of its own, such as
+/// one created with plain git, is shown on the parent its merge bases
+/// suggest, marked `(inferred)`. The tree view asks whether to record it
+/// when there is someone to answer; `--adopt-inferred` records it outright.
+pub fn cmd_log(args: &[String]) -> StackResult<()> {
+    read_cached_prs(args);
+    let show_all = args.iter().any(|a| a == "--all");
+    let show_stat = args.iter().any(|a| a == "--stat");
+    let format = flag_values(args, "--format").pop();
+    if let Some(f) = format.as_deref()
+        && !matches!(f, "tree" | "mermaid" | "dot")
+    {
+        return Err(StackError::Usage(format!(
+            "Unknown log format '{}'; use tree, mermaid or dot",
+            f
+        )));
+    }
+    let current = get_current_branch()?;
+    let mut stack = Stack::load()?;
+    stack.check_cycles()?;
+    // Parents created outside stack would otherwise cut the tree short
+    let inferred: Vec<(String, String)> = stack
+        .roots()
+        .into_iter()
+        .filter(|root| root != stack.trunk() && stack.exists(root))
+        .filter_map(|root| stack.infer_parent(&root).map(|parent| (root, parent)))
+        .collect();
+    for (branch, parent) in &inferred {
+        stack.assume_parent(branch, parent);
+    }
+    let in_stack = current == stack.trunk()
+        || stack.parent(&current).is_some()
+        || !stack.children(&current).is_empty();
+
+    let roots = if show_all || !in_stack {
+        stack.roots()
+    } else {
+        // Find the root of the stack (walk up parents)
+        let mut root = current.as_str();
+        while let Some(parent) = stack.parent(root) {
+            root = parent;
+        }
+        vec![root.to_string()]
+    };
+
+    let prs = get_forge()?.review_status();
+    let stats = if show_stat && !matches!(format.as_deref(), Some("mermaid" | "dot")) {
+        branch_stats(&roots, &stack)?
+    } else {
+        HashMap::new()
+    };
+    let ctx = TreeContext {
+        current: &current,
+        stack: &stack,
+        prs: &prs,
+        stats: &stats,
+        inferred: inferred.iter().map(|(branch, _)| branch.as_str()).collect(),
+    };
+
+    match format.as_deref() {
+        Some("mermaid") => {
+            print!("{}", mermaid_graph(&roots, &ctx));
+            return Ok(());
+        }
+        Some("dot") => {
+            print!("{}", dot_graph(&roots, &ctx));
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    // Print each tree starting from its root
+    println!();
+    for root in &roots {
+        print_tree(root, None, &ctx, "", true)?;
+        println!();
+    }
+
+    // A guess is only recorded when someone says so: `--yes` doesn't
+    let adopt_inferred = args.iter().any(|a| a == "--adopt-inferred");
+    if inferred.is_empty() || !(adopt_inferred || interactive()) {
+        return Ok(());
+    }
+    // `log` doesn't otherwise take the lock; busy, it can ask next time
+    let Ok(_lock) = Lock::acquire("log") else {
+        return Ok(());
+    };
+    for (branch, parent) in &inferred {
+        let record = adopt_inferred
+            || prompt(&format!(
+                "{} has no recorded parent; stack it on {}? [y/N] ",
+                branch, parent
+            ))
+            .is_ok_and(|answer| answer.eq_ignore_ascii_case("y"));
+        if record {
+            adopt(branch, parent, None)?;
+            info!("Stacked {} on {}", branch, parent);
+        }
+    }
+    Ok(())
+}
+
+/// Everything `print_tree` needs besides the branch being printed.
+struct TreeContext<'a> {
+    current: &'a str,
+    stack: &'a Stack,
+    prs: &'a HashMap<String, PrInfo>,
+    /// Diff statistics for `--stat`, by branch.
+    stats: &'a HashMap<String, DiffStat>,
+    /// Branches shown on a parent `infer_parent` guessed.
+    inferred: HashSet<&'a str>,
+}
+
+/// What each non-root branch under `roots` changes relative to its parent.
+fn branch_stats(roots: &[String], stack: &Stack) -> StackResult<HashMap<String, DiffStat>> {
+    let branches: Vec<&str> = graph_nodes(roots, stack)
+        .into_iter()
+        .filter(|(_, parent)| parent.is_some())
+        .map(|(branch, _)| branch)
+        .collect();
+    let pairs: Vec<(String, String)> = branches
+        .iter()
+        .map(|b| (own_commits_base(b), b.to_string()))
+        .collect();
+    let stats = diff_stats(&pairs)?;
+    Ok(branches
+        .into_iter()
+        .map(str::to_string)
+        .zip(stats)
+        .collect())
+}
+
+fn print_tree(
+    branch: &str,
+    parent: Option<&str>,
+    ctx: &TreeContext,
+    prefix: &str,
+    is_last: bool,
+) -> StackResult<()> {
+    let connector = if parent.is_none() {
+        ""
+    } else if is_last {
+        "└── "
+    } else {
+        "├── "
+    };
+    let name = if branch == ctx.current {
+        paint(Paint::Current, &format!("{} ◀", branch))
+    } else if branch == ctx.stack.trunk() {
+        paint(Paint::Trunk, branch)
+    } else {
+        branch.to_string()
+    };
+    let frozen = match ctx.stack.branch(branch) {
+        Some(b) if b.frozen => " (frozen)",
+        _ => "",
+    };
+    // A branch needs restacking once its parent has commits it lacks
+    let drift = match parent.map(|p| (p, ahead_behind(branch, p))) {
+        Some((p, Ok((ahead, behind)))) => {
+            let restack = if behind > 0 {
+                format!(" {}", paint(Paint::Restack, "(needs restack)"))
+            } else {
+                String::new()
+            };
+            let inferred = if ctx.inferred.contains(branch) {
+                " (inferred)"
+            } else {
+                ""
+            };
+            format!("  +{}/-{} vs {}{}{}", ahead, behind, p, inferred, restack)
+        }
+        _ => String::new(),
+    };
+    let pr = match ctx.prs.get(branch) {
+        Some(pr) if pr.state == "MERGED" => {
+            format!(
+                "  {}",
+                paint(Paint::Merged, &format!("({})", pr.annotation()))
+            )
+        }
+        Some(pr) => format!("  ({})", pr.annotation()),
+        None => String::new(),
+    };
+
+    // Get short commit info
+    let commit_info = match ctx.stack.head(branch) {
+        Some(head) => head.summary(),
+        None => commit_summary(branch).unwrap_or_default(),
+    };
+
+    // Children hang off the root's column; deeper levels keep drawing the
+    // parent's vertical line while it still has siblings below
+    let new_prefix = if parent.is_none() {
+        "".to_string()
+    } else if is_last {
+        format!("{}    ", prefix)
+    } else {
+        format!("{}│   ", prefix)
+    };
+    let info_prefix = if parent.is_none() {
+        "    "
+    } else {
+        &new_prefix
+    };
+
+    println!("{}{}{}{}{}{}", prefix, connector, name, frozen, drift, pr);
+    println!("{}{}", info_prefix, commit_info);
+    if let Some(stat) = ctx.stats.get(branch) {
+        println!("{}{}", info_prefix, stat);
+    }
+    if let Some(note) = ctx.stack.branch(branch).and_then(|b| b.note.as_deref()) {
+        println!("{}Note: {}", info_prefix, note);
+    }
+
+    let children = ctx.stack.children(branch);
+    for (i, child) in children.iter().enumerate() {
+        let child_is_last = i == children.len() - 1;
+        print_tree(child, Some(branch), ctx, &new_prefix, child_is_last)?;
+    }
+
+    Ok(())
+}
+
+/// Every branch under `roots` in tree order, with its parent.
+fn graph_nodes<'a>(roots: &'a [String], stack: &'a Stack) -> Vec<(&'a str, Option<&'a str>)> {
+    fn walk<'a>(
+        branch: &'a str,
+        parent: Option<&'a str>,
+        stack: &'a Stack,
+        out: &mut Vec<(&'a str, Option<&'a str>)>,
+    ) {
+        out.push((branch, parent));
+        for child in stack.children(branch) {
+            walk(child, Some(branch), stack, out);
+        }
+    }
+
+    let mut nodes = Vec::new();
+    for root in roots {
+        walk(root, None, stack, &mut nodes);
+    }
+    nodes
+}
+
+/// A Mermaid flowchart, trunk at the top. Branch names aren't valid node
+/// ids, so nodes are numbered and labelled, with `#` and `"` as entities.
+fn mermaid_graph(roots: &[String], ctx: &TreeContext) -> String {
+    let nodes = graph_nodes(roots, ctx.stack);
+    let id = |branch: &str| {
+        let i = nodes.iter().position(|(b, _)| *b == branch).unwrap_or(0);
+        format!("n{}", i)
+    };
+
+    let mut out = String::from("graph TD\n");
+    for (branch, _) in &nodes {
+        let mut label = branch.to_string();
+        if let Some(pr) = ctx.prs.get(*branch) {
+            label.push_str(&format!("<br/>{}", pr.annotation()));
+        }
+        out.push_str(&format!(
+            "    {}[\"{}\"]\n",
+            id(branch),
+            label.replace('#', "#35;").replace('"', "#quot;")
+        ));
+    }
+    for (branch, parent) in &nodes {
+        if let Some(parent) = parent {
+            out.push_str(&format!("    {} --> {}\n", id(parent), id(branch)));
+        }
+    }
+    for (branch, _) in &nodes {
+        if let Some(pr) = ctx.prs.get(*branch).filter(|pr| !pr.url.is_empty()) {
+            out.push_str(&format!("    click {} \"{}\" _blank\n", id(branch), pr.url));
+        }
+    }
+    if nodes.iter().any(|(b, _)| *b == ctx.current) {
+        out.push_str(&format!("    style {} stroke-width:3px\n", id(ctx.current)));
+    }
+    out
+}
+
+/// A Graphviz digraph, trunk at the top.
+fn dot_graph(roots: &[String], ctx: &TreeContext) -> String {
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    let quote = |s: &str| format!("\"{}\"", escape(s));
+    let nodes = graph_nodes(roots, ctx.stack);
+
+    let mut out = String::from("digraph stack {\n    node [shape=box];\n");
+    for (branch, _) in &nodes {
+        let mut attrs = Vec::new();
+        if let Some(pr) = ctx.prs.get(*branch) {
+            attrs.push(format!(
+                "label=\"{}\\n{}\"",
+                escape(branch),
+                escape(&pr.annotation())
+            ));
+            if !pr.url.is_empty() {
+                attrs.push(format!("URL={}", quote(&pr.url)));
+            }
+        }
+        if *branch == ctx.current {
+            attrs.push("penwidth=3".to_string());
+        }
+        if attrs.is_empty() {
+            out.push_str(&format!("    {};\n", quote(branch)));
+        } else {
+            out.push_str(&format!("    {} [{}];\n", quote(branch), attrs.join(", ")));
+        }
+    }
+    for (branch, parent) in &nodes {
+        if let Some(parent) = parent {
+            out.push_str(&format!("    {} -> {};\n", quote(parent), quote(branch)));
+        }
+    }
+    out.push_str("}\n");
+    out
+}
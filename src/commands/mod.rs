@@ -0,0 +1,16 @@
+//! One module per group of subcommands.
+
+pub mod config;
+pub mod create;
+pub mod edit;
+pub mod foreach;
+pub mod land;
+pub mod log;
+pub mod note;
+pub mod prune;
+pub mod rename;
+pub mod restack;
+pub mod serve;
+pub mod snapshot;
+pub mod submit;
+pub mod switch;
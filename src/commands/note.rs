@@ -0,0 +1,43 @@
+use crate::args::{flag_values, positional_args};
+use stack_core::error::{StackError, StackResult};
+use stack_core::git::require_current_branch;
+use stack_core::info;
+use stack_core::metadata::{get_note, require_parent, set_note};
+
+/// Attach a short note to a branch (`--branch`, default: the current one),
+/// shown beside it in `log` and `status`: what it is waiting on, what is
+/// left to do. With no text, print the note; `--clear` removes it.
+pub fn cmd_note(args: &[String]) -> StackResult<()> {
+    let branch = match flag_values(args, "--branch").pop() {
+        Some(branch) => branch,
+        None => require_current_branch("note")?,
+    };
+    require_parent(&branch)?;
+    // One line, so it fits in the tree
+    let note = positional_args(args, &["--branch"])
+        .iter()
+        .flat_map(|s| s.split_whitespace())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if args.iter().any(|a| a == "--clear") {
+        if !note.is_empty() {
+            return Err(StackError::Usage(
+                "Usage: stack note [--branch <branch>] [<text> | --clear]".to_string(),
+            ));
+        }
+        set_note(&branch, None)?;
+        info!("Cleared the note on {}", branch);
+        return Ok(());
+    }
+    if note.is_empty() {
+        match get_note(&branch) {
+            Some(note) => println!("{}", note),
+            None => println!("No note on {}.", branch),
+        }
+        return Ok(());
+    }
+    set_note(&branch, Some(&note))?;
+    info!("Noted on {}: {}", branch, note);
+    Ok(())
+}
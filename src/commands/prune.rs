@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+
+use crate::args::flag_values;
+use stack_core::config::setting;
+use stack_core::engine::{Stack, merged_branches};
+use stack_core::error::{StackError, StackResult};
+use stack_core::forge::get_forge;
+use stack_core::git::{
+    branch_exists, commit_ids, get_current_branch, get_remote, git, is_ancestor, set_config,
+    try_command, unset_config,
+};
+use stack_core::metadata::{delete_local_meta, delete_meta, meta_branches, own_commits_base};
+use stack_core::pr::unix_now;
+use stack_core::ui::confirm;
+
+/// Remove stack metadata that no longer describes a stack: entries for
+/// branches deleted behind stack's back (`git branch -D`) and for branches
+/// already merged into trunk. Their children move down onto the nearest
+/// parent that stays, ready for `stack restack`. `--dry-run` only lists what
+/// would change.
+pub fn cmd_prune(args: &[String]) -> StackResult<()> {
+    let dry_run = args.iter().any(|a| a == "--dry-run" || a == "-n");
+    let stack = Stack::load()?;
+    let trunk = stack.trunk().to_string();
+
+    // Best effort: without the remote, merged means merged into local trunk
+    let remote = get_remote(&trunk);
+    let _ = try_command("git", &["fetch", "--quiet", &remote, &trunk]);
+    let remote_trunk = format!("{}/{}", remote, trunk);
+    let merged_prs = merged_branches().unwrap_or_default();
+
+    // Deleting a branch drops its config section, so a deleted parent may
+    // only show up in its children's entries
+    let mut names: Vec<String> = stack.branches().map(|b| b.name.clone()).collect();
+    let mut missing = Vec::new();
+    for parent in stack.branches().filter_map(|b| b.parent.as_deref()) {
+        if parent != trunk && !branch_exists(parent)? {
+            missing.push(parent.to_string());
+        }
+    }
+    for branch in missing.into_iter().chain(meta_branches()?) {
+        if !names.contains(&branch) {
+            names.push(branch);
+        }
+    }
+    names.sort();
+
+    // Branch -> whether it still exists
+    let mut stale: HashMap<String, bool> = HashMap::new();
+    let mut report = Vec::new();
+    for name in names {
+        if !branch_exists(&name)? {
+            report.push(format!("{} (branch no longer exists)", name));
+            stale.insert(name, false);
+        } else if merged_prs.contains(&name) || is_merged(&name, &[&trunk, &remote_trunk])? {
+            report.push(format!("{} (merged into {})", name, trunk));
+            stale.insert(name, true);
+        }
+    }
+    if stale.is_empty() {
+        println!("Nothing to prune.");
+        return Ok(());
+    }
+
+    let moves = children_to_move(&stack, &stale);
+    let verb = if dry_run { "Would remove" } else { "Removing" };
+    for line in &report {
+        println!("{} metadata for {}", verb, line);
+    }
+    print_moves(&moves, dry_run);
+    if dry_run {
+        return Ok(());
+    }
+
+    drop_branches(&stale, &moves)?;
+    println!("Pruned {} branch(es).", stale.len());
+    if !moves.is_empty() {
+        println!("Run `stack restack` to rebase the moved branches.");
+    }
+    Ok(())
+}
+
+/// Surviving children of `stale` branches as `(child, new parent, old
+/// parent)`, each moving onto its nearest ancestor that stays.
+fn children_to_move<'a>(
+    stack: &'a Stack,
+    stale: &HashMap<String, bool>,
+) -> Vec<(&'a str, &'a str, &'a str)> {
+    let trunk = stack.trunk();
+    let mut moves = Vec::new();
+    let mut children: Vec<&str> = stack
+        .branches()
+        .map(|b| b.name.as_str())
+        .filter(|b| !stale.contains_key(*b))
+        .collect();
+    children.sort();
+    for child in children {
+        let Some(old) = stack.parent(child) else {
+            continue;
+        };
+        if !stale.contains_key(old) {
+            continue;
+        }
+        // Bounded so a parent cycle can't hang
+        let mut new = old;
+        for _ in 0..=stale.len() {
+            if !stale.contains_key(new) {
+                break;
+            }
+            new = stack.parent(new).unwrap_or(trunk);
+        }
+        if stale.contains_key(new) {
+            new = trunk;
+        }
+        moves.push((child, new, old));
+    }
+    moves
+}
+
+fn print_moves(moves: &[(&str, &str, &str)], dry_run: bool) {
+    let verb = if dry_run { "Would move" } else { "Moving" };
+    for (child, new, old) in moves {
+        println!("{} {} onto {} (was on {})", verb, child, new, old);
+    }
+}
+
+/// Move `moves` and forget the `stale` branches, each flagged with whether
+/// the branch itself still exists.
+fn drop_branches(stale: &HashMap<String, bool>, moves: &[(&str, &str, &str)]) -> StackResult<()> {
+    for (child, new, _) in moves {
+        set_config(&format!("branch.{}.stack-parent", child), new)?;
+    }
+    for (branch, exists) in stale {
+        let _ = unset_config(&format!("branch.{}.stack-parent", branch));
+        let _ = unset_config(&format!("branch.{}.stack-base", branch));
+        let _ = unset_config(&format!("branch.{}.stack-frozen", branch));
+        let _ = unset_config(&format!("branch.{}.stack-note", branch));
+        // Someone else may still have a branch we only deleted locally
+        if *exists {
+            delete_meta(branch);
+        } else {
+            delete_local_meta(branch);
+        }
+    }
+    Ok(())
+}
+
+/// Whether `branch` has commits of its own and all of them are in one of
+/// `trunks`. A branch with nothing on it yet is not merged, just new.
+fn is_merged(branch: &str, trunks: &[&str]) -> StackResult<bool> {
+    if commit_ids(&own_commits_base(branch), branch)?.is_empty() {
+        return Ok(false);
+    }
+    Ok(trunks
+        .iter()
+        .any(|trunk| is_ancestor(branch, trunk).unwrap_or(false)))
+}
+
+/// Point out branches that look abandoned: no commits in `--days` days
+/// (`stack.stale-days`, default 30), PRs closed without merging, and stacks
+/// whose every branch has merged. Each one found is offered for deletion,
+/// after which `prune` tidies the metadata and moves survivors down.
+/// `--dry-run` only lists them.
+pub fn cmd_tidy(args: &[String]) -> StackResult<()> {
+    let dry_run = args.iter().any(|a| a == "--dry-run" || a == "-n");
+    let days = match flag_values(args, "--days")
+        .pop()
+        .or_else(|| setting("stale-days"))
+    {
+        Some(days) => days.parse::<u64>().map_err(|_| {
+            StackError::Usage(format!("--days takes a number of days, not '{}'", days))
+        })?,
+        None => 30,
+    };
+    let stack = Stack::load()?;
+    let trunk = stack.trunk().to_string();
+    let remote = get_remote(&trunk);
+    let _ = try_command("git", &["fetch", "--quiet", &remote, &trunk]);
+    let remote_trunk = format!("{}/{}", remote, trunk);
+    let prs = get_forge()?.review_status();
+
+    let mut names: Vec<&str> = stack
+        .branches()
+        .map(|b| b.name.as_str())
+        .filter(|b| stack.exists(b))
+        .collect();
+    names.sort();
+
+    let now = unix_now();
+    let mut stale = Vec::new();
+    let mut closed = Vec::new();
+    for name in &names {
+        if let Some(head) = stack.head(name) {
+            let age = now.saturating_sub(head.committed) / 86_400;
+            if age >= days {
+                stale.push((name.to_string(), format!("last commit {} days ago", age)));
+            }
+        }
+        if let Some(pr) = prs.get(*name).filter(|pr| pr.state == "CLOSED") {
+            closed.push((name.to_string(), format!("#{} closed", pr.number)));
+        }
+    }
+
+    let mut merged = Vec::new();
+    for root in stack.children(&trunk) {
+        let mut branches = vec![root.clone()];
+        branches.extend(stack.descendants(root));
+        let mut all_merged = true;
+        for branch in &branches {
+            let pr_merged = prs.get(branch).is_some_and(|pr| pr.state == "MERGED");
+            if !pr_merged && !is_merged(branch, &[&trunk, &remote_trunk])? {
+                all_merged = false;
+                break;
+            }
+        }
+        if all_merged {
+            let what = format!("{} branch(es) merged", branches.len());
+            merged.extend(branches.into_iter().map(|b| (b, what.clone())));
+        }
+    }
+
+    let sections = [
+        (format!("No commits in {} days:", days), &stale),
+        ("PRs closed without merging:".to_string(), &closed),
+        ("Stacks merged into trunk:".to_string(), &merged),
+    ];
+    let mut candidates: Vec<&str> = Vec::new();
+    for (title, found) in &sections {
+        if found.is_empty() {
+            continue;
+        }
+        println!("{}", title);
+        for (branch, why) in found.iter() {
+            println!("  {} ({})", branch, why);
+            if !candidates.contains(&branch.as_str()) {
+                candidates.push(branch);
+            }
+        }
+    }
+    if candidates.is_empty() {
+        println!("Nothing to tidy.");
+        return Ok(());
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    let current = get_current_branch()?;
+    let mut deleted = HashMap::new();
+    for branch in candidates {
+        if branch == current {
+            eprintln!("Warning: skipping {}: it is checked out", branch);
+            continue;
+        }
+        if confirm(&format!("Delete {}? [y/N] ", branch))? {
+            git(&["branch", "--quiet", "-D", branch])?;
+            deleted.insert(branch.to_string(), false);
+        }
+    }
+    if deleted.is_empty() {
+        return Ok(());
+    }
+
+    let moves = children_to_move(&stack, &deleted);
+    print_moves(&moves, false);
+    drop_branches(&deleted, &moves)?;
+    println!("Deleted {} branch(es).", deleted.len());
+    if !moves.is_empty() {
+        println!("Run `stack restack` to rebase the moved branches.");
+    }
+    Ok(())
+}
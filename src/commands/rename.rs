@@ -0,0 +1,96 @@
+use crate::args::positional_args;
+use stack_core::config::{ensure_unprotected, trunk};
+use stack_core::engine::Stack;
+use stack_core::error::{StackError, StackResult, err};
+use stack_core::forge::{authenticated_forge, submit_target};
+use stack_core::git::{branch_exists, git, require_current_branch, set_config, try_command};
+use stack_core::info;
+use stack_core::metadata::{delete_meta, meta_branches, push_meta};
+use stack_core::naming::branch_name;
+use stack_core::pr::invalidate_pr_cache;
+
+/// Rename a branch (the current one unless two names are given) along with
+/// everything that points at it: its children's parent, its shared metadata,
+/// and its remote branch and review. Forges that can't rename branches
+/// server-side get the new name pushed and the old one deleted, and the
+/// review has to be submitted again.
+pub fn cmd_rename(args: &[String]) -> StackResult<()> {
+    let (old, new) = match positional_args(args, &[]).as_slice() {
+        [new] => (require_current_branch("rename")?, branch_name(new)),
+        [old, new] => (old.to_string(), branch_name(new)),
+        _ => {
+            return Err(StackError::Usage(
+                "Usage: stack rename [<branch>] <new-name>".to_string(),
+            ));
+        }
+    };
+    if old == trunk() {
+        return Err(err(&format!("Cannot rename trunk ({})", old)));
+    }
+    ensure_unprotected(&old, "rename")?;
+    if !branch_exists(&old)? {
+        return Err(err(&format!("Branch '{}' does not exist", old)));
+    }
+    if branch_exists(&new)? {
+        return Err(err(&format!("Branch '{}' already exists", new)));
+    }
+
+    let remote = submit_target(&old)?.push_remote;
+    let old_ref = format!("refs/heads/{}", old);
+    let new_ref = format!("refs/heads/{}", new);
+    let on_remote = try_command("git", &["ls-remote", "--exit-code", &remote, &old_ref]).is_some();
+    let children = Stack::load()?.children(&old).to_vec();
+    let shared = meta_branches()?;
+
+    // Remote first: if that fails, nothing has changed locally
+    let mut resubmit = false;
+    if on_remote && !authenticated_forge()?.rename_branch(&old, &new)? {
+        git(&[
+            "push",
+            "--quiet",
+            &remote,
+            &format!("{}:{}", old_ref, new_ref),
+        ])?;
+        git(&["push", "--quiet", &remote, "--delete", &old])?;
+        resubmit = true;
+    }
+
+    // Moves the branch's config section, stack-parent and stack-base included
+    git(&["branch", "-m", &old, &new])?;
+    for child in &children {
+        set_config(&format!("branch.{}.stack-parent", child), &new)?;
+    }
+    info!("Renamed {} to {}", old, new);
+
+    if on_remote {
+        git(&["fetch", "--quiet", &remote, &new_ref])?;
+        git(&[
+            "branch",
+            "--quiet",
+            "--set-upstream-to",
+            &format!("{}/{}", remote, new),
+            &new,
+        ])?;
+        let _ = git(&[
+            "update-ref",
+            "-d",
+            &format!("refs/remotes/{}/{}", remote, old),
+        ]);
+    }
+
+    if shared.contains(&old) {
+        delete_meta(&old);
+        let mut republish = vec![new.clone()];
+        republish.extend(children.iter().filter(|c| shared.contains(c)).cloned());
+        push_meta(&republish)?;
+    }
+    invalidate_pr_cache();
+
+    if resubmit {
+        println!(
+            "Pushed {} and deleted {} on {}. Run `stack submit --stack` to update the reviews.",
+            new, old, remote
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,577 @@
+use std::collections::HashSet;
+
+use crate::args::{flag_values, positional_args};
+use stack_core::autostash::{restore_autostash, with_autostash};
+use stack_core::config::{ensure_unprotected, trunk};
+use stack_core::engine::{RestackPlan, Stack, merged_branches, set_rebase_flags};
+use stack_core::error::{StackError, StackResult, err};
+use stack_core::events::set_output_format;
+use stack_core::forge::{get_forge, submit_target};
+use stack_core::git::{
+    branch_exists, commit_ids, get_remote, git, git_passthrough, is_ancestor, offline,
+    operation_in_progress, other_worktrees, require_current_branch, set_config, try_command,
+};
+use stack_core::info;
+use stack_core::lock::is_read_only;
+use stack_core::metadata::{
+    auto_import_meta, delete_meta, get_base, get_parent, is_frozen, own_commits_base,
+    require_parent, set_base, set_frozen, set_order,
+};
+use stack_core::restack_progress::RestackProgress;
+use stack_core::ui::{confirm, edit_text, pick_index};
+
+/// Refuse to run `command` on top of an unfinished rebase, merge or
+/// cherry-pick. Commands that only read state are always allowed.
+pub fn guard_operation(command: &str) -> StackResult<()> {
+    if command == "continue" || is_read_only(command) {
+        return Ok(());
+    }
+    match operation_in_progress() {
+        Ok(Some(op)) => Err(StackError::Conflict(format!(
+            "A {} is in progress. Resolve it and run `stack continue`, or abort with `git {} --abort`.",
+            op, op
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Finish the git operation a stack command stopped on, then pick up where
+/// the restack left off: record the new bases, run the rebases it had left
+/// (or, for a rebase it didn't start, restack the children of the branch
+/// being rebased), return to the branch it started on, and put back what it
+/// stashed.
+pub fn cmd_continue() -> StackResult<()> {
+    let Some(op) = operation_in_progress()? else {
+        return Err(err("Nothing to continue"));
+    };
+    git_passthrough(&[op, "--continue"])?;
+
+    if let Some(op) = operation_in_progress()? {
+        println!(
+            "The {} stopped again. Resolve it and run `stack continue`.",
+            op
+        );
+        return Ok(());
+    }
+    if op != "rebase" {
+        return Ok(());
+    }
+
+    let current = require_current_branch("continue")?;
+    let trunk = trunk();
+    for branch in Stack::load()?.path_to_trunk(&current) {
+        let Some(mut parent) = get_parent(&branch) else {
+            continue;
+        };
+        // Interrupted while moving off a parent that has landed and is gone
+        if !branch_exists(&parent)? && is_ancestor(&trunk, &branch)? {
+            set_config(&format!("branch.{}.stack-parent", branch), &trunk)?;
+            parent = trunk.clone();
+        }
+        if branch_exists(&parent)? && is_ancestor(&parent, &branch)? {
+            set_base(&branch, &parent)?;
+        }
+    }
+
+    let progress = RestackProgress::load()?;
+    RestackProgress::clear()?;
+    let return_to = match progress.filter(|p| p.stopped_on == current) {
+        Some(progress) => {
+            if !progress.steps.is_empty() {
+                info!("Finishing the restack...");
+            }
+            progress.plan().execute(&progress.start_branch)?;
+            progress.start_branch
+        }
+        None => {
+            info!("Restacking children of {}...", current);
+            RestackPlan::above(&Stack::load()?, &current, &HashSet::new())?.execute(&current)?;
+            current
+        }
+    };
+    if !return_to.is_empty() {
+        git(&["checkout", &return_to])?;
+    }
+    restore_autostash()
+}
+
+/// Restack everything above the current branch. `--from-trunk` first brings
+/// trunk up to date with the remote, then rebases the whole stack through
+/// the current branch onto it, bottom branch first.
+///
+/// Branches whose commits all turn out to be in their parent already are
+/// offered for deletion, so their PRs don't linger with empty diffs.
+/// Uncommitted changes and untracked files are stashed for the duration.
+/// `--output json` reports each rebase and conflict as a JSON line on
+/// stdout.
+///
+/// `--check` moves nothing: it tries each rebase as an in-memory trial
+/// merge and lists the branches that would conflict, and in which files.
+/// With `--from-trunk` it previews against trunk as it is locally.
+///
+/// Rebase options after `--` (`--committer-date-is-author-date`,
+/// `--autosquash`, `-Xtheirs`, ...) are passed to every rebase, and
+/// `rebase.autoSquash` is honored too. With `rerere.autoUpdate`, a rebase
+/// whose conflicts rerere resolves keeps going instead of stopping.
+pub fn cmd_restack(args: &[String]) -> StackResult<()> {
+    let (args, rebase_flags) = match args.iter().position(|a| a == "--") {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => (args, &[][..]),
+    };
+    set_rebase_flags(rebase_flags)?;
+    set_output_format(flag_values(args, "--output").pop().as_deref())?;
+    auto_import_meta();
+    let start_branch = require_current_branch("restack")?;
+    if args.iter().any(|a| a == "--check") {
+        return check_restack(args, &start_branch);
+    }
+    with_autostash("restack", || restack(args, &start_branch))
+}
+
+fn restack(args: &[String], start_branch: &str) -> StackResult<()> {
+    let start_branch = start_branch.to_string();
+    let from_trunk = args.iter().any(|a| a == "--from-trunk");
+    if from_trunk {
+        update_trunk(&start_branch)?;
+    }
+    let stack = Stack::load()?;
+    let merged = merged_branches()?;
+    let trunk = trunk();
+
+    let bottom = stack.path_to_trunk(&start_branch).remove(0);
+    let mut moving = if from_trunk && start_branch != trunk {
+        vec![bottom.clone()]
+    } else {
+        vec![start_branch.clone()]
+    };
+    moving.extend(stack.descendants(&moving[0]));
+    let had_commits = with_own_commits(&moving)?;
+
+    if from_trunk && start_branch != trunk {
+        info!("Restacking {} and everything above it...", bottom);
+        RestackPlan::including(&stack, &bottom, &merged)?.execute(&start_branch)?;
+    } else {
+        // The current branch itself moves when its parent has landed
+        let plan = match get_parent(&start_branch) {
+            Some(parent) if merged.contains(&parent) || !branch_exists(&parent)? => {
+                info!("Restacking {} and its children...", start_branch);
+                RestackPlan::including(&stack, &start_branch, &merged)?
+            }
+            _ => {
+                info!("Restacking children of {}...", start_branch);
+                RestackPlan::above(&stack, &start_branch, &merged)?
+            }
+        };
+        plan.execute(&start_branch)?;
+    }
+
+    let still = with_own_commits(&had_commits)?;
+    let emptied: Vec<String> = had_commits
+        .into_iter()
+        .filter(|b| !still.contains(b))
+        .collect();
+    let return_to = fold_empty_branches(&emptied, &start_branch)?;
+
+    info!("Done. Returning to {}", return_to);
+    git(&["checkout", &return_to])?;
+    Ok(())
+}
+
+/// Preview the restack `restack` would do from `start_branch`, without
+/// moving any branch, and fail if any of it would conflict.
+fn check_restack(args: &[String], start_branch: &str) -> StackResult<()> {
+    let stack = Stack::load()?;
+    let merged = merged_branches()?;
+    let trunk = trunk();
+    let plan = if args.iter().any(|a| a == "--from-trunk") && start_branch != trunk {
+        let bottom = stack.path_to_trunk(start_branch).remove(0);
+        RestackPlan::including(&stack, &bottom, &merged)?
+    } else {
+        // The current branch itself moves when its parent has landed
+        match get_parent(start_branch) {
+            Some(parent) if merged.contains(&parent) || !branch_exists(&parent)? => {
+                RestackPlan::including(&stack, start_branch, &merged)?
+            }
+            _ => RestackPlan::above(&stack, start_branch, &merged)?,
+        }
+    };
+
+    let preview = plan.preview()?;
+    if preview.is_empty() {
+        println!("Nothing to restack.");
+        return Ok(());
+    }
+    let mut conflicted = 0;
+    for (branch, onto, files) in &preview {
+        if files.is_empty() {
+            println!("  {} onto {}: clean", branch, onto);
+        } else {
+            conflicted += 1;
+            println!(
+                "  {} onto {}: conflicts in {}",
+                branch,
+                onto,
+                files.join(", ")
+            );
+        }
+    }
+    if conflicted > 0 {
+        return Err(StackError::Conflict(format!(
+            "{} of {} rebases would conflict; nothing was changed",
+            conflicted,
+            preview.len()
+        )));
+    }
+    println!("No conflicts.");
+    Ok(())
+}
+
+/// The `branches` that have commits of their own.
+fn with_own_commits(branches: &[String]) -> StackResult<Vec<String>> {
+    let mut out = Vec::new();
+    for branch in branches {
+        if branch_exists(branch)? && !commit_ids(&own_commits_base(branch), branch)?.is_empty() {
+            out.push(branch.clone());
+        }
+    }
+    Ok(out)
+}
+
+/// Offer to delete `branches`, which a restack left without commits of their
+/// own because their parent already has the changes: children move onto
+/// the parent and PRs are closed. Returns the branch to finish on, which is
+/// `current` unless that one went.
+fn fold_empty_branches(branches: &[String], current: &str) -> StackResult<String> {
+    let mut return_to = current.to_string();
+    for branch in branches {
+        let parent = get_parent(branch).unwrap_or_else(trunk);
+        eprintln!(
+            "Warning: {} has no commits left; its changes are already in {}",
+            branch, parent
+        );
+        if !confirm(&format!(
+            "Delete {} and close its PR, moving its children onto {}? [y/N] ",
+            branch, parent
+        ))? {
+            continue;
+        }
+
+        for child in Stack::load()?.children(branch) {
+            info!("Moving {} onto {}", child, parent);
+            set_config(&format!("branch.{}.stack-parent", child), &parent)?;
+        }
+        if let Err(e) = get_forge().and_then(|forge| forge.close_pr(branch)) {
+            eprintln!("Warning: could not close the PR for {}: {}", branch, e);
+        }
+        let remote = submit_target(branch)?.push_remote;
+        let remote_ref = format!("refs/heads/{}", branch);
+        if try_command("git", &["ls-remote", "--exit-code", &remote, &remote_ref]).is_some() {
+            let _ = git(&["push", "--quiet", &remote, "--delete", branch]);
+        }
+
+        if return_to == *branch {
+            return_to = parent.clone();
+            git(&["checkout", "--quiet", &parent])?;
+        }
+        git(&["branch", "--quiet", "-D", branch])?;
+        delete_meta(branch);
+        info!("Deleted {}", branch);
+    }
+    Ok(return_to)
+}
+
+/// Fast-forward local trunk to the remote's. A trunk with commits of its own
+/// stays as it is, with a warning, and is what the stack goes onto.
+fn update_trunk(current: &str) -> StackResult<()> {
+    let trunk = trunk();
+    let remote = get_remote(&trunk);
+    if offline() {
+        info!(
+            "Offline: restacking onto {}/{} as last fetched",
+            remote, trunk
+        );
+    } else {
+        info!("Fetching {} from {}...", trunk, remote);
+        git(&["fetch", "--quiet", &remote, &trunk])?;
+    }
+
+    let remote_trunk = format!("{}/{}", remote, trunk);
+    if is_ancestor(&remote_trunk, &trunk)? {
+        return Ok(());
+    }
+    if !is_ancestor(&trunk, &remote_trunk)? {
+        eprintln!(
+            "Warning: {} has commits that aren't on {}; restacking onto local {}",
+            trunk, remote_trunk, trunk
+        );
+        return Ok(());
+    }
+    // A checked-out trunk has to move with its worktree
+    if current == trunk {
+        git(&["merge", "--quiet", "--ff-only", &remote_trunk])?;
+    } else if let Some(dir) = other_worktrees()?.get(&trunk) {
+        let dir_arg = dir.to_string_lossy();
+        git(&[
+            "-C",
+            &dir_arg,
+            "merge",
+            "--quiet",
+            "--ff-only",
+            &remote_trunk,
+        ])?;
+    } else {
+        git(&[
+            "update-ref",
+            &format!("refs/heads/{}", trunk),
+            &remote_trunk,
+        ])?;
+    }
+    Ok(())
+}
+
+/// Pin a branch (default: the current one) so restacks leave it alone, for
+/// instance while it is under review.
+pub fn cmd_freeze(args: &[String]) -> StackResult<()> {
+    let branch = freeze_target(args, "freeze")?;
+    set_frozen(&branch, true)?;
+    info!(
+        "Froze {}. Restacks will skip it until `stack unfreeze`.",
+        branch
+    );
+    Ok(())
+}
+
+pub fn cmd_unfreeze(args: &[String]) -> StackResult<()> {
+    let branch = freeze_target(args, "unfreeze")?;
+    set_frozen(&branch, false)?;
+    info!(
+        "Unfroze {}. Run `stack restack` to bring it up to date.",
+        branch
+    );
+    Ok(())
+}
+
+fn freeze_target(args: &[String], command: &str) -> StackResult<String> {
+    let branch = match args.first() {
+        Some(branch) => branch.clone(),
+        None => require_current_branch(command)?,
+    };
+    require_parent(&branch)?;
+    Ok(branch)
+}
+
+/// Give the current branch a new parent, `--onto` or picked from the tree
+/// of stacks, then rebase its own commits there and restack its children.
+pub fn cmd_move(args: &[String]) -> StackResult<()> {
+    let branch = require_current_branch("move")?;
+    ensure_unprotected(&branch, "move")?;
+    let old = require_parent(&branch)?;
+    let stack = Stack::load()?;
+    let trunk = trunk();
+    let descendants = stack.descendants(&branch);
+
+    let onto = match flag_values(args, "--onto").pop() {
+        Some(onto) => onto,
+        None => {
+            // Everything but the branch and what sits on it, as a tree
+            let mut choices = Vec::new();
+            for root in stack.roots() {
+                tree_choices(&stack, &root, 0, &branch, &mut choices);
+            }
+            if !choices.iter().any(|(b, _)| *b == trunk) {
+                choices.insert(0, (trunk.clone(), trunk.clone()));
+            }
+            let labels: Vec<String> = choices
+                .iter()
+                .map(|(b, label)| {
+                    if *b == old {
+                        format!("{} (current parent)", label)
+                    } else {
+                        label.clone()
+                    }
+                })
+                .collect();
+            let i = pick_index(&format!("Move {} onto:", branch), &labels)?;
+            choices.swap_remove(i).0
+        }
+    };
+
+    if onto == branch || descendants.contains(&onto) {
+        return Err(err(&format!(
+            "Can't move {} onto {}: {} is stacked on {}",
+            branch, onto, onto, branch
+        )));
+    }
+    if onto != trunk && !branch_exists(&onto)? {
+        return Err(err(&format!("Branch '{}' does not exist", onto)));
+    }
+    if onto == old {
+        println!("{} is already on {}.", branch, onto);
+        return Ok(());
+    }
+
+    info!("Moving {} from {} onto {}", branch, old, onto);
+    // Pin where its own commits start, or the old parent's would come along
+    if !get_base(&branch).is_some_and(|b| is_ancestor(&b, &branch).unwrap_or(false)) {
+        set_base(&branch, &old)?;
+    }
+    set_config(&format!("branch.{}.stack-parent", branch), &onto)?;
+    RestackPlan::including(&Stack::load()?, &branch, &HashSet::new())?.execute(&branch)?;
+    git(&["checkout", &branch])?;
+    Ok(())
+}
+
+/// `(branch, indented label)` for `branch` and everything above it, skipping
+/// `exclude` and its descendants.
+fn tree_choices(
+    stack: &Stack,
+    branch: &str,
+    depth: usize,
+    exclude: &str,
+    out: &mut Vec<(String, String)>,
+) {
+    if branch == exclude {
+        return;
+    }
+    out.push((
+        branch.to_string(),
+        format!("{}{}", "  ".repeat(depth), branch),
+    ));
+    for child in stack.children(branch) {
+        tree_choices(stack, child, depth + 1, exclude, out);
+    }
+}
+
+/// Put the branches of the current stack in a new order, edited as a list,
+/// and rebase each onto the one now below it. The new order is recorded
+/// before anything moves, so a rebase that stops on conflicts is finished
+/// with `stack continue` like any restack.
+pub fn cmd_reorder() -> StackResult<()> {
+    let start_branch = require_current_branch("reorder")?;
+    require_parent(&start_branch)?;
+    let stack = Stack::load()?;
+    let chain = stack.linear_chain(&start_branch);
+    if chain.len() < 2 {
+        return Err(err(
+            "Nothing to reorder: the stack has fewer than two branches",
+        ));
+    }
+    for branch in &chain {
+        ensure_unprotected(branch, "reorder")?;
+        if is_frozen(branch) {
+            return Err(err(&format!(
+                "{} is frozen, so `stack reorder` won't move it (`stack unfreeze {}` first)",
+                branch, branch
+            )));
+        }
+    }
+    let root = get_parent(&chain[0]).unwrap_or_else(trunk);
+
+    let mut todo = chain.join("\n");
+    todo.push_str(&format!(
+        "\n\n# Reorder the branches above; the first line sits directly on {}.\n\
+         # Lines starting with '#' are ignored. Every branch must stay listed.\n",
+        root
+    ));
+    let order: Vec<String> = edit_text(&todo)?
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+
+    let mut sorted_order = order.clone();
+    let mut sorted_chain = chain.clone();
+    sorted_order.sort();
+    sorted_chain.sort();
+    if sorted_order != sorted_chain {
+        return Err(err(
+            "Aborting: the edited list must contain each branch exactly once",
+        ));
+    }
+    if order == chain {
+        info!("Order unchanged.");
+        return Ok(());
+    }
+
+    // Pin where each branch's own commits start, then record the whole new
+    // order, so the restack below moves each branch's own commits only
+    for branch in &chain {
+        if !get_base(branch).is_some_and(|b| is_ancestor(&b, branch).unwrap_or(false)) {
+            set_base(branch, &get_parent(branch).unwrap_or_else(|| root.clone()))?;
+        }
+    }
+    let mut parent = root;
+    for branch in &order {
+        set_config(&format!("branch.{}.stack-parent", branch), &parent)?;
+        parent = branch.clone();
+    }
+
+    // Rebase into that order; branches hanging off the chain follow along
+    RestackPlan::including(&Stack::load()?, &order[0], &HashSet::new())?.execute(&start_branch)?;
+
+    info!("Done. Returning to {}", start_branch);
+    git(&["checkout", &start_branch])?;
+    Ok(())
+}
+
+/// Set the order `log`, `top` and `restack` visit the branches stacked on
+/// `--parent` (default: the current branch) in. Children named on the
+/// command line go first, in that order, and the rest keep theirs; with
+/// none named, the list opens in the editor. Nothing is rebased.
+pub fn cmd_reorder_children(args: &[String]) -> StackResult<()> {
+    let parent = match flag_values(args, "--parent").pop() {
+        Some(parent) => parent,
+        None => require_current_branch("reorder-children")?,
+    };
+    let children = Stack::load()?.children(&parent).to_vec();
+    if children.len() < 2 {
+        return Err(err(&format!(
+            "Nothing to reorder: fewer than two branches are stacked on {}",
+            parent
+        )));
+    }
+
+    let named: Vec<String> = positional_args(args, &["--parent"])
+        .into_iter()
+        .cloned()
+        .collect();
+    let mut order = if named.is_empty() {
+        let mut todo = children.join("\n");
+        todo.push_str(&format!(
+            "\n\n# Order the branches stacked on {}; the first is visited first.\n\
+             # Lines starting with '#' are ignored. Every branch must stay listed.\n",
+            parent
+        ));
+        edit_text(&todo)?
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    } else {
+        named
+    };
+    for branch in &order {
+        if !children.contains(branch) {
+            return Err(err(&format!("{} is not stacked on {}", branch, parent)));
+        }
+    }
+    let mut unique = order.clone();
+    unique.sort();
+    unique.dedup();
+    if unique.len() != order.len() {
+        return Err(err("Aborting: each branch may only be listed once"));
+    }
+    for child in &children {
+        if !order.contains(child) {
+            order.push(child.clone());
+        }
+    }
+
+    for (i, branch) in order.iter().enumerate() {
+        set_order(branch, i as u64 + 1)?;
+    }
+    info!("Branches on {}: {}", parent, order.join(", "));
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process::{Command, Stdio};
+
+use serde_json::{Value, json};
+
+use stack_core::engine::Stack;
+use stack_core::error::{StackError, StackResult, err};
+use stack_core::forge::get_forge;
+use stack_core::git::{ahead_behind, get_current_branch, worktree_changes};
+use stack_core::metadata::get_note;
+use stack_core::pr::{PrInfo, invalidate_pr_cache};
+
+/// JSON-RPC 2.0 error codes.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+/// Answer JSON-RPC 2.0 requests from editor plugins, one per line on stdin,
+/// one response per line on stdout, until stdin closes. The methods:
+/// `tree` (every stack branch, with its parent, children, tip, PR and
+/// whether it needs a restack), `status` (the checked-out branch) and `run`
+/// (`{"args": ["restack"]}` runs that stack command, non-interactively, and
+/// returns its exit code and output). State is read afresh per request.
+pub fn cmd_serve() -> StackResult<()> {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout().lock();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => {
+                // Notifications, without an id, get no answer
+                let Some(id) = request.get("id").cloned() else {
+                    let _ = handle(&request);
+                    continue;
+                };
+                match handle(&request) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, message)) => error_response(id, code, &message),
+                }
+            }
+            Err(e) => error_response(Value::Null, PARSE_ERROR, &e.to_string()),
+        };
+        writeln!(stdout, "{}", response)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+fn handle(request: &Value) -> Result<Value, (i64, String)> {
+    let failed = |e: StackError| (SERVER_ERROR, e.to_string());
+    match request["method"].as_str().unwrap_or_default() {
+        "tree" => tree().map_err(failed),
+        "status" => status().map_err(failed),
+        "run" => {
+            let args: Option<Vec<&str>> = request["params"]["args"]
+                .as_array()
+                .and_then(|a| a.iter().map(Value::as_str).collect());
+            match args {
+                Some(args) if !args.is_empty() && args[0] != "serve" => run(&args).map_err(failed),
+                _ => Err((
+                    INVALID_PARAMS,
+                    "run takes {\"args\": [<command>, ...]}".to_string(),
+                )),
+            }
+        }
+        other => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'", other))),
+    }
+}
+
+fn pr_json(pr: Option<&PrInfo>) -> Value {
+    match pr {
+        Some(pr) => json!({
+            "number": pr.number,
+            "state": pr.state,
+            "review": pr.review,
+            "draft": pr.draft,
+            "checks": pr.ci_status(),
+            "url": pr.url,
+        }),
+        None => Value::Null,
+    }
+}
+
+fn tree() -> StackResult<Value> {
+    let stack = Stack::load()?;
+    stack.check_cycles()?;
+    let prs = get_forge()?.review_status();
+    let mut names: Vec<&str> = stack
+        .branches()
+        .filter(|b| b.parent.is_some() && stack.exists(&b.name))
+        .map(|b| b.name.as_str())
+        .collect();
+    names.sort();
+
+    let branches: Vec<Value> = names
+        .iter()
+        .map(|name| {
+            let parent = stack.parent(name).unwrap_or_default();
+            let behind = ahead_behind(name, parent).map(|(_, behind)| behind).ok();
+            json!({
+                "name": name,
+                "parent": parent,
+                "children": stack.children(name),
+                "head": stack.head(name).map(|h| h.oid.clone()),
+                "subject": stack.head(name).map(|h| h.subject.clone()),
+                "frozen": stack.branch(name).is_some_and(|b| b.frozen),
+                "note": stack.branch(name).and_then(|b| b.note.as_deref()),
+                "needs_restack": behind.is_some_and(|b| b > 0),
+                "pr": pr_json(prs.get(*name)),
+            })
+        })
+        .collect();
+    Ok(json!({
+        "trunk": stack.trunk(),
+        "current": get_current_branch()?,
+        "roots": stack.roots(),
+        "branches": branches,
+    }))
+}
+
+fn status() -> StackResult<Value> {
+    let branch = get_current_branch()?;
+    let stack = Stack::load()?;
+    let parent = stack.parent(&branch);
+    let (ahead, behind) = match parent {
+        Some(parent) => ahead_behind(&branch, parent)
+            .map(|(a, b)| (Some(a), Some(b)))
+            .unwrap_or_default(),
+        None => (None, None),
+    };
+    let (changed, untracked) = worktree_changes()?;
+    Ok(json!({
+        "branch": branch,
+        "parent": parent,
+        "note": get_note(&branch),
+        "ahead": ahead,
+        "behind": behind,
+        "changed": changed,
+        "untracked": untracked,
+        "pr": pr_json(get_forge()?.review_status().get(&branch)),
+    }))
+}
+
+/// Run `stack <args>` as its own process, so its output and its lock stay
+/// out of the protocol, answering any prompt with the default.
+fn run(args: &[&str]) -> StackResult<Value> {
+    let exe = env::current_exe().map_err(|e| err(&format!("Cannot find stack itself: {}", e)))?;
+    let output = Command::new(exe)
+        .arg("--yes")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()?;
+    // What it changed isn't in this process's view of the PRs yet
+    invalidate_pr_cache();
+    Ok(json!({
+        "exit_code": output.status.code(),
+        "stdout": String::from_utf8_lossy(&output.stdout),
+        "stderr": String::from_utf8_lossy(&output.stderr),
+    }))
+}
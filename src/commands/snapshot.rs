@@ -0,0 +1,90 @@
+use crate::args::positional_args;
+use stack_core::engine::Stack;
+use stack_core::error::{StackError, StackResult};
+use stack_core::git::{ensure_clean_worktree, get_current_branch, git, other_worktrees};
+use stack_core::info;
+use stack_core::naming::{date, today};
+use stack_core::snapshot::{load_snapshot, save_snapshot, snapshots};
+
+/// Save every stacked branch's tip and metadata as a named checkpoint
+/// (today's date by default) for `stack restore` to go back to.
+pub fn cmd_snapshot(args: &[String]) -> StackResult<()> {
+    let name = match positional_args(args, &[]).first() {
+        Some(name) => name.to_string(),
+        None => {
+            let taken: Vec<String> = snapshots()?.into_iter().map(|s| s.name).collect();
+            let mut name = today();
+            let mut n = 2;
+            while taken.contains(&name) {
+                name = format!("{}-{}", today(), n);
+                n += 1;
+            }
+            name
+        }
+    };
+    let count = save_snapshot(&name, &Stack::load()?)?;
+    println!(
+        "Saved snapshot {} of {} branch(es). `stack restore {}` puts them back.",
+        name, count, name
+    );
+    Ok(())
+}
+
+/// List the saved snapshots, oldest first.
+pub fn cmd_snapshots() -> StackResult<()> {
+    let all = snapshots()?;
+    if all.is_empty() {
+        println!("No snapshots. Take one with `stack snapshot [<name>]`.");
+        return Ok(());
+    }
+    let width = all.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    for snapshot in all {
+        let names: Vec<&str> = snapshot
+            .branches
+            .iter()
+            .map(|(_, b)| b.name.as_str())
+            .collect();
+        println!(
+            "{:width$}  {}  {}",
+            snapshot.name,
+            date(snapshot.created),
+            names.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Reset every branch in a snapshot to the tip it had then, metadata and
+/// all, recreating deleted ones. Branches made since are left alone, and so
+/// are branches checked out in other worktrees, which would be left with
+/// the wrong files.
+pub fn cmd_restore(args: &[String]) -> StackResult<()> {
+    let Some(name) = positional_args(args, &[]).first().map(|n| n.to_string()) else {
+        return Err(StackError::Usage(
+            "Usage: stack restore <snapshot> (see `stack snapshots`)".to_string(),
+        ));
+    };
+    let snapshot = load_snapshot(&name)?;
+    ensure_clean_worktree("restoring a snapshot")?;
+    let current = get_current_branch()?;
+    let elsewhere = other_worktrees()?;
+
+    for (tip, branch) in &snapshot.branches {
+        if let Some(dir) = elsewhere.get(&branch.name) {
+            eprintln!(
+                "Warning: skipping {}: it is checked out in {}",
+                branch.name,
+                dir.display()
+            );
+            continue;
+        }
+        info!("   -> {} at {}", branch.name, &tip[..tip.len().min(10)]);
+        snapshot.restore_branch(tip, branch)?;
+    }
+    // The checked-out branch moved under the worktree
+    if snapshot.branches.iter().any(|(_, b)| b.name == current) {
+        git(&["reset", "--quiet", "--hard"])?;
+    }
+    info!("Restored snapshot {}.", name);
+    Ok(())
+}
@@ -0,0 +1,326 @@
+use std::collections::HashSet;
+
+use crate::args::{flag_values, positional_args};
+use stack_core::config::{ensure_unprotected, trunk};
+use stack_core::drafts::{clear_description, save_description, saved_description};
+use stack_core::engine::{RestackPlan, Stack};
+use stack_core::error::{StackError, StackResult, err};
+use stack_core::events::set_output_format;
+use stack_core::footer::refresh_footers;
+use stack_core::forge::{Forge, SubmitOptions, authenticated_forge, get_forge, submit_target};
+use stack_core::git::{ensure_clean_worktree, git, require_current_branch, rev_parse, try_command};
+use stack_core::hooks::run_hook;
+use stack_core::limits::check_size_limits;
+use stack_core::metadata::{get_parent, push_meta, require_parent};
+use stack_core::per_commit::{STACK_ID_TRAILER, ensure_stack_ids, sync_commit_branches};
+use stack_core::pr::invalidate_pr_cache;
+use stack_core::test_results::require_passing_tests;
+use stack_core::ui::{edit_pr_message, open_url, pr_defaults};
+use stack_core::{info, report};
+
+/// Push the current branch (or with `--stack`, everything below it too) and
+/// open or update its PR. `--draft` and `--ready` override `stack.draft` for
+/// the PRs this opens. `--per-commit` opens one PR per commit of the current
+/// branch instead, each on its own `stack/<branch>/<id>` branch.
+///
+/// Descriptions written for new PRs are saved until the PRs exist, so a
+/// failed submit reuses them next time. `--edit-description` opens each
+/// branch's description (saved, current or default) in the editor first.
+/// `--output json` reports pushes and PRs as JSON lines on stdout.
+///
+/// `--no-push` updates the PRs (base, reviewers, footers) from the branches
+/// as they are on the remote, pushing nothing; `--push-only` pushes the
+/// branches (to run CI, say) and leaves the PRs alone.
+pub fn cmd_submit(args: &[String]) -> StackResult<()> {
+    set_output_format(flag_values(args, "--output").pop().as_deref())?;
+    let whole_stack = args.iter().any(|a| a == "--stack");
+    let per_commit = args.iter().any(|a| a == "--per-commit");
+    let no_push = args.iter().any(|a| a == "--no-push");
+    let push_only = args.iter().any(|a| a == "--push-only");
+    if no_push && push_only {
+        return Err(StackError::Usage(
+            "Use one of --no-push and --push-only, not both".to_string(),
+        ));
+    }
+    let current = require_current_branch("submit")?;
+    // Pushing alone needs no forge credentials
+    let forge = if push_only {
+        get_forge()?
+    } else {
+        authenticated_forge()?
+    };
+
+    let mut stale = Vec::new();
+    let branches = if per_commit {
+        let (branches, gone) = commit_branches(&current)?;
+        stale = gone;
+        branches
+    } else if whole_stack {
+        Stack::load()?.path_to_trunk(&current)
+    } else {
+        vec![current.clone()]
+    };
+
+    let verify = !args.iter().any(|a| a == "--no-verify");
+    if verify {
+        require_passing_tests(&branches, "submit")?;
+        check_size_limits(&branches, "submit")?;
+        run_hook("pre-submit", &branches)?;
+    }
+
+    if args.iter().any(|a| a == "--edit-description") && !per_commit && !push_only {
+        edit_descriptions(forge.as_ref(), &branches)?;
+    }
+
+    let defaults = SubmitOptions::with_defaults(
+        flag_values(args, "--reviewer"),
+        flag_values(args, "--label"),
+        flag_values(args, "--assignee"),
+    );
+    let options = SubmitOptions {
+        force: args.iter().any(|a| a == "--force" || a == "-f"),
+        draft: draft_flag(args).unwrap_or(defaults.draft),
+        from_commits: per_commit,
+        no_push,
+        ..defaults
+    };
+    if push_only {
+        forge.push(&branches, &options)?;
+        if !per_commit {
+            push_meta(&branches)?;
+        }
+    } else {
+        forge.submit(&branches, &options)?;
+
+        for branch in &branches {
+            clear_description(branch);
+        }
+
+        if per_commit {
+            close_stale_prs(forge.as_ref(), &stale)?;
+            refresh_footers(forge.as_ref(), &branches)?;
+        } else {
+            if !no_push {
+                push_meta(&branches)?;
+            }
+            refresh_footers(forge.as_ref(), &Stack::load()?.linear_chain(&current))?;
+        }
+    }
+
+    if verify {
+        run_hook("post-submit", &branches)?;
+    }
+    Ok(())
+}
+
+/// Edit the description of each of `branches`, starting from the saved one,
+/// else the PR's, else the defaults for a new PR. Open PRs are updated right
+/// away; the rest keep theirs saved for the PR `submit` opens.
+fn edit_descriptions(forge: &dyn Forge, branches: &[String]) -> StackResult<()> {
+    let prs = forge.review_status();
+    let mut updated = false;
+    for branch in branches {
+        let open = prs.get(branch).is_some_and(|pr| pr.state == "OPEN");
+        let parent = get_parent(branch).unwrap_or_else(trunk);
+        let (title, body) = match saved_description(branch) {
+            Some(saved) => saved,
+            None if open => forge.pr_description(branch)?,
+            None => pr_defaults(branch, &parent),
+        };
+        let context = if open {
+            format!("Editing PR for {}", branch)
+        } else {
+            format!("New PR: {} -> {}", branch, parent)
+        };
+        let (title, body) = edit_pr_message(&title, &body, &context)?;
+        save_description(branch, &title, &body)?;
+        if open {
+            forge.set_pr_description(branch, &title, &body)?;
+            clear_description(branch);
+            info!("Updated PR description for {}", branch);
+            updated = true;
+        }
+    }
+    if updated {
+        invalidate_pr_cache();
+    }
+    Ok(())
+}
+
+/// Give each commit of `branch` its generated branch, first adding the
+/// `Stack-Id`s that tell commits apart across amends and reorders (and
+/// restacking whatever sits on `branch` if that rewrote it). Returns the
+/// generated branches bottom-up, and those whose commits are gone.
+fn commit_branches(branch: &str) -> StackResult<(Vec<String>, Vec<String>)> {
+    ensure_unprotected(branch, "submit --per-commit")?;
+    require_parent(branch)?;
+    ensure_clean_worktree("submitting per commit")?;
+
+    if ensure_stack_ids(branch)? {
+        info!(
+            "Added {} trailers to the commits on {}",
+            STACK_ID_TRAILER, branch
+        );
+        RestackPlan::above(&Stack::load()?, branch, &HashSet::new())?.execute(branch)?;
+        git(&["checkout", "--quiet", branch])?;
+    }
+    let (branches, stale) = sync_commit_branches(branch)?;
+    if branches.is_empty() {
+        return Err(err(&format!("{} has no commits to submit", branch)));
+    }
+    Ok((branches, stale))
+}
+
+/// Close the PRs of commits dropped since the last `submit --per-commit` and
+/// delete their remote branches.
+fn close_stale_prs(forge: &dyn Forge, stale: &[String]) -> StackResult<()> {
+    for branch in stale {
+        if let Err(e) = forge.close_pr(branch) {
+            eprintln!("Warning: could not close the PR for {}: {}", branch, e);
+        }
+        let remote = submit_target(branch)?.push_remote;
+        let remote_ref = format!("refs/heads/{}", branch);
+        if try_command("git", &["ls-remote", "--exit-code", &remote, &remote_ref]).is_some() {
+            let _ = git(&["push", "--quiet", &remote, "--delete", branch]);
+        }
+        info!("Closed the PR for {}, whose commit is gone", branch);
+    }
+    Ok(())
+}
+
+/// `--draft` or `--ready`, whichever comes last.
+fn draft_flag(args: &[String]) -> Option<bool> {
+    args.iter().rev().find_map(|a| match a.as_str() {
+        "--draft" => Some(true),
+        "--ready" => Some(false),
+        _ => None,
+    })
+}
+
+/// Mark the current branch's draft PR (or with `--stack`, every draft in the
+/// stack through it) ready for review. Nothing is published until every
+/// branch is pushed as it is locally and its checks have passed, so
+/// reviewers only hear about finished stacks.
+pub fn cmd_publish(args: &[String]) -> StackResult<()> {
+    let current = require_current_branch("publish")?;
+    let branches = if args.iter().any(|a| a == "--stack") {
+        Stack::load()?.path_to_trunk(&current)
+    } else {
+        vec![current]
+    };
+
+    let forge = authenticated_forge()?;
+    let prs = forge.review_status();
+    let mut problems = Vec::new();
+    for branch in &branches {
+        let Some(pr) = prs.get(branch).filter(|pr| pr.state == "OPEN") else {
+            problems.push(format!("{} has no open PR (run `stack submit`)", branch));
+            continue;
+        };
+        let remote = submit_target(branch)?.push_remote;
+        let pushed = try_command(
+            "git",
+            &["ls-remote", &remote, &format!("refs/heads/{}", branch)],
+        )
+        .and_then(|out| out.split_whitespace().next().map(str::to_string));
+        if pushed != rev_parse(branch).ok() {
+            problems.push(format!("{} has changes that aren't pushed", branch));
+        }
+        match pr.ci_status() {
+            Some("failing") => problems.push(format!("{} has failing checks", branch)),
+            Some("pending") => problems.push(format!("{} has checks still running", branch)),
+            _ => {}
+        }
+    }
+    if !problems.is_empty() {
+        return Err(err(&format!(
+            "Not publishing yet:\n  {}",
+            problems.join("\n  ")
+        )));
+    }
+
+    let drafts: Vec<&String> = branches.iter().filter(|b| prs[*b].draft).collect();
+    if drafts.is_empty() {
+        report!("Nothing to publish: no draft PRs.");
+        return Ok(());
+    }
+    for branch in drafts {
+        forge.publish(branch)?;
+        info!("Marked the PR for {} ready for review", branch);
+    }
+    invalidate_pr_cache();
+    Ok(())
+}
+
+/// `stack pr [<branch>] [--web]` shows the PR for a branch (default: the
+/// current one) or opens it in the browser; `stack pr edit` edits the
+/// current branch's title and description.
+pub fn cmd_pr(args: &[String]) -> StackResult<()> {
+    match args.first().map(String::as_str) {
+        Some("edit") => {
+            let branch = require_current_branch("pr edit")?;
+            let forge = authenticated_forge()?;
+            let (title, body) = match saved_description(&branch) {
+                Some(saved) => saved,
+                None => forge.pr_description(&branch)?,
+            };
+            let (title, body) =
+                edit_pr_message(&title, &body, &format!("Editing PR for {}", branch))?;
+            save_description(&branch, &title, &body)?;
+            forge.set_pr_description(&branch, &title, &body)?;
+            clear_description(&branch);
+            invalidate_pr_cache();
+            info!("Updated PR description for {}", branch);
+            Ok(())
+        }
+        _ => show_pr(args),
+    }
+}
+
+fn show_pr(args: &[String]) -> StackResult<()> {
+    let web = args.iter().any(|a| a == "--web" || a == "-w");
+    let branch = match positional_args(args, &[]).as_slice() {
+        [] => require_current_branch("pr")?,
+        [branch] => branch.to_string(),
+        _ => {
+            return Err(StackError::Usage(
+                "Usage: stack pr [<branch>] [--web] | stack pr edit".to_string(),
+            ));
+        }
+    };
+
+    let forge = authenticated_forge()?;
+    let Some(pr) = forge.review_status().remove(&branch) else {
+        return Err(err(&format!(
+            "No PR for {}. Run `stack submit` to open one.",
+            branch
+        )));
+    };
+    if web {
+        return open_url(&pr.url);
+    }
+
+    println!("#{} {}", pr.number, pr.url);
+    println!("State:     {}", pr.state.to_lowercase());
+    if !pr.review.is_empty() {
+        println!("Review:    {}", pr.review.to_lowercase().replace('_', " "));
+    }
+    let reviewers = forge.pr_reviewers(&branch);
+    if !reviewers.is_empty() {
+        println!("Reviewers: {}", reviewers.join(", "));
+    }
+    if let Some(ci) = pr.ci_status() {
+        // Counts per state, in the order they first appear
+        let mut counts: Vec<(String, usize)> = Vec::new();
+        for check in &pr.checks {
+            let state = check.to_lowercase().replace('_', " ");
+            match counts.iter_mut().find(|(s, _)| *s == state) {
+                Some(entry) => entry.1 += 1,
+                None => counts.push((state, 1)),
+            }
+        }
+        let counts: Vec<String> = counts.iter().map(|(s, n)| format!("{} {}", n, s)).collect();
+        println!("Checks:    {} ({})", ci, counts.join(", "));
+    }
+    Ok(())
+}
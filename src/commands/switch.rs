@@ -0,0 +1,123 @@
+use stack_core::config::trunk;
+use stack_core::engine::Stack;
+use stack_core::error::{StackError, StackResult, err};
+use stack_core::forge::authenticated_forge;
+use stack_core::git::{branch_exists, git_passthrough, local_branches, require_current_branch};
+use stack_core::metadata::require_parent;
+use stack_core::recent::recent_branches;
+use stack_core::ui::pick;
+
+pub fn cmd_switch(args: &[String]) -> StackResult<()> {
+    if args.is_empty() {
+        return Err(StackError::Usage(
+            "Usage: stack switch <branch-name|pattern|#pr|->".to_string(),
+        ));
+    }
+    let name = if args[0] == "-" {
+        recent_branches()?
+            .into_iter()
+            .next()
+            .ok_or_else(|| err("No recently visited branch to switch back to"))?
+    } else {
+        resolve_branch(&args[0])?
+    };
+
+    // We use passthrough so users see the nice git output (colors, info)
+    git_passthrough(&["checkout", &name])
+}
+
+/// Pick one of the stack branches visited lately and check it out, most
+/// recent first.
+pub fn cmd_recent() -> StackResult<()> {
+    let recent = recent_branches()?;
+    if recent.is_empty() {
+        println!("No other stack branches visited yet.");
+        return Ok(());
+    }
+    let branch = pick("Recently visited:", &recent)?;
+    git_passthrough(&["checkout", &branch])
+}
+
+/// Check out the tip of the current stack, asking which one when the stack
+/// branches above the current branch.
+pub fn cmd_top() -> StackResult<()> {
+    let current = require_current_branch("top")?;
+    let stack = Stack::load()?;
+    let tips: Vec<String> = stack
+        .descendants(&current)
+        .into_iter()
+        .filter(|b| stack.children(b).is_empty())
+        .collect();
+
+    let tip = match tips.as_slice() {
+        [] => {
+            println!("Already at the top of the stack.");
+            return Ok(());
+        }
+        [only] => only.clone(),
+        _ => pick(&format!("The stack above {} branches:", current), &tips)?,
+    };
+    git_passthrough(&["checkout", &tip])
+}
+
+/// Check out the bottom branch of the current stack, the one just above
+/// trunk. From trunk itself, that is one of the branches stacked on it.
+pub fn cmd_bottom() -> StackResult<()> {
+    let current = require_current_branch("bottom")?;
+    let stack = Stack::load()?;
+
+    let bottom = if current == trunk() {
+        match stack.children(&current) {
+            [] => return Err(err(&format!("Nothing is stacked on {}", current))),
+            [only] => only.clone(),
+            children => pick("Several stacks start on trunk:", children)?,
+        }
+    } else {
+        require_parent(&current)?;
+        stack.path_to_trunk(&current).remove(0)
+    };
+    if bottom == current {
+        println!("Already at the bottom of the stack.");
+        return Ok(());
+    }
+    git_passthrough(&["checkout", &bottom])
+}
+
+/// Turn a `switch` argument into a branch name: an exact branch, `#123` for
+/// a PR's head branch, or a case-insensitive substring (then subsequence)
+/// match, prompting when several branches match.
+fn resolve_branch(query: &str) -> StackResult<String> {
+    if let Some(number) = query.strip_prefix('#')
+        && let Ok(number) = number.parse()
+    {
+        return authenticated_forge()?.pr_head(number);
+    }
+    if branch_exists(query)? {
+        return Ok(query.to_string());
+    }
+
+    let needle = query.to_lowercase();
+    let branches = local_branches()?;
+    let mut matches: Vec<String> = branches
+        .iter()
+        .filter(|b| b.to_lowercase().contains(&needle))
+        .cloned()
+        .collect();
+    if matches.is_empty() {
+        matches = branches
+            .into_iter()
+            .filter(|b| is_subsequence(&needle, &b.to_lowercase()))
+            .collect();
+    }
+
+    match matches.len() {
+        0 => Err(err(&format!("No branch matches '{}'", query))),
+        1 => Ok(matches.remove(0)),
+        _ => pick(&format!("Multiple branches match '{}':", query), &matches),
+    }
+}
+
+fn is_subsequence(needle: &str, haystack: &str) -> bool {
+    let mut chars = haystack.chars();
+    needle.chars().all(|c| chars.any(|h| h == c))
+}
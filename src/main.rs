@@ -1,393 +1,254 @@
-use std::collections::HashMap;
-use std::env;
-use std::error::Error;
-use std::fmt;
-use std::io::{self, Write};
-use std::process::{Command, Stdio};
-use std::str;
-
-// --- Custom Error Type ---
-#[derive(Debug)]
-struct StackError(String);
-
-impl fmt::Display for StackError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
-
-impl Error for StackError {}
-
-fn err(msg: &str) -> Box<dyn Error> {
-    Box::new(StackError(msg.to_string()))
-}
+mod args;
+mod commands;
 
-type StackResult<T> = Result<T, Box<dyn Error>>;
-
-fn prompt(message: &str) -> StackResult<String> {
-    print!("{}", message);
-    io::stdout().flush()?;
-    let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
-    Ok(input.trim().to_string())
-}
+use std::env;
 
-fn prompt_multiline(message: &str) -> StackResult<String> {
-    println!("{} (enter empty line to finish):", message);
-    let mut lines = Vec::new();
-    loop {
-        let mut line = String::new();
-        io::stdin().read_line(&mut line)?;
-        let trimmed = line.trim_end_matches('\n').to_string();
-        if trimmed.is_empty() {
-            break;
+use crate::commands::config::{cmd_config, cmd_fix, cmd_onboard};
+use crate::commands::create::{cmd_clone_stack, cmd_insert, cmd_new};
+use crate::commands::edit::{cmd_absorb, cmd_amend, cmd_autosquash, cmd_squash};
+use crate::commands::foreach::{cmd_foreach, cmd_test};
+use crate::commands::land::cmd_land;
+use crate::commands::log::{cmd_diff, cmd_log, cmd_stats, cmd_status};
+use crate::commands::note::cmd_note;
+use crate::commands::prune::{cmd_prune, cmd_tidy};
+use crate::commands::rename::cmd_rename;
+use crate::commands::restack::{
+    cmd_continue, cmd_freeze, cmd_move, cmd_reorder, cmd_reorder_children, cmd_restack,
+    cmd_unfreeze, guard_operation,
+};
+use crate::commands::serve::cmd_serve;
+use crate::commands::snapshot::{cmd_restore, cmd_snapshot, cmd_snapshots};
+use crate::commands::submit::{cmd_pr, cmd_publish, cmd_submit};
+use crate::commands::switch::{cmd_bottom, cmd_recent, cmd_switch, cmd_top};
+use stack_core::alias::{Resolved, resolve, run_resolved};
+use stack_core::error::{StackError, StackResult, err};
+use stack_core::forge::get_forge;
+use stack_core::git::{CheckoutGuard, offline, set_offline};
+use stack_core::lock::{Lock, force_unlock, is_read_only};
+use stack_core::metadata::import_meta;
+use stack_core::pr::pr_cache_changed;
+use stack_core::recent::record_visit;
+use stack_core::ui::{Verbosity, set_non_interactive, set_verbosity};
+
+/// What is left once `global_flags` has applied the options before the
+/// command.
+struct Globals<'a> {
+    /// The command and its arguments.
+    args: &'a [String],
+    /// `--force-unlock`: clear a leftover lock before running.
+    unlock: bool,
+}
+
+/// Apply the options that go before the command, `-C <dir>`, `-q`/`--quiet`,
+/// `-v`/`--verbose`, `-y`/`--yes` and `--offline`. `--force-unlock` is only recorded: it
+/// applies to the repository that `-C` ends up in.
+fn global_flags(mut args: &[String]) -> StackResult<Globals<'_>> {
+    let mut unlock = false;
+    while let Some(flag) = args.first() {
+        match flag.as_str() {
+            "--force-unlock" => unlock = true,
+            "-q" | "--quiet" => set_verbosity(Verbosity::Quiet),
+            "-v" | "--verbose" => set_verbosity(Verbosity::Verbose),
+            "-y" | "--yes" | "--no-interactive" => set_non_interactive(),
+            "--offline" => set_offline(),
+            // Like git, each -C is relative to the one before
+            "-C" => {
+                let dir = args
+                    .get(1)
+                    .ok_or_else(|| StackError::Usage("-C needs a directory".to_string()))?;
+                env::set_current_dir(dir)
+                    .map_err(|e| StackError::Usage(format!("Cannot change to '{}': {}", dir, e)))?;
+                args = &args[1..];
+            }
+            _ => break,
         }
-        lines.push(trimmed);
+        args = &args[1..];
     }
-    Ok(lines.join("\n"))
+    Ok(Globals { args, unlock })
 }
 
-// --- Git Helpers ---
-
-fn run_command(cmd: &str, args: &[&str]) -> StackResult<String> {
-    // println!("> {} {}", cmd, args.join(" ")); // Uncomment for debug
-    let output = Command::new(cmd)
-        .args(args)
-        .stdin(Stdio::inherit())
-        .stderr(Stdio::piped())
-        .stdout(Stdio::piped())
-        .output()?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        eprintln!("{}", stderr);
-        return Err(err(&format!("Command failed: {} {}", cmd, args.join(" "))));
+/// The lock for `command` unless it only reads state.
+fn lock_for(command: &str) -> StackResult<Option<Lock>> {
+    if is_read_only(command) {
+        return Ok(None);
     }
-
-    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
-}
-
-fn git(args: &[&str]) -> StackResult<String> {
-    run_command("git", args)
+    Lock::acquire(command).map(Some)
 }
 
-fn git_passthrough(args: &[&str]) -> StackResult<()> {
-    let status = Command::new("git")
-        .args(args)
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .status()?;
-
-    if !status.success() {
-        return Err(err("Git command failed"));
+/// Refuse the commands that are all about the network when offline.
+/// Others carry on without it, using what was last fetched.
+fn guard_offline(command: &str) -> StackResult<()> {
+    if offline() && matches!(command, "submit" | "land" | "publish" | "fetch-meta") {
+        return Err(err(&format!(
+            "`stack {}` needs the network; drop --offline (or stack.offline) to run it",
+            command
+        )));
     }
     Ok(())
 }
 
-fn get_current_branch() -> StackResult<String> {
-    git(&["branch", "--show-current"])
-}
-
-// --- Logic ---
-
-fn get_child_map() -> StackResult<HashMap<String, Vec<String>>> {
-    let raw = match git(&["config", "--get-regexp", "branch\\..*\\.stack-parent"]) {
-        Ok(out) => out,
-        Err(_) => return Ok(HashMap::new()),
-    };
-
-    let mut map: HashMap<String, Vec<String>> = HashMap::new();
-
-    for line in raw.lines() {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() != 2 {
-            continue;
-        }
-
-        let key = parts[0];
-        let parent = parts[1];
-
-        if let Some(without_prefix) = key.strip_prefix("branch.") {
-            if let Some(child) = without_prefix.strip_suffix(".stack-parent") {
-                map.entry(parent.to_string())
-                    .or_default()
-                    .push(child.to_string());
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Globals { args, unlock } = global_flags(&args).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    });
+    if unlock {
+        match force_unlock() {
+            Ok(true) => eprintln!("Removed the stack lock."),
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(e.exit_code());
             }
         }
+        if args.is_empty() {
+            return;
+        }
     }
-    Ok(map)
-}
-
-fn recursive_rebase(current: &str, child_map: &HashMap<String, Vec<String>>) -> StackResult<()> {
-    let children = match child_map.get(current) {
-        Some(c) => c,
-        None => return Ok(()),
-    };
-
-    for child in children {
-        println!("   -> Rebase {} onto {}", child, current);
-        git(&["checkout", child])?;
-        git(&["rebase", current])?;
-        recursive_rebase(child, child_map)?;
-    }
-    Ok(())
-}
-
-// --- Commands ---
-
-fn cmd_new(args: &[String]) -> StackResult<()> {
-    if args.is_empty() {
-        return Err(err("Usage: stack new <branch-name>"));
-    }
-    let name = &args[0];
-
-    let parent = get_current_branch()?;
-    println!("Creating branch '{}' tracking parent '{}'", name, parent);
-
-    git(&["checkout", "-b", name])?;
-    git(&["config", &format!("branch.{}.stack-parent", name), &parent])?;
-
-    Ok(())
-}
-
-fn cmd_switch(args: &[String]) -> StackResult<()> {
     if args.is_empty() {
-        return Err(err("Usage: stack switch <branch-name>"));
-    }
-    let name = &args[0];
-
-    // We use passthrough so users see the nice git output (colors, info)
-    git_passthrough(&["checkout", name])
-}
-
-fn cmd_submit() -> StackResult<()> {
-    let current = get_current_branch()?;
-    let parent = git(&["config", &format!("branch.{}.stack-parent", current)])
-        .unwrap_or_else(|_| "main".to_string());
-
-    println!("Pushing {}...", current);
-    git(&["push", "origin", &current, "--force-with-lease"])?;
-
-    // Check if PR already exists
-    let pr_exists = run_command("gh", &["pr", "view", &current]).is_ok();
-
-    if pr_exists {
-        run_command("gh", &["pr", "edit", &current, "--base", &parent])?;
-        println!("Updated existing PR base to {}", parent);
-    } else {
-        println!("Creating PR against {}...", parent);
-
-        let title = prompt("PR Title: ")?;
-        let body = prompt_multiline("PR Description")?;
-
-        let mut gh_args = vec![
-            "pr", "create", "--base", &parent, "--head", &current, "--title", &title,
-        ];
-
-        if body.is_empty() {
-            gh_args.extend_from_slice(&["--body", ""]);
-        } else {
-            gh_args.extend_from_slice(&["--body", &body])
-        }
-
-        run_command("gh", &gh_args)?;
-        println!("PR created!");
+        eprintln!(
+            "Usage: stack [-C <dir>] [-q|-v] [-y] [--offline] [--force-unlock] <{}>",
+            COMMANDS.join("|")
+        );
+        std::process::exit(1);
     }
 
-    Ok(())
-}
-
-fn cmd_restack() -> StackResult<()> {
-    let start_branch = get_current_branch()?;
-    let child_map = get_child_map()?;
-
-    println!("Restacking children of {}...", start_branch);
-    recursive_rebase(&start_branch, &child_map)?;
-
-    println!("Done. Returning to {}", start_branch);
-    git(&["checkout", &start_branch])?;
-    Ok(())
-}
-
-fn cmd_amend() -> StackResult<()> {
-    println!("Amending...");
-    git_passthrough(&["commit", "--amend", "--no-edit"])?;
-    cmd_restack()
-}
-
-fn cmd_log() -> StackResult<()> {
-    let current = get_current_branch()?;
-    let child_map = get_child_map()?;
-
-    // Find the root of the stack (walk up parents)
-    let mut root = current.clone();
-    loop {
-        match git(&["config", &format!("branch.{}.stack-parent", root)]) {
-            Ok(parent) => root = parent,
-            Err(_) => break,
+    let args = match resolve(args, is_builtin) {
+        Ok(Resolved::Builtin(args)) => args,
+        // Not locked: these usually run stack commands of their own
+        Ok(resolved) => match run_resolved(resolved) {
+            Ok(code) => std::process::exit(code),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(e.exit_code());
+            }
+        },
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(e.exit_code());
         }
-    }
-
-    // Print the tree starting from root
-    println!();
-    print_tree(&root, &current, &child_map, "", true)?;
-    println!();
-
-    Ok(())
-}
-
-fn print_tree(
-    branch: &str,
-    current: &str,
-    child_map: &HashMap<String, Vec<String>>,
-    prefix: &str,
-    is_last: bool,
-) -> StackResult<()> {
-    let connector = if prefix.is_empty() {
-        ""
-    } else if is_last {
-        "└── "
-    } else {
-        "├── "
     };
-    let marker = if branch == current { " ◀" } else { "" };
-
-    // Get short commit info
-    let commit_info = git(&["log", "-1", "--format=%h %s", branch]).unwrap_or_default();
-
-    println!("{}{}{}{}", prefix, connector, branch, marker);
-    println!("{}    {}", prefix, commit_info);
-
-    if let Some(children) = child_map.get(branch) {
-        let new_prefix = if prefix.is_empty() {
-            "".to_string()
-        } else if is_last {
-            format!("{}    ", prefix)
-        } else {
-            format!("{}│   ", prefix)
-        };
-
-        for (i, child) in children.iter().enumerate() {
-            let child_is_last = i == children.len() - 1;
-            print_tree(child, current, child_map, &new_prefix, child_is_last)?;
+    let command = &args[0];
+    let remaining_args = &args[1..];
+
+    let result = guard_operation(command).and_then(|()| {
+        guard_offline(command)?;
+        // Dropped before exiting, which skips destructors
+        let lock = lock_for(command)?;
+        // Commands that change things get put back on their branch on failure
+        let checkout = lock.as_ref().map(|_| CheckoutGuard::new()).transpose()?;
+        record_visit();
+        let result = dispatch(command, remaining_args);
+        // Leave `log` the PR state this command's changes led to
+        if pr_cache_changed() && !offline() {
+            let _ = get_forge().map(|forge| forge.review_status());
         }
-    }
-
-    Ok(())
-}
-
-fn cmd_land() -> StackResult<()> {
-    let current = get_current_branch()?;
-
-    // Build the stack from current back to main
-    let mut stack = vec![current.clone()];
-    let mut branch = current.clone();
-
-    loop {
-        match git(&["config", &format!("branch.{}.stack-parent", branch)]) {
-            Ok(parent) => {
-                if parent == "main" {
-                    break;
-                }
-                // Only add if branch exists AND hasn't been merged into main yet
-                if branch_exists(&parent)? && !is_merged_into_main(&parent)? {
-                    stack.push(parent.clone());
-                }
-                branch = parent;
-            }
-            Err(_) => break,
+        match checkout {
+            Some(checkout) if result.is_ok() => checkout.disarm(),
+            checkout => drop(checkout),
         }
-    }
-
-    // Reverse so we merge bottom-up (closest to main first)
-    stack.reverse();
-
-    if stack.is_empty() {
-        return Err(err("Nothing to land"));
-    }
-
-    println!("Will land the following branches into main:");
-    for b in &stack {
-        println!("  - {}", b);
-    }
-
-    let confirm = prompt("Proceed? [y/N] ")?;
-    if confirm.to_lowercase() != "y" {
-        println!("Aborted.");
-        return Ok(());
-    }
-
-    // Switch to main and pull latest
-    git(&["checkout", "main"])?;
-    git(&["pull", "origin", "main"])?;
-
-    for branch in &stack {
-        println!("Merging {}...", branch);
-
-        // Merge with squash or regular merge - using squash for clean history
-        git(&["merge", "--squash", branch])?;
-
-        // Get the original commit message
-        let msg = git(&["log", "-1", "--format=%B", branch])?;
-        git(&["commit", "-m", &msg])?;
-
-        // Delete the branch locally and remotely
-        git(&["branch", "-D", branch])?;
-        let _ = git(&["push", "origin", "--delete", branch]); // Ignore if remote doesn't exist
-
-        // Clean up the stack-parent config
-        let _ = git(&[
-            "config",
-            "--unset",
-            &format!("branch.{}.stack-parent", branch),
-        ]);
-    }
-
-    println!("Pushing main...");
-    git(&["push", "origin", "main"])?;
-
-    println!("Done! Landed {} branch(es).", stack.len());
-    Ok(())
-}
-
-fn branch_exists(name: &str) -> StackResult<bool> {
-    Ok(git(&["rev-parse", "--verify", name]).is_ok())
-}
-
-fn is_merged_into_main(branch: &str) -> StackResult<bool> {
-    // Fetch latest main first to be accurate
-    let _ = git(&["fetch", "origin", "main"]);
-
-    // Check if branch is an ancestor of main (i.e., already merged)
-    Ok(git(&["merge-base", "--is-ancestor", branch, "origin/main"]).is_ok())
-}
-
-// --- Main ---
-
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: stack <new|switch|submit|restack|amend|log|land>");
-        std::process::exit(1);
-    }
-
-    let command = &args[1];
-    let remaining_args = &args[2..];
-
-    let result = match command.as_str() {
-        "new" => cmd_new(remaining_args),
-        "switch" => cmd_switch(remaining_args), // Added switch command
-        "submit" => cmd_submit(),
-        "restack" => cmd_restack(),
-        "amend" => cmd_amend(),
-        "log" => cmd_log(),
-        "land" => cmd_land(),
-        _ => Err(err(&format!("Unknown command: {}", command))),
-    };
+        record_visit();
+        result
+    });
 
     if let Err(e) = result {
         eprintln!("Error: {}", e);
-        std::process::exit(1);
+        std::process::exit(e.exit_code());
+    }
+}
+
+/// The built-in commands, as listed in the usage line.
+const COMMANDS: &[&str] = &[
+    "new",
+    "insert",
+    "clone-stack",
+    "switch",
+    "top",
+    "bottom",
+    "recent",
+    "submit",
+    "restack",
+    "amend",
+    "log",
+    "land",
+    "pr",
+    "publish",
+    "status",
+    "stats",
+    "reorder",
+    "reorder-children",
+    "move",
+    "config",
+    "absorb",
+    "squash",
+    "autosquash",
+    "continue",
+    "fetch-meta",
+    "onboard",
+    "fix",
+    "foreach",
+    "test",
+    "diff",
+    "prune",
+    "tidy",
+    "rename",
+    "freeze",
+    "unfreeze",
+    "note",
+    "snapshot",
+    "snapshots",
+    "restore",
+    "serve",
+];
+
+/// Whether `dispatch` handles `command`, which aliases can't override.
+fn is_builtin(command: &str) -> bool {
+    COMMANDS.contains(&command) || matches!(command, "checkout" | "run")
+}
+
+fn dispatch(command: &str, remaining_args: &[String]) -> StackResult<()> {
+    match command {
+        "new" => cmd_new(remaining_args),
+        "insert" => cmd_insert(remaining_args),
+        "clone-stack" => cmd_clone_stack(remaining_args),
+        "switch" | "checkout" => cmd_switch(remaining_args), // Added switch command
+        "top" => cmd_top(),
+        "bottom" => cmd_bottom(),
+        "recent" => cmd_recent(),
+        "submit" => cmd_submit(remaining_args),
+        "restack" => cmd_restack(remaining_args),
+        "move" => cmd_move(remaining_args),
+        "amend" => cmd_amend(remaining_args),
+        "absorb" => cmd_absorb(remaining_args),
+        "squash" => cmd_squash(remaining_args),
+        "autosquash" => cmd_autosquash(),
+        "reorder" => cmd_reorder(),
+        "reorder-children" => cmd_reorder_children(remaining_args),
+        "log" => cmd_log(remaining_args),
+        "land" => cmd_land(remaining_args),
+        "pr" => cmd_pr(remaining_args),
+        "publish" => cmd_publish(remaining_args),
+        "status" => cmd_status(remaining_args),
+        "stats" => cmd_stats(remaining_args),
+        "config" => cmd_config(remaining_args),
+        "continue" => cmd_continue(),
+        "fetch-meta" => import_meta(false),
+        "onboard" => cmd_onboard(remaining_args),
+        "fix" => cmd_fix(remaining_args),
+        "foreach" => cmd_foreach(remaining_args),
+        "test" | "run" => cmd_test(remaining_args),
+        "diff" => cmd_diff(remaining_args),
+        "prune" => cmd_prune(remaining_args),
+        "tidy" => cmd_tidy(remaining_args),
+        "rename" => cmd_rename(remaining_args),
+        "freeze" => cmd_freeze(remaining_args),
+        "unfreeze" => cmd_unfreeze(remaining_args),
+        "note" => cmd_note(remaining_args),
+        "snapshot" => cmd_snapshot(remaining_args),
+        "snapshots" => cmd_snapshots(),
+        "restore" => cmd_restore(remaining_args),
+        "serve" => cmd_serve(),
+        _ => Err(StackError::Usage(format!("Unknown command: {}", command))),
     }
 }
@@ -0,0 +1,80 @@
+//! Staged hunks for `stack absorb`.
+
+use std::path::PathBuf;
+
+use git2::Repository;
+
+use crate::error::StackResult;
+
+/// A staged hunk: the lines it replaces in HEAD and what replaces them.
+pub struct StagedHunk {
+    pub path: PathBuf,
+    /// Whether the file exists on both sides. Only edits to existing files
+    /// can be absorbed.
+    pub modified: bool,
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_content: Vec<u8>,
+}
+
+/// Hunks of `staged` against `base`, without context so each change is its
+/// own hunk.
+pub fn staged_hunks(
+    repo: &Repository,
+    base: &git2::Tree,
+    staged: &git2::Tree,
+) -> StackResult<Vec<StagedHunk>> {
+    let mut opts = git2::DiffOptions::new();
+    opts.context_lines(0);
+    let diff = repo.diff_tree_to_tree(Some(base), Some(staged), Some(&mut opts))?;
+
+    let mut hunks = Vec::new();
+    for (i, delta) in diff.deltas().enumerate() {
+        let Some(patch) = git2::Patch::from_diff(&diff, i)? else {
+            continue;
+        };
+        let path = delta
+            .old_file()
+            .path()
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        for h in 0..patch.num_hunks() {
+            let (hunk, line_count) = patch.hunk(h)?;
+            let mut new_content = Vec::new();
+            for l in 0..line_count {
+                let line = patch.line_in_hunk(h, l)?;
+                if line.origin() == '+' {
+                    new_content.extend_from_slice(line.content());
+                }
+            }
+            hunks.push(StagedHunk {
+                path: path.clone(),
+                modified: delta.status() == git2::Delta::Modified,
+                old_start: hunk.old_start(),
+                old_lines: hunk.old_lines(),
+                new_content,
+            });
+        }
+    }
+    Ok(hunks)
+}
+
+/// `base` with `hunks` applied. They all come from one zero-context diff of
+/// `base`, so splicing from the bottom up keeps earlier line numbers valid.
+pub fn splice_hunks(base: &[u8], hunks: &[&StagedHunk]) -> Vec<u8> {
+    let mut lines: Vec<&[u8]> = base.split_inclusive(|&b| b == b'\n').collect();
+    let mut sorted = hunks.to_vec();
+    sorted.sort_by_key(|h| std::cmp::Reverse(h.old_start));
+    for hunk in sorted {
+        // A pure addition's `old_start` is the line it goes after
+        let start = if hunk.old_lines == 0 {
+            hunk.old_start as usize
+        } else {
+            hunk.old_start as usize - 1
+        };
+        let end = start + hunk.old_lines as usize;
+        let replacement: Vec<&[u8]> = hunk.new_content.split_inclusive(|&b| b == b'\n').collect();
+        lines.splice(start..end, replacement);
+    }
+    lines.concat()
+}
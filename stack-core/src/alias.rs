@@ -0,0 +1,141 @@
+//! Commands beyond the built-in ones, the way git does it: aliases from
+//! `stack.alias.<name>` (`ss = "submit --stack"`, or `!` and a shell snippet
+//! for anything else) and `stack-<name>` programs found on PATH. Neither can
+//! replace a built-in command.
+
+use std::env;
+use std::path::PathBuf;
+
+use crate::config::setting;
+use crate::error::{StackError, StackResult, err};
+use crate::process::shell_command;
+
+/// How deep aliases may refer to other aliases before it counts as a loop.
+const MAX_DEPTH: usize = 16;
+
+/// What a command line turned out to mean.
+#[derive(Debug, PartialEq)]
+pub enum Resolved {
+    /// A built-in command and its arguments, with aliases expanded.
+    Builtin(Vec<String>),
+    /// A `!` alias: the snippet, and the arguments it was given.
+    Shell(String, Vec<String>),
+    /// A `stack-<name>` program and its arguments.
+    External(PathBuf, Vec<String>),
+}
+
+/// Expand aliases in `args` until the command is one `is_builtin` knows, a
+/// shell snippet, or an external program.
+pub fn resolve(args: &[String], is_builtin: impl Fn(&str) -> bool) -> StackResult<Resolved> {
+    let mut args = args.to_vec();
+    let mut seen = Vec::new();
+    loop {
+        let command = args[0].clone();
+        if is_builtin(&command) {
+            return Ok(Resolved::Builtin(args));
+        }
+        let Some(expansion) = setting(&format!("alias.{}", command)) else {
+            if let Some(program) = external_command(&command) {
+                return Ok(Resolved::External(program, args[1..].to_vec()));
+            }
+            return Err(StackError::Usage(format!("Unknown command: {}", command)));
+        };
+        if seen.contains(&command) || seen.len() == MAX_DEPTH {
+            seen.push(command);
+            return Err(err(&format!("Alias loop: {}", seen.join(" -> "))));
+        }
+        seen.push(command.clone());
+
+        if let Some(script) = expansion.strip_prefix('!') {
+            return Ok(Resolved::Shell(script.to_string(), args[1..].to_vec()));
+        }
+        let mut expanded = split_args(&expansion);
+        if expanded.is_empty() {
+            return Err(err(&format!("Alias '{}' is empty", command)));
+        }
+        expanded.extend(args.drain(1..));
+        args = expanded;
+    }
+}
+
+/// Split an alias into words at whitespace, keeping quoted strings whole.
+/// Single quotes take everything literally, double quotes and bare words
+/// take a backslash as an escape.
+pub fn split_args(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), c) => word.push(c),
+            (_, '\\') => word.extend(chars.next()),
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+                continue;
+            }
+            (None, c) => word.push(c),
+        }
+        in_word = true;
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// The `stack-<name>` program on PATH, if there is one.
+pub fn external_command(name: &str) -> Option<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) {
+        return None;
+    }
+    let file = format!("stack-{}{}", name, env::consts::EXE_SUFFIX);
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(&file))
+        .find(|path| is_executable(path))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &std::path::Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &std::path::Path) -> bool {
+    path.is_file()
+}
+
+/// Run a `!` alias or an external program to completion, returning the
+/// exit code to finish with.
+pub fn run_resolved(resolved: Resolved) -> StackResult<i32> {
+    let (mut command, what) = match resolved {
+        Resolved::Builtin(_) => return Err(err("Built-in commands run in-process")),
+        Resolved::Shell(script, args) => {
+            let mut command = shell_command(&script, "stack");
+            command.args(args);
+            (command, script)
+        }
+        Resolved::External(program, args) => {
+            let mut command = std::process::Command::new(&program);
+            command.args(args);
+            (command, program.display().to_string())
+        }
+    };
+    // So the program can call back into the same build of stack
+    if let Ok(exe) = env::current_exe() {
+        command.env("STACK_EXE", exe);
+    }
+    let status = command
+        .status()
+        .map_err(|e| err(&format!("Failed to run {}: {}", what, e)))?;
+    Ok(status.code().unwrap_or(1))
+}
@@ -0,0 +1,102 @@
+//! Uncommitted work kept out of a restack's way. Rebasing checks branches
+//! out, which refuses to run over local changes and can clobber or trip on
+//! untracked files such as build output, so `restack` stashes both first
+//! (`git stash --include-untracked`) and puts them back when it is done,
+//! failed or not. A restack that stops on a conflict leaves them stashed
+//! until `stack continue` finishes it.
+//!
+//! `stack.autostash = false` turns this off.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::setting;
+use crate::error::StackResult;
+use crate::git::{get_current_branch, git, operation_in_progress, stack_dir, worktree_changes};
+use crate::info;
+
+/// Holds the commit of a stash waiting for `stack continue`.
+const AUTOSTASH_FILE: &str = "autostash";
+
+fn autostash_path() -> StackResult<PathBuf> {
+    Ok(stack_dir()?.join(AUTOSTASH_FILE))
+}
+
+/// Run `f` with the worktree's changes and untracked files stashed, then
+/// restore them on the branch `f` started on, unless `f` stopped in the
+/// middle of a rebase.
+pub fn with_autostash<T>(command: &str, f: impl FnOnce() -> StackResult<T>) -> StackResult<T> {
+    let (changed, untracked) = worktree_changes()?;
+    if changed + untracked == 0 || setting("autostash").as_deref() == Some("false") {
+        return f();
+    }
+    if let Some(old) = pending_stash()? {
+        eprintln!(
+            "Warning: an earlier autostash ({}) was never restored; it is still in `git stash list`",
+            short(&old)
+        );
+    }
+
+    let start = get_current_branch()?;
+    info!("Stashing uncommitted changes and untracked files...");
+    git(&[
+        "stash",
+        "push",
+        "--quiet",
+        "--include-untracked",
+        "-m",
+        &format!("stack {} autostash", command),
+    ])?;
+    let stash = git(&["rev-parse", "stash@{0}"])?;
+    fs::write(autostash_path()?, &stash)?;
+
+    let result = f();
+    if operation_in_progress()?.is_some() {
+        eprintln!(
+            "Your uncommitted changes are stashed ({}); `stack continue` puts them back once the rebase is done.",
+            short(&stash)
+        );
+        return result;
+    }
+    if result.is_err() && get_current_branch().is_ok_and(|b| b != start) {
+        let _ = git(&["checkout", "--quiet", &start]);
+    }
+    restore_autostash()?;
+    result
+}
+
+/// The stash a stopped restack left, if any.
+fn pending_stash() -> StackResult<Option<String>> {
+    Ok(fs::read_to_string(autostash_path()?)
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty()))
+}
+
+/// Put back the changes the last restack stashed, if it left any. A stash
+/// that doesn't apply cleanly stays in `git stash list`.
+pub fn restore_autostash() -> StackResult<()> {
+    let Some(stash) = pending_stash()? else {
+        return Ok(());
+    };
+    fs::remove_file(autostash_path()?)?;
+
+    info!("Restoring stashed changes...");
+    if git(&["stash", "apply", "--quiet", "--index", &stash]).is_err() {
+        eprintln!(
+            "Warning: the stashed changes ({}) don't apply cleanly here; they are still in `git stash list`",
+            short(&stash)
+        );
+        return Ok(());
+    }
+    // Drop it by position: `git stash drop` only takes stash@{n}
+    let list = git(&["stash", "list", "--format=%H"])?;
+    if let Some(n) = list.lines().position(|oid| oid == stash) {
+        git(&["stash", "drop", "--quiet", &format!("stash@{{{}}}", n)])?;
+    }
+    Ok(())
+}
+
+fn short(oid: &str) -> &str {
+    &oid[..oid.len().min(10)]
+}
@@ -0,0 +1,208 @@
+//! Settings are layered: per-clone git config (`stack.<key>`) overrides the
+//! repo's `.stack.toml`, shared with the team, which overrides the user's
+//! `~/.config/stack/config.toml`. Keys are the same in every layer; dotted
+//! keys such as `hook.pre-submit` are tables in TOML.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use toml_edit::{DocumentMut, Item, Table, TableLike};
+
+use crate::error::{StackError, StackResult, err};
+use crate::git::{git_config_all, repo_root};
+
+pub const REPO_CONFIG_FILE: &str = ".stack.toml";
+
+pub fn user_config_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+    Some(base.join("stack").join("config.toml"))
+}
+
+/// A config file's contents. Missing files are empty; broken ones are
+/// reported and skipped so one bad file doesn't stop every command.
+pub fn read_toml(path: &Path) -> DocumentMut {
+    let Ok(text) = fs::read_to_string(path) else {
+        return DocumentMut::new();
+    };
+    text.parse().unwrap_or_else(|e| {
+        eprintln!("Warning: ignoring {}: {}", path.display(), e);
+        DocumentMut::new()
+    })
+}
+
+/// Values of a dotted `key` in `doc`. Arrays give one value per element.
+pub fn toml_values(doc: &DocumentMut, key: &str) -> Vec<String> {
+    let mut item = doc.as_item();
+    for part in key.split('.') {
+        match item.as_table_like().and_then(|t| t.get(part)) {
+            Some(next) => item = next,
+            None => return Vec::new(),
+        }
+    }
+
+    let scalar = |v: &toml_edit::Value| match v.as_str() {
+        Some(s) => s.to_string(),
+        None => v.to_string().trim().to_string(),
+    };
+    match item.as_value() {
+        Some(toml_edit::Value::Array(values)) => values.iter().map(scalar).collect(),
+        Some(value) => vec![scalar(value)],
+        None => Vec::new(),
+    }
+}
+
+/// Every value of `key` from the highest-precedence layer that sets it.
+pub fn setting_all(key: &str) -> Vec<String> {
+    let values = git_config_all(&format!("stack.{}", key));
+    if !values.is_empty() {
+        return values;
+    }
+
+    let repo_file = repo_root().ok().map(|r| r.join(REPO_CONFIG_FILE));
+    for path in [repo_file, user_config_path()].into_iter().flatten() {
+        let values = toml_values(&read_toml(&path), key);
+        if !values.is_empty() {
+            return values;
+        }
+    }
+    Vec::new()
+}
+
+/// Single-valued setting. As in git config, the last value wins.
+pub fn setting(key: &str) -> Option<String> {
+    setting_all(key).pop().filter(|v| !v.is_empty())
+}
+
+/// The branch stacks are based on and land into (`trunk`, default `main`).
+pub fn trunk() -> String {
+    setting("trunk").unwrap_or_else(|| "main".to_string())
+}
+
+/// Whether stack commands must leave `branch` as it is: trunk always, plus
+/// anything matching a `protected` pattern, where `*` matches any run of
+/// characters (`release/*`).
+pub fn is_protected(branch: &str) -> bool {
+    branch == trunk()
+        || setting_all("protected")
+            .iter()
+            .any(|pattern| glob_match(pattern, branch))
+}
+
+/// Refuse to let `stack <command>` rewrite a protected `branch`.
+pub fn ensure_unprotected(branch: &str, command: &str) -> StackResult<()> {
+    if is_protected(branch) {
+        return Err(err(&format!(
+            "{} is protected, so `stack {}` won't rewrite it. Create a branch for your change with `stack new`.",
+            branch, command
+        )));
+    }
+    Ok(())
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*`: the whole pattern had to match
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// How `land` brings branches into trunk (`land-strategy`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LandStrategy {
+    /// One commit per branch, with the branch's first commit message.
+    Squash,
+    /// A merge commit per branch.
+    Merge,
+    /// The branch's commits as they are; trunk must fast-forward to them.
+    Rebase,
+}
+
+impl LandStrategy {
+    pub fn name(self) -> &'static str {
+        match self {
+            LandStrategy::Squash => "squash",
+            LandStrategy::Merge => "merge",
+            LandStrategy::Rebase => "rebase",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "squash" => Some(LandStrategy::Squash),
+            "merge" => Some(LandStrategy::Merge),
+            "rebase" => Some(LandStrategy::Rebase),
+            _ => None,
+        }
+    }
+}
+
+pub fn land_strategy() -> StackResult<LandStrategy> {
+    match setting("land-strategy") {
+        None => Ok(LandStrategy::Squash),
+        Some(name) => LandStrategy::from_name(&name).ok_or_else(|| {
+            StackError::Metadata(format!(
+                "Unknown land-strategy '{}' (expected squash, merge or rebase)",
+                name
+            ))
+        }),
+    }
+}
+
+/// Store `key` in the TOML file at `path`, keeping the rest of the file as it
+/// was. Several values are written as an array.
+pub fn write_toml_setting(path: &Path, key: &str, values: &[String]) -> StackResult<()> {
+    let mut doc: DocumentMut = match fs::read_to_string(path) {
+        Ok(text) => text.parse()?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => DocumentMut::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    let parts: Vec<&str> = key.split('.').collect();
+    let (last, tables) = parts.split_last().ok_or_else(|| err("Empty config key"))?;
+
+    let mut table: &mut dyn TableLike = doc.as_table_mut();
+    for part in tables {
+        let mut implicit = Table::new();
+        implicit.set_implicit(true);
+        table = table
+            .entry(part)
+            .or_insert(Item::Table(implicit))
+            .as_table_like_mut()
+            .ok_or_else(|| {
+                StackError::Metadata(format!("{} is not a table in {}", part, path.display()))
+            })?;
+    }
+
+    let value = match values {
+        [one] => toml_edit::value(one.as_str()),
+        many => toml_edit::value(
+            many.iter()
+                .map(String::as_str)
+                .collect::<toml_edit::Array>(),
+        ),
+    };
+    table.insert(last, value);
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, doc.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,44 @@
+//! PR titles and descriptions kept until the PR they were written for
+//! exists, so a `submit` that fails (network, auth) doesn't lose what was
+//! typed. Each lives in `.git/stack/drafts/<branch>`, `git commit` style:
+//! the title, a blank line, then the description.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::error::StackResult;
+use crate::git::stack_dir;
+use crate::process::normalize_newlines;
+
+fn draft_path(branch: &str) -> StackResult<PathBuf> {
+    Ok(stack_dir()?.join("drafts").join(branch))
+}
+
+/// The title and description saved for `branch`'s PR, if any.
+pub fn saved_description(branch: &str) -> Option<(String, String)> {
+    let text = fs::read_to_string(draft_path(branch).ok()?).ok()?;
+    let text = normalize_newlines(&text);
+    let (title, body) = text.split_once('\n').unwrap_or((&text, ""));
+    let title = title.trim();
+    if title.is_empty() {
+        return None;
+    }
+    Some((title.to_string(), body.trim().to_string()))
+}
+
+pub fn save_description(branch: &str, title: &str, body: &str) -> StackResult<()> {
+    let path = draft_path(branch)?;
+    // Branch names may have slashes in them
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, format!("{}\n\n{}\n", title, body))?;
+    Ok(())
+}
+
+/// Forget `branch`'s saved description, once its PR has it.
+pub fn clear_description(branch: &str) {
+    if let Ok(path) = draft_path(branch) {
+        let _ = fs::remove_file(path);
+    }
+}
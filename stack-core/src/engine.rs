@@ -0,0 +1,819 @@
+//! The stack model and the restack engine that keeps every branch on top of
+//! its parent.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use git2::Oid;
+use serde_json::json;
+
+use crate::config::{is_protected, trunk};
+use crate::error::{StackError, StackResult};
+use crate::events::emit;
+use crate::forge::get_forge;
+use crate::git::{
+    BranchHead, branch_exists, branch_heads, get_remote, git, git_config_bool, git_streamed,
+    git_supports_update_refs, is_ancestor, open_repo, operation_in_progress, other_worktrees,
+    rev_parse, set_config, trial_merge, worktree_changes, worktree_is_dirty,
+};
+use crate::info;
+use crate::metadata::{Branch, get_base, is_frozen, set_base};
+use crate::restack_progress::RestackProgress;
+use crate::ui::Spinner;
+
+/// Every branch with a recorded parent, as trees rooted at trunk (and at any
+/// parent that isn't stacked itself), plus the tip of every local branch.
+pub struct Stack {
+    trunk: String,
+    branches: HashMap<String, Branch>,
+    children: HashMap<String, Vec<String>>,
+    heads: HashMap<String, BranchHead>,
+}
+
+impl Stack {
+    /// Read every `branch.<name>.stack-parent`, `stack-base`, `stack-frozen`,
+    /// `stack-order` and `stack-note` in one pass over the config, and every
+    /// branch tip with one `for-each-ref`. Siblings are kept in `stack-order`
+    /// order, then by name.
+    pub fn load() -> StackResult<Self> {
+        let config = open_repo()?.config()?;
+        let mut branches: HashMap<String, Branch> = HashMap::new();
+        let mut children: HashMap<String, Vec<String>> = HashMap::new();
+        let mut generated = HashSet::new();
+
+        let mut entries = config.entries(Some(
+            "branch\\..*\\.stack-(parent|base|frozen|order|note|source)",
+        ))?;
+        while let Some(entry) = entries.next() {
+            let entry = entry?;
+            let (Ok(key), Ok(value)) = (entry.name(), entry.value()) else {
+                continue;
+            };
+            let Some(key) = key.strip_prefix("branch.") else {
+                continue;
+            };
+
+            if let Some(child) = key.strip_suffix(".stack-parent") {
+                children
+                    .entry(value.to_string())
+                    .or_default()
+                    .push(child.to_string());
+                branches
+                    .entry(child.to_string())
+                    .or_insert_with(|| Branch::new(child))
+                    .parent = Some(value.to_string());
+            } else if let Some(branch) = key.strip_suffix(".stack-base") {
+                branches
+                    .entry(branch.to_string())
+                    .or_insert_with(|| Branch::new(branch))
+                    .base = Some(value.to_string());
+            } else if let Some(branch) = key.strip_suffix(".stack-frozen") {
+                branches
+                    .entry(branch.to_string())
+                    .or_insert_with(|| Branch::new(branch))
+                    .frozen = value == "true";
+            } else if let Some(branch) = key.strip_suffix(".stack-order") {
+                branches
+                    .entry(branch.to_string())
+                    .or_insert_with(|| Branch::new(branch))
+                    .order = value.parse().ok();
+            } else if let Some(branch) = key.strip_suffix(".stack-note") {
+                branches
+                    .entry(branch.to_string())
+                    .or_insert_with(|| Branch::new(branch))
+                    .note = Some(value.to_string());
+            } else if let Some(branch) = key.strip_suffix(".stack-source") {
+                generated.insert(branch.to_string());
+            }
+        }
+        // A base without a parent is left over from a branch that was unstacked
+        branches.retain(|_, b| b.parent.is_some());
+        // `submit --per-commit` branches are managed from their source branch
+        branches.retain(|name, _| !generated.contains(name));
+        children.retain(|parent, _| !generated.contains(parent));
+        for names in children.values_mut() {
+            names.retain(|name| !generated.contains(name));
+            // Branches from before orders were recorded go first
+            names.sort_by_cached_key(|name| {
+                let order = branches.get(name).and_then(|b| b.order);
+                (order.unwrap_or(0), name.clone())
+            });
+        }
+
+        Ok(Stack {
+            trunk: trunk(),
+            branches,
+            children,
+            heads: branch_heads()?,
+        })
+    }
+
+    pub fn trunk(&self) -> &str {
+        &self.trunk
+    }
+
+    /// A stacked branch, or `None` for trunk and branches outside any stack.
+    pub fn branch(&self, name: &str) -> Option<&Branch> {
+        self.branches.get(name)
+    }
+
+    pub fn parent(&self, name: &str) -> Option<&str> {
+        self.branch(name).and_then(|b| b.parent.as_deref())
+    }
+
+    /// Tip of local branch `name`, as of loading.
+    pub fn head(&self, name: &str) -> Option<&BranchHead> {
+        self.heads.get(name)
+    }
+
+    /// Whether local branch `name` existed when the stack was loaded.
+    pub fn exists(&self, name: &str) -> bool {
+        self.heads.contains_key(name)
+    }
+
+    /// Every stacked branch, in no particular order.
+    pub fn branches(&self) -> impl Iterator<Item = &Branch> {
+        self.branches.values()
+    }
+
+    /// Branches stacked directly on `name`.
+    pub fn children(&self, name: &str) -> &[String] {
+        self.children.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// Branches from the bottom of the stack (just above trunk) up to `branch`.
+    /// Stops short of going round a `stack-parent` loop.
+    pub fn path_to_trunk(&self, branch: &str) -> Vec<String> {
+        let mut path = vec![branch.to_string()];
+        let mut current = branch;
+        while let Some(parent) = self.parent(current) {
+            if parent == self.trunk || path.iter().any(|b| b == parent) {
+                break;
+            }
+            path.push(parent.to_string());
+            current = parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// Everything stacked above `branch`, parents before children.
+    pub fn descendants(&self, branch: &str) -> Vec<String> {
+        let mut out = Vec::new();
+        self.add_descendants(branch, &mut out);
+        out
+    }
+
+    fn add_descendants(&self, branch: &str, out: &mut Vec<String>) {
+        for child in self.children(branch) {
+            // A loop comes back round to a branch already listed
+            if child == branch || out.contains(child) {
+                continue;
+            }
+            out.push(child.clone());
+            self.add_descendants(child, out);
+        }
+    }
+
+    /// The stack through `branch` as one line: its ancestors above trunk, then
+    /// descendants for as long as each has exactly one child.
+    pub fn linear_chain(&self, branch: &str) -> Vec<String> {
+        let mut chain = self.path_to_trunk(branch);
+        let mut tip = branch;
+        while let [only] = self.children(tip) {
+            if chain.contains(only) {
+                break;
+            }
+            chain.push(only.clone());
+            tip = only;
+        }
+        chain
+    }
+
+    /// Every branch that has stacked children but no parent of its own, with
+    /// trunk first and the rest sorted by name.
+    pub fn roots(&self) -> Vec<String> {
+        let mut roots: Vec<String> = self
+            .children
+            .keys()
+            .filter(|b| **b != self.trunk && self.parent(b).is_none())
+            .cloned()
+            .collect();
+        roots.sort();
+        roots.insert(0, self.trunk.clone());
+        roots
+    }
+
+    /// The likeliest parent of `branch`, which has none recorded (say it was
+    /// created outside stack): of trunk, the stacked branches and the roots
+    /// not above it, the one its merge base with is closest to its tip.
+    /// Ties go to a candidate whose tip is that merge base, then to trunk.
+    pub fn infer_parent(&self, branch: &str) -> Option<String> {
+        let repo = open_repo().ok()?;
+        let tip = Oid::from_str(&self.head(branch)?.oid).ok()?;
+        let above = self.descendants(branch);
+        let mut candidates: Vec<&str> = self
+            .branches
+            .keys()
+            .chain(self.children.keys())
+            .map(String::as_str)
+            .filter(|c| *c != branch && *c != self.trunk && !above.iter().any(|a| a == c))
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        candidates.insert(0, &self.trunk);
+
+        let mut best: Option<((usize, bool), &str)> = None;
+        for candidate in candidates {
+            let Some(oid) = self
+                .head(candidate)
+                .and_then(|h| Oid::from_str(&h.oid).ok())
+            else {
+                continue;
+            };
+            let Ok(base) = repo.merge_base(tip, oid) else {
+                continue;
+            };
+            // A branch that contains all of this one sits above it, not below
+            if base == tip && candidate != self.trunk {
+                continue;
+            }
+            let Ok((ahead, _)) = repo.graph_ahead_behind(tip, base) else {
+                continue;
+            };
+            let rank = (ahead, base != oid);
+            if best.is_none_or(|(best, _)| rank < best) {
+                best = Some((rank, candidate));
+            }
+        }
+        best.map(|(_, parent)| parent.to_string())
+    }
+
+    /// Treat `branch` as stacked on `parent` from here on, without recording
+    /// it: for showing a parent `infer_parent` came up with.
+    pub fn assume_parent(&mut self, branch: &str, parent: &str) {
+        self.branches
+            .entry(branch.to_string())
+            .or_insert_with(|| Branch::new(branch))
+            .parent = Some(parent.to_string());
+        let siblings = self.children.entry(parent.to_string()).or_default();
+        if !siblings.iter().any(|b| b == branch) {
+            siblings.push(branch.to_string());
+        }
+    }
+
+    /// The first `stack-parent` loop found, as the branches around it:
+    /// `[a, b, a]` when `a` is stacked on `b` and `b` on `a`.
+    pub fn parent_cycle(&self) -> Option<Vec<String>> {
+        let mut names: Vec<&String> = self.branches.keys().collect();
+        names.sort();
+        let mut acyclic: HashSet<&str> = HashSet::new();
+        for name in names {
+            let mut path = vec![name.as_str()];
+            let mut current = name.as_str();
+            while let Some(parent) = self.parent(current) {
+                if let Some(i) = path.iter().position(|b| *b == parent) {
+                    let mut cycle: Vec<String> = path[i..].iter().map(|b| b.to_string()).collect();
+                    cycle.push(parent.to_string());
+                    return Some(cycle);
+                }
+                if acyclic.contains(parent) || !self.branches.contains_key(parent) {
+                    break;
+                }
+                path.push(parent);
+                current = parent;
+            }
+            acyclic.extend(path);
+        }
+        None
+    }
+
+    /// Fail, naming the edge that closes it, if some branches are stacked on
+    /// each other in a loop, which no command can walk to trunk.
+    pub fn check_cycles(&self) -> StackResult<()> {
+        let Some(cycle) = self.parent_cycle() else {
+            return Ok(());
+        };
+        let (child, parent) = (&cycle[cycle.len() - 2], &cycle[cycle.len() - 1]);
+        Err(StackError::Metadata(format!(
+            "Branches are stacked on each other in a loop: {}. {} sits on {}, which closes it; point it at its real parent with `git config branch.{}.stack-parent <parent>` (or `stack fix --from-prs` to take parents from the PRs).",
+            cycle.join(" -> "),
+            child,
+            parent,
+            child
+        )))
+    }
+
+    /// `branch` and the descendants that can move with it in one rebase: each
+    /// the only child of the one before and already sitting on its tip.
+    fn linear_run(&self, branch: &str) -> StackResult<Vec<String>> {
+        let mut chain = vec![branch.to_string()];
+        let mut tip = branch;
+        while let [only] = self.children(tip) {
+            if chain.contains(only) {
+                break;
+            }
+            let base = self.branch(only).and_then(|b| b.base.as_deref());
+            let tip_oid = match self.head(tip) {
+                Some(head) => head.oid.clone(),
+                None => rev_parse(tip)?,
+            };
+            if base != Some(tip_oid.as_str()) {
+                break;
+            }
+            chain.push(only.clone());
+            tip = only;
+        }
+        Ok(chain)
+    }
+}
+
+/// One rebase in a `RestackPlan`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RestackStep {
+    /// Rebase `branch` onto `parent`, or onto trunk if `parent` has landed.
+    Branch { branch: String, parent: String },
+    /// Rebase a linear run of branches onto `parent` with one
+    /// `rebase --update-refs` of the last, which carries the others along.
+    Chain {
+        parent: String,
+        branches: Vec<String>,
+    },
+}
+
+/// The rebases that put branches back on top of their parents, worked out
+/// from the stack before anything moves and run parents first.
+pub struct RestackPlan {
+    pub steps: Vec<RestackStep>,
+    merged: HashSet<String>,
+}
+
+impl RestackPlan {
+    /// A plan of `steps` already worked out, such as those a stopped restack
+    /// left.
+    pub fn new(steps: Vec<RestackStep>, merged: HashSet<String>) -> Self {
+        RestackPlan { steps, merged }
+    }
+
+    /// Restack everything above `branch`. Branches in `merged` have landed,
+    /// so their children go onto trunk instead.
+    pub fn above(stack: &Stack, branch: &str, merged: &HashSet<String>) -> StackResult<Self> {
+        stack.check_cycles()?;
+        let mut plan = RestackPlan {
+            steps: Vec::new(),
+            merged: merged.clone(),
+        };
+        plan.add_children(stack, branch, &other_worktrees()?)?;
+        Ok(plan)
+    }
+
+    /// Restack `branch` onto its parent, then everything above it.
+    pub fn including(stack: &Stack, branch: &str, merged: &HashSet<String>) -> StackResult<Self> {
+        stack.check_cycles()?;
+        let mut plan = RestackPlan {
+            steps: Vec::new(),
+            merged: merged.clone(),
+        };
+        if let Some(parent) = stack.parent(branch) {
+            plan.steps.push(RestackStep::Branch {
+                branch: branch.to_string(),
+                parent: parent.to_string(),
+            });
+        }
+        plan.add_children(stack, branch, &other_worktrees()?)?;
+        Ok(plan)
+    }
+
+    /// `elsewhere` holds the branches checked out in other worktrees, which
+    /// `rebase --update-refs` won't move.
+    fn add_children(
+        &mut self,
+        stack: &Stack,
+        current: &str,
+        elsewhere: &HashMap<String, PathBuf>,
+    ) -> StackResult<()> {
+        let parent_landed = self.merged.contains(current) || !branch_exists(current)?;
+        for child in stack.children(current) {
+            let chain = stack.linear_run(child)?;
+            if chain.len() > 1
+                && !parent_landed
+                && !chain.iter().any(|b| {
+                    elsewhere.contains_key(b)
+                        || stack.branch(b).is_some_and(|b| b.frozen)
+                        || is_protected(b)
+                })
+                && git_supports_update_refs()
+            {
+                let top = chain[chain.len() - 1].clone();
+                self.steps.push(RestackStep::Chain {
+                    parent: current.to_string(),
+                    branches: chain,
+                });
+                self.add_children(stack, &top, elsewhere)?;
+            } else {
+                self.steps.push(RestackStep::Branch {
+                    branch: child.clone(),
+                    parent: current.to_string(),
+                });
+                self.add_children(stack, child, elsewhere)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Try every rebase in the plan as a trial merge, touching no branch,
+    /// and return each `(branch, onto, conflicting files)` in order. Each
+    /// branch goes onto what its parent's trial came out as, so conflicts
+    /// further up account for the steps below them. Branches the restack
+    /// would skip, or that are already in place, are left out.
+    pub fn preview(&self) -> StackResult<Vec<(String, String, Vec<String>)>> {
+        let mut edges: Vec<(String, String)> = Vec::new();
+        for step in &self.steps {
+            match step {
+                RestackStep::Branch { branch, parent } => {
+                    edges.push((branch.clone(), parent.clone()));
+                }
+                RestackStep::Chain { parent, branches } => {
+                    edges.push((branches[0].clone(), parent.clone()));
+                    for pair in branches.windows(2) {
+                        edges.push((pair[1].clone(), pair[0].clone()));
+                    }
+                }
+            }
+        }
+
+        let trunk = trunk();
+        let mut moved: HashMap<String, String> = HashMap::new();
+        let mut preview = Vec::new();
+        for (branch, parent) in edges {
+            if is_protected(&branch) || is_frozen(&branch) {
+                continue;
+            }
+            let landed = self.merged.contains(&parent) || !branch_exists(&parent)?;
+            let onto = if landed {
+                trunk.clone()
+            } else {
+                parent.clone()
+            };
+            let base = match get_base(&branch).filter(|b| is_ancestor(b, &branch).unwrap_or(false))
+            {
+                Some(base) => rev_parse(&base)?,
+                None if !landed => rev_parse(&parent)?,
+                // The restack itself would stop and say so
+                None => continue,
+            };
+            let onto_tip = match moved.get(&onto) {
+                Some(tip) => tip.clone(),
+                None => rev_parse(&onto)?,
+            };
+            if onto_tip == base {
+                continue;
+            }
+
+            let merge = trial_merge(&base, &onto_tip, &branch)?;
+            let tip = git(&[
+                "commit-tree",
+                &merge.tree,
+                "-p",
+                &onto_tip,
+                "-m",
+                "restack preview",
+            ])?;
+            moved.insert(branch.clone(), tip);
+            preview.push((branch, onto, merge.conflicts));
+        }
+        Ok(preview)
+    }
+
+    /// Run the rebases in order, stopping at the first that fails. When a
+    /// rebase stops on conflicts, the steps after it are saved with
+    /// `return_to`, the branch the command finishes on, for `stack continue`.
+    pub fn execute(&self, return_to: &str) -> StackResult<()> {
+        RestackProgress::clear()?;
+        for (i, step) in self.steps.iter().enumerate() {
+            let result = match step {
+                RestackStep::Branch { branch, parent } => {
+                    restack_branch(branch, parent, &self.merged)
+                }
+                RestackStep::Chain { parent, branches } => rebase_chain(parent, branches),
+            };
+            if let Err(e) = result {
+                if let Ok(Some(_)) = operation_in_progress() {
+                    let mut merged: Vec<String> = self.merged.iter().cloned().collect();
+                    merged.sort();
+                    let stopped_on = match step {
+                        RestackStep::Branch { branch, .. } => branch,
+                        RestackStep::Chain { branches, .. } => &branches[branches.len() - 1],
+                    };
+                    RestackProgress {
+                        start_branch: return_to.to_string(),
+                        stopped_on: stopped_on.clone(),
+                        steps: self.steps[i + 1..].to_vec(),
+                        merged,
+                    }
+                    .save()?;
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `git rebase` options `stack restack -- <flags>` passes along to each of
+/// its rebases: ones that change how commits are replayed, not which. Those
+/// that take a value need it attached (`--strategy-option=theirs`, `-Xtheirs`).
+const SAFE_REBASE_FLAGS: &[&str] = &[
+    "--autosquash",
+    "--no-autosquash",
+    "--committer-date-is-author-date",
+    "--reset-author-date",
+    "--ignore-date",
+    "--ignore-whitespace",
+    "--whitespace",
+    "--signoff",
+    "--gpg-sign",
+    "--no-gpg-sign",
+    "--rerere-autoupdate",
+    "--no-rerere-autoupdate",
+    "--no-verify",
+    "--strategy",
+    "--strategy-option",
+];
+
+static REBASE_FLAGS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Pass `flags` along to every rebase a restack runs, once they're checked
+/// against `SAFE_REBASE_FLAGS`.
+pub fn set_rebase_flags(flags: &[String]) -> StackResult<()> {
+    for flag in flags {
+        let name = flag.split('=').next().unwrap_or(flag);
+        let short = ["-S", "-X"].iter().any(|s| flag.starts_with(s)) && flag != "-X";
+        if !short && !SAFE_REBASE_FLAGS.contains(&name) {
+            return Err(StackError::Usage(format!(
+                "`stack restack` doesn't pass {} on to git rebase; it can pass {}, -S and -X",
+                flag,
+                SAFE_REBASE_FLAGS.join(", ")
+            )));
+        }
+    }
+    let _ = REBASE_FLAGS.set(flags.to_vec());
+    Ok(())
+}
+
+/// The `git rebase` command line for `args`, in the worktree at `dir` if
+/// given, with the passed-through flags. Autosquashing, from
+/// `--autosquash` or `rebase.autoSquash`, needs an interactive rebase; its
+/// todo list is taken as it comes.
+fn rebase_command(dir: Option<&Path>, args: &[&str]) -> Vec<String> {
+    let flags = REBASE_FLAGS.get().map(Vec::as_slice).unwrap_or_default();
+    let autosquash = match flags.iter().rfind(|f| f.ends_with("-autosquash")) {
+        Some(flag) => flag == "--autosquash",
+        None => git_config_bool("rebase.autoSquash") == Some(true),
+    };
+
+    let mut command = in_worktree(dir);
+    if autosquash {
+        command.extend(
+            [
+                "-c",
+                "sequence.editor=:",
+                "rebase",
+                "--interactive",
+                "--autosquash",
+            ]
+            .map(String::from),
+        );
+    } else {
+        command.push("rebase".to_string());
+    }
+    command.extend(
+        flags
+            .iter()
+            .filter(|f| !f.ends_with("-autosquash"))
+            .cloned(),
+    );
+    command.extend(args.iter().map(|a| a.to_string()));
+    command
+}
+
+/// Run a restack's rebase of `args`, in the worktree at `dir` if given.
+/// When rerere replays a recorded resolution for every conflict and
+/// `rerere.autoUpdate` stages it, the rebase carries on instead of stopping.
+fn run_rebase(dir: Option<&Path>, args: &[&str]) -> StackResult<()> {
+    let command = rebase_command(dir, args);
+    let mut result = git_streamed(&command.iter().map(String::as_str).collect::<Vec<_>>());
+    let mut resume = in_worktree(dir);
+    resume.extend(["-c", "core.editor=:", "rebase", "--continue"].map(String::from));
+    let mut stopped_at = None;
+    loop {
+        let Err(e) = result else {
+            return Ok(());
+        };
+        let head = rerere_resolved(dir);
+        // Nothing replayed, or the last `--continue` didn't get any further
+        if head.is_none() || head == stopped_at {
+            return Err(e);
+        }
+        stopped_at = head;
+        info!("   -> rerere resolved the conflicts; continuing");
+        result = git_streamed(&resume.iter().map(String::as_str).collect::<Vec<_>>());
+    }
+}
+
+/// `git -C <dir>`, for a command run in another worktree.
+fn in_worktree(dir: Option<&Path>) -> Vec<String> {
+    dir.map(|dir| vec!["-C".to_string(), dir.to_string_lossy().into_owned()])
+        .unwrap_or_default()
+}
+
+/// Where a stopped rebase (in `dir`, if given) is at, if rerere has staged
+/// resolutions for all its conflicts.
+fn rerere_resolved(dir: Option<&Path>) -> Option<git2::Oid> {
+    if git_config_bool("rerere.autoUpdate") != Some(true) {
+        return None;
+    }
+    let repo = match dir {
+        Some(dir) => git2::Repository::open(dir),
+        None => git2::Repository::open_from_env(),
+    }
+    .ok()?;
+    if repo.state() == git2::RepositoryState::Clean || repo.index().ok()?.has_conflicts() {
+        return None;
+    }
+    repo.head().ok()?.target()
+}
+
+/// Classify a failed `git rebase` of `branch` onto `onto`: stopped on
+/// conflicts, refused because of local changes, or some other git error.
+pub fn rebase_error(e: StackError, branch: &str, onto: &str) -> StackError {
+    if let Ok(Some(_)) = operation_in_progress() {
+        conflict(
+            branch,
+            onto,
+            format!(
+                "Rebasing {} onto {} stopped. Resolve the conflicts and run `stack continue`, or `git rebase --abort`.",
+                branch, onto
+            ),
+        )
+    } else if worktree_changes().is_ok_and(|(changed, _)| changed > 0) {
+        StackError::DirtyTree(format!(
+            "Cannot rebase {} with uncommitted changes; commit or stash them and run `stack restack`.",
+            branch
+        ))
+    } else {
+        e
+    }
+}
+
+/// `rebase_error` for a rebase run in the worktree at `dir`.
+fn worktree_rebase_error(e: StackError, branch: &str, onto: &str, dir: &Path) -> StackError {
+    match git2::Repository::open(dir) {
+        Ok(repo) if repo.state() != git2::RepositoryState::Clean => conflict(
+            branch,
+            onto,
+            format!(
+                "Rebasing {} in {} stopped. Resolve the conflicts there and run `stack continue`, or `git rebase --abort`.",
+                branch,
+                dir.display()
+            ),
+        ),
+        _ => e,
+    }
+}
+
+/// A `Conflict` error for a rebase of `branch` onto `onto` that stopped,
+/// announced as an event first.
+fn conflict(branch: &str, onto: &str, message: String) -> StackError {
+    emit(
+        "conflict",
+        json!({ "branch": branch, "onto": onto, "message": message }),
+    );
+    StackError::Conflict(message)
+}
+
+/// Restack `chain` (from `Stack::linear_run`) onto `parent` with a single
+/// `rebase --update-refs` of its top branch, which carries the others along.
+pub fn rebase_chain(parent: &str, chain: &[String]) -> StackResult<()> {
+    let (first, top) = (&chain[0], &chain[chain.len() - 1]);
+    let upstream = get_base(first)
+        .filter(|b| is_ancestor(b, first).unwrap_or(false))
+        .unwrap_or_else(|| parent.to_string());
+
+    info!("   -> Rebase {} onto {}", chain.join(", "), parent);
+    let _spinner = Spinner::start(&format!("Rebasing {}", chain.join(", ")));
+    run_rebase(None, &["--update-refs", "--onto", parent, &upstream, top])
+        .map_err(|e| rebase_error(e, &chain.join(", "), parent))?;
+
+    set_base(first, parent)?;
+    emit("rebased", json!({ "branch": first, "onto": parent }));
+    for pair in chain.windows(2) {
+        set_base(&pair[1], &pair[0])?;
+        emit("rebased", json!({ "branch": pair[1], "onto": pair[0] }));
+    }
+    Ok(())
+}
+
+/// Rebase `branch` onto `parent`, replaying only its own commits: those after
+/// its recorded base, so an amended parent's old commits are dropped.
+///
+/// When the parent has landed (its PR is in `merged`, or `land` deleted it),
+/// its commits are already in trunk under a squash commit. The branch then
+/// goes `--onto` trunk and is reparented there.
+///
+/// A branch checked out in another worktree is rebased there, since git
+/// won't check it out here, and skipped if that worktree has changes.
+/// Frozen and protected branches are skipped too.
+pub fn restack_branch(branch: &str, parent: &str, merged: &HashSet<String>) -> StackResult<()> {
+    if is_protected(branch) {
+        eprintln!("Warning: skipping {}: it is protected", branch);
+        return Ok(());
+    }
+    if is_frozen(branch) {
+        eprintln!(
+            "Warning: skipping {}: it is frozen (`stack unfreeze {}` to restack it)",
+            branch, branch
+        );
+        return Ok(());
+    }
+
+    let elsewhere = other_worktrees()?.remove(branch);
+    if let Some(dir) = &elsewhere
+        && worktree_is_dirty(dir)?
+    {
+        eprintln!(
+            "Warning: skipping {}: it is checked out with uncommitted changes in {}",
+            branch,
+            dir.display()
+        );
+        return Ok(());
+    }
+
+    let landed = merged.contains(parent) || !branch_exists(parent)?;
+    let trunk = trunk();
+    let onto = if landed { trunk.as_str() } else { parent };
+
+    let base = get_base(branch).filter(|b| is_ancestor(b, branch).unwrap_or(false));
+    let upstream = match base {
+        Some(base) => base,
+        None if !landed => parent.to_string(),
+        None => {
+            return Err(StackError::Metadata(format!(
+                "{} was stacked on {}, which has landed, but its base commit is unknown. Rebase it manually with `git rebase --onto {} <old-parent-commit> {}`.",
+                branch, parent, trunk, branch
+            )));
+        }
+    };
+
+    if landed {
+        info!(
+            "   -> Rebase {} onto {} ({} has landed)",
+            branch, trunk, parent
+        );
+    } else {
+        info!("   -> Rebase {} onto {}", branch, onto);
+    }
+    let spinner = Spinner::start(&format!("Rebasing {}", branch));
+    match &elsewhere {
+        Some(dir) => {
+            run_rebase(Some(dir), &["--onto", onto, &upstream])
+                .map_err(|e| worktree_rebase_error(e, branch, onto, dir))?;
+        }
+        None => {
+            run_rebase(None, &["--onto", onto, &upstream, branch])
+                .map_err(|e| rebase_error(e, branch, onto))?;
+        }
+    }
+    drop(spinner);
+
+    if landed {
+        set_config(&format!("branch.{}.stack-parent", branch), &trunk)?;
+    }
+    set_base(branch, onto)?;
+    emit("rebased", json!({ "branch": branch, "onto": onto }));
+    Ok(())
+}
+
+/// Branches whose PRs the forge reports as merged.
+pub fn merged_branches() -> StackResult<HashSet<String>> {
+    Ok(get_forge()?
+        .review_status()
+        .into_iter()
+        .filter(|(_, pr)| pr.state == "MERGED")
+        .map(|(branch, _)| branch)
+        .collect())
+}
+
+/// Whether `branch` is in the remote trunk, as of the last fetch. Only
+/// reads: callers that want it current fetch first.
+pub fn is_merged_into_trunk(branch: &str) -> StackResult<bool> {
+    let trunk = trunk();
+    let remote = get_remote(&trunk);
+    let remote_trunk = format!("{}/{}", remote, trunk);
+    Ok(is_ancestor(branch, &remote_trunk).unwrap_or(false))
+}
@@ -0,0 +1,80 @@
+//! Error classes shared by the library and the CLI.
+
+use std::io;
+
+use thiserror::Error;
+
+/// What went wrong, by class, so the CLI can exit with a distinct code for
+/// each (see `exit_code`).
+#[derive(Debug, Error)]
+pub enum StackError {
+    /// Anything without a more specific class: a missing branch, nothing to
+    /// do, a declined prompt.
+    #[error("{0}")]
+    Other(String),
+    #[error("{0}")]
+    Usage(String),
+    /// A git command failed.
+    #[error("{0}")]
+    Git(String),
+    #[error(transparent)]
+    Libgit2(#[from] git2::Error),
+    /// The code review host, its CLI, or its API failed.
+    #[error("{0}")]
+    Forge(String),
+    /// A rebase or merge stopped on conflicts and needs the user.
+    #[error("{0}")]
+    Conflict(String),
+    /// Uncommitted changes are in the way.
+    #[error("{0}")]
+    DirtyTree(String),
+    /// Stack metadata or configuration is missing pieces or malformed.
+    #[error("{0}")]
+    Metadata(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl StackError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            StackError::Other(_) | StackError::Io(_) => 1,
+            StackError::Usage(_) => 2,
+            StackError::Git(_) | StackError::Libgit2(_) => 3,
+            StackError::Forge(_) => 4,
+            StackError::Conflict(_) => 5,
+            StackError::DirtyTree(_) => 6,
+            StackError::Metadata(_) => 7,
+        }
+    }
+}
+
+impl From<toml_edit::TomlError> for StackError {
+    fn from(e: toml_edit::TomlError) -> Self {
+        StackError::Metadata(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for StackError {
+    fn from(e: serde_json::Error) -> Self {
+        StackError::Forge(e.to_string())
+    }
+}
+
+impl From<ureq::Error> for StackError {
+    fn from(e: ureq::Error) -> Self {
+        StackError::Forge(e.to_string())
+    }
+}
+
+impl From<ureq::http::Error> for StackError {
+    fn from(e: ureq::http::Error) -> Self {
+        StackError::Forge(e.to_string())
+    }
+}
+
+pub fn err(msg: &str) -> StackError {
+    StackError::Other(msg.to_string())
+}
+
+pub type StackResult<T> = Result<T, StackError>;
@@ -0,0 +1,63 @@
+//! Machine-readable progress for editor integrations and bots. With
+//! `--output json`, `submit`, `restack` and `land` print one JSON object per
+//! line on stdout as things happen, `{"event": "pushed", "branch": ...}`,
+//! and everything meant for people moves to stderr.
+//!
+//! The events: `pushed` (`branch`, `remote`), `pr_created` (`branch`,
+//! `number`, `url`), `pr_updated` (`branch`, `base`), `rebased` (`branch`,
+//! `onto`), `conflict` (`branch`, `onto`, `message`) and `landed` (`branch`,
+//! `into`).
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde_json::{Value, json};
+
+use crate::error::{StackError, StackResult};
+
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Apply `--output <format>` (`text` or `json`) from a command's arguments.
+pub fn set_output_format(format: Option<&str>) -> StackResult<()> {
+    match format {
+        None | Some("text") => Ok(()),
+        Some("json") => {
+            JSON_OUTPUT.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+        Some(other) => Err(StackError::Usage(format!(
+            "Unknown --output '{}'; use text or json",
+            other
+        ))),
+    }
+}
+
+/// Whether stdout is reserved for events.
+pub fn json_output() -> bool {
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Print `event` with `fields` (a JSON object) as one line, under
+/// `--output json`.
+pub fn emit(event: &str, fields: Value) {
+    if !json_output() {
+        return;
+    }
+    let mut line = json!({ "event": event });
+    if let (Some(line), Value::Object(fields)) = (line.as_object_mut(), fields) {
+        line.extend(fields);
+    }
+    let mut stdout = io::stdout().lock();
+    let _ = writeln!(stdout, "{}", line);
+    let _ = stdout.flush();
+}
+
+/// The PR number at the end of a PR URL (`.../pull/12`), if there is one.
+pub fn pr_number(url: &str) -> Option<u64> {
+    url.trim()
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()?
+        .parse()
+        .ok()
+}
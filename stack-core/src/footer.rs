@@ -0,0 +1,116 @@
+//! Navigation footers in PR descriptions: each PR of a stack lists the whole
+//! stack, marks its own place in it, and links the PRs on either side.
+//!
+//! The footer sits between two HTML comments, so it can be replaced on every
+//! submit without touching what the author wrote above it.
+
+use std::collections::HashMap;
+
+use crate::config::setting;
+use crate::error::StackResult;
+use crate::forge::Forge;
+use crate::info;
+use crate::pr::{PrInfo, invalidate_pr_cache};
+
+pub const FOOTER_START: &str = "<!-- stack:footer -->";
+pub const FOOTER_END: &str = "<!-- /stack:footer -->";
+
+/// Footer for `branch`'s PR in `chain` (bottom-up), or `None` when fewer
+/// than two of the branches have open PRs.
+pub fn stack_footer(
+    chain: &[String],
+    prs: &HashMap<String, PrInfo>,
+    branch: &str,
+) -> Option<String> {
+    let open: Vec<(&str, &PrInfo)> = chain
+        .iter()
+        .filter_map(|b| prs.get(b).map(|pr| (b.as_str(), pr)))
+        .filter(|(_, pr)| pr.state == "OPEN")
+        .collect();
+    let position = open.iter().position(|(b, _)| *b == branch)?;
+    if open.len() < 2 {
+        return None;
+    }
+
+    let link = |pr: &PrInfo| format!("[#{}]({})", pr.number, pr.url);
+    let mut lines = vec![
+        FOOTER_START.to_string(),
+        "---".to_string(),
+        format!(
+            "This PR is part {} of {} in a stack:",
+            position + 1,
+            open.len()
+        ),
+        String::new(),
+    ];
+    for (i, (b, pr)) in open.iter().enumerate() {
+        if i == position {
+            lines.push(format!("{}. **{} {}** ← this PR", i + 1, link(pr), b));
+        } else {
+            lines.push(format!("{}. {} {}", i + 1, link(pr), b));
+        }
+    }
+
+    let mut nav = Vec::new();
+    if position > 0 {
+        nav.push(format!("Previous: {}", link(open[position - 1].1)));
+    }
+    if let Some((_, next)) = open.get(position + 1) {
+        nav.push(format!("Next: {}", link(next)));
+    }
+    lines.push(String::new());
+    lines.push(nav.join(" · "));
+    lines.push(FOOTER_END.to_string());
+    Some(lines.join("\n"))
+}
+
+/// `body` with its footer replaced by `footer`, or removed when `None`.
+pub fn with_footer(body: &str, footer: Option<&str>) -> String {
+    let mut text = match (body.find(FOOTER_START), body.find(FOOTER_END)) {
+        (Some(start), Some(end)) if end > start => {
+            format!("{}{}", &body[..start], &body[end + FOOTER_END.len()..])
+        }
+        _ => body.to_string(),
+    }
+    .trim_end()
+    .to_string();
+
+    if let Some(footer) = footer {
+        if !text.is_empty() {
+            text.push_str("\n\n");
+        }
+        text.push_str(footer);
+    }
+    text
+}
+
+/// Bring the footer of every open PR in `chain` up to date, skipping the
+/// ones that already are. Off with `stack.pr-footer = false`; best effort,
+/// since forges without editable descriptions have no footers.
+pub fn refresh_footers(forge: &dyn Forge, chain: &[String]) -> StackResult<()> {
+    if setting("pr-footer").as_deref() == Some("false") {
+        return Ok(());
+    }
+
+    let prs = forge.review_status();
+    let mut updated = 0;
+    for branch in chain {
+        if prs.get(branch).is_none_or(|pr| pr.state != "OPEN") {
+            continue;
+        }
+        let Ok((title, body)) = forge.pr_description(branch) else {
+            continue;
+        };
+        let new_body = with_footer(&body, stack_footer(chain, &prs, branch).as_deref());
+        if new_body != body.trim_end() {
+            forge.set_pr_description(branch, &title, &new_body)?;
+            updated += 1;
+        }
+    }
+
+    if updated > 0 {
+        invalidate_pr_cache();
+        info!("Updated the stack footer on {} PR(s)", updated);
+    }
+    Ok(())
+}
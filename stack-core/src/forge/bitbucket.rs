@@ -0,0 +1,306 @@
+//! Bitbucket Cloud through its REST API.
+
+use std::collections::HashMap;
+use std::env;
+
+use serde_json::{Value, json};
+
+use crate::config::{LandStrategy, setting, trunk};
+use crate::error::{StackError, StackResult};
+use crate::events::emit;
+use crate::forge::{Forge, SubmitOptions, new_pr_message, push_stack};
+use crate::git::remote_slug;
+use crate::http::{base64_encode, http_json, percent_encode};
+use crate::info;
+use crate::metadata::get_parent;
+use crate::pr::PrInfo;
+
+/// Bitbucket Cloud through its REST API.
+///
+/// Authenticates with `BITBUCKET_TOKEN` (or `stack.bitbucket-token`), else
+/// `BITBUCKET_USERNAME` + `BITBUCKET_APP_PASSWORD` (or `stack.bitbucket-user`
+/// and `stack.bitbucket-app-password`).
+pub struct Bitbucket {
+    /// `https://api.bitbucket.org/2.0/repositories/<workspace>/<repo>`
+    pub api: String,
+    pub auth: Option<String>,
+}
+
+impl Bitbucket {
+    pub fn new(remote: &str) -> StackResult<Self> {
+        let credential = |var: &str, key: &str| env::var(var).ok().or_else(|| setting(key));
+
+        let auth = match credential("BITBUCKET_TOKEN", "bitbucket-token") {
+            Some(token) => Some(format!("Bearer {}", token)),
+            None => match (
+                credential("BITBUCKET_USERNAME", "bitbucket-user"),
+                credential("BITBUCKET_APP_PASSWORD", "bitbucket-app-password"),
+            ) {
+                (Some(user), Some(pass)) => Some(format!(
+                    "Basic {}",
+                    base64_encode(format!("{}:{}", user, pass).as_bytes())
+                )),
+                _ => None,
+            },
+        };
+
+        Ok(Bitbucket {
+            api: format!(
+                "https://api.bitbucket.org/2.0/repositories/{}",
+                remote_slug(remote)?
+            ),
+            auth,
+        })
+    }
+
+    fn auth(&self) -> StackResult<&str> {
+        self.auth.as_deref().ok_or_else(|| {
+            StackError::Forge("Bitbucket credentials missing: set BITBUCKET_TOKEN, or BITBUCKET_USERNAME and BITBUCKET_APP_PASSWORD".to_string())
+        })
+    }
+
+    fn request(&self, method: &str, path: &str, body: Option<&Value>) -> StackResult<Value> {
+        http_json(method, &format!("{}{}", self.api, path), self.auth()?, body)
+    }
+
+    /// The open PR whose source is `branch`, if any.
+    fn open_pr(&self, branch: &str) -> StackResult<Option<Value>> {
+        let query = format!("source.branch.name=\"{}\" AND state=\"OPEN\"", branch);
+        let page = self.request(
+            "GET",
+            &format!("/pullrequests?q={}", percent_encode(&query)),
+            None,
+        )?;
+        Ok(page["values"].as_array().and_then(|v| v.first()).cloned())
+    }
+
+    /// Add `opts.reviewers` (account IDs or `{uuid}`s) to an existing PR.
+    /// Bitbucket replaces the reviewer list on update, so merge with the
+    /// current one.
+    fn add_reviewers(&self, pr: &Value, opts: &SubmitOptions) -> StackResult<()> {
+        if opts.reviewers.is_empty() {
+            return Ok(());
+        }
+        let path = format!("/pullrequests/{}", pr["id"]);
+        let full = self.request("GET", &path, None)?;
+
+        let mut reviewers: Vec<Value> = full["reviewers"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|r| json!({ "uuid": r["uuid"] }))
+            .collect();
+        reviewers.extend(bitbucket_reviewers(opts));
+
+        let body = json!({ "title": full["title"], "reviewers": reviewers });
+        self.request("PUT", &path, Some(&body))?;
+        Ok(())
+    }
+
+    fn retarget(&self, pr: &Value, base: &str) -> StackResult<()> {
+        if pr["destination"]["branch"]["name"] == base {
+            return Ok(());
+        }
+        let body = json!({
+            "title": pr["title"],
+            "destination": { "branch": { "name": base } },
+        });
+        self.request("PUT", &format!("/pullrequests/{}", pr["id"]), Some(&body))?;
+        Ok(())
+    }
+}
+
+impl Forge for Bitbucket {
+    fn submit(&self, branches: &[String], opts: &SubmitOptions) -> StackResult<()> {
+        // Fail before pushing anything
+        self.auth()?;
+        if !opts.labels.is_empty() || !opts.assignees.is_empty() {
+            println!("Warning: Bitbucket PRs have no labels or assignees; ignoring them");
+        }
+
+        push_stack(branches, opts)?;
+
+        for branch in branches {
+            let parent = get_parent(branch).unwrap_or_else(trunk);
+            if let Some(pr) = self.open_pr(branch)? {
+                self.retarget(&pr, &parent)?;
+                self.add_reviewers(&pr, opts)?;
+                info!("Updated {} PR #{} base to {}", branch, pr["id"], parent);
+                emit("pr_updated", json!({ "branch": branch, "base": parent }));
+                continue;
+            }
+
+            info!("Creating PR for {} against {}...", branch, parent);
+            let (title, body) = new_pr_message(branch, &parent, opts)?;
+            let created = self.request(
+                "POST",
+                "/pullrequests",
+                Some(&json!({
+                    "title": title,
+                    "description": body,
+                    "source": { "branch": { "name": branch } },
+                    "destination": { "branch": { "name": parent } },
+                    "reviewers": bitbucket_reviewers(opts),
+                    "draft": opts.draft,
+                })),
+            )?;
+            info!(
+                "PR created: {}",
+                created["links"]["html"]["href"]
+                    .as_str()
+                    .unwrap_or_default()
+            );
+            emit(
+                "pr_created",
+                json!({
+                    "branch": branch,
+                    "number": created["id"],
+                    "url": created["links"]["html"]["href"],
+                }),
+            );
+        }
+        Ok(())
+    }
+
+    fn check_auth(&self) -> StackResult<()> {
+        self.auth().map(|_| ())
+    }
+
+    fn review_status(&self) -> HashMap<String, PrInfo> {
+        let path = "/pullrequests?state=OPEN&state=MERGED&pagelen=50\
+            &fields=values.id,values.state,values.source.branch.name,values.participants.approved,\
+            values.links.html.href,values.draft";
+        let Ok(page) = self.request("GET", path, None) else {
+            return HashMap::new();
+        };
+
+        let mut prs = HashMap::new();
+        for pr in page["values"].as_array().into_iter().flatten() {
+            let (Some(branch), Some(number)) =
+                (pr["source"]["branch"]["name"].as_str(), pr["id"].as_u64())
+            else {
+                continue;
+            };
+            let approved = pr["participants"]
+                .as_array()
+                .is_some_and(|ps| ps.iter().any(|p| p["approved"] == true));
+            prs.entry(branch.to_string()).or_insert(PrInfo {
+                number,
+                state: pr["state"].as_str().unwrap_or_default().to_string(),
+                review: if approved { "APPROVED" } else { "" }.to_string(),
+                checks: Vec::new(),
+                url: pr["links"]["html"]["href"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                draft: pr["draft"] == true,
+            });
+        }
+        prs
+    }
+
+    fn pr_description(&self, branch: &str) -> StackResult<(String, String)> {
+        let pr = self
+            .open_pr(branch)?
+            .ok_or_else(|| StackError::Forge(format!("No open Bitbucket PR for {}", branch)))?;
+        Ok((
+            pr["title"].as_str().unwrap_or_default().to_string(),
+            pr["description"].as_str().unwrap_or_default().to_string(),
+        ))
+    }
+
+    fn set_pr_description(&self, branch: &str, title: &str, body: &str) -> StackResult<()> {
+        let pr = self
+            .open_pr(branch)?
+            .ok_or_else(|| StackError::Forge(format!("No open Bitbucket PR for {}", branch)))?;
+        self.request(
+            "PUT",
+            &format!("/pullrequests/{}", pr["id"]),
+            Some(&json!({ "title": title, "description": body })),
+        )?;
+        Ok(())
+    }
+
+    fn publish(&self, branch: &str) -> StackResult<()> {
+        let pr = self
+            .open_pr(branch)?
+            .ok_or_else(|| StackError::Forge(format!("No open Bitbucket PR for {}", branch)))?;
+        self.request(
+            "PUT",
+            &format!("/pullrequests/{}", pr["id"]),
+            Some(&json!({ "title": pr["title"], "draft": false })),
+        )?;
+        Ok(())
+    }
+
+    fn close_pr(&self, branch: &str) -> StackResult<()> {
+        if let Some(pr) = self.open_pr(branch)? {
+            self.request("POST", &format!("/pullrequests/{}/decline", pr["id"]), None)?;
+        }
+        Ok(())
+    }
+
+    fn pr_head(&self, number: u64) -> StackResult<String> {
+        let pr = self.request("GET", &format!("/pullrequests/{}", number), None)?;
+        Ok(pr["source"]["branch"]["name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string())
+    }
+
+    fn open_pr_bases(&self) -> StackResult<Vec<(String, String)>> {
+        let prs = self.request("GET", "/pullrequests?state=OPEN&pagelen=50", None)?;
+        Ok(prs["values"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|pr| {
+                Some((
+                    pr["source"]["branch"]["name"].as_str()?,
+                    pr["destination"]["branch"]["name"].as_str()?,
+                ))
+            })
+            .map(|(head, base)| (head.to_string(), base.to_string()))
+            .collect())
+    }
+
+    fn set_pr_base(&self, branch: &str, base: &str) -> StackResult<()> {
+        if let Some(pr) = self.open_pr(branch)? {
+            self.retarget(&pr, base)?;
+        }
+        Ok(())
+    }
+
+    fn merge(&self, branch: &str, strategy: LandStrategy) -> StackResult<bool> {
+        let merge_strategy = match strategy {
+            LandStrategy::Squash => "squash",
+            LandStrategy::Merge => "merge_commit",
+            LandStrategy::Rebase => "fast_forward",
+        };
+        let pr = self
+            .open_pr(branch)?
+            .ok_or_else(|| StackError::Forge(format!("No open Bitbucket PR for {}", branch)))?;
+
+        // The parent was just merged, so point at trunk before merging
+        self.retarget(&pr, &trunk())?;
+        self.request(
+            "POST",
+            &format!("/pullrequests/{}/merge", pr["id"]),
+            Some(&json!({ "merge_strategy": merge_strategy, "close_source_branch": false })),
+        )?;
+        Ok(true)
+    }
+}
+
+pub fn bitbucket_reviewers(opts: &SubmitOptions) -> Vec<Value> {
+    opts.reviewers
+        .iter()
+        .map(|r| {
+            if r.starts_with('{') {
+                json!({ "uuid": r })
+            } else {
+                json!({ "account_id": r })
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,85 @@
+//! One PR query per command. `get_forge` wraps every forge in a
+//! `ForgeCache`, which asks the forge for the whole repo's PR state (one
+//! `gh pr list`, one REST page) the first time anything wants it, unless
+//! the on-disk cache in `pr` is fresh enough, then answers from memory until
+//! a change to a PR, here or through `invalidate_pr_cache`, makes it ask
+//! again.
+
+use std::collections::HashMap;
+
+use crate::config::LandStrategy;
+use crate::error::StackResult;
+use crate::forge::{Forge, SubmitOptions};
+use crate::pr::{PrInfo, cached_pr_map, invalidate_pr_cache, pr_changed, remembered_pr_map};
+
+pub struct ForgeCache(pub Box<dyn Forge>);
+
+/// `result`, after forgetting PR state the call may have changed.
+fn changed<T>(result: StackResult<T>) -> StackResult<T> {
+    pr_changed();
+    result
+}
+
+impl Forge for ForgeCache {
+    fn submit(&self, branches: &[String], opts: &SubmitOptions) -> StackResult<()> {
+        changed(self.0.submit(branches, opts))
+    }
+
+    fn push(&self, branches: &[String], opts: &SubmitOptions) -> StackResult<()> {
+        // Pushing opens or edits no PR, so there is nothing to fetch again for
+        invalidate_pr_cache();
+        self.0.push(branches, opts)
+    }
+
+    fn review_status(&self) -> HashMap<String, PrInfo> {
+        remembered_pr_map(|| cached_pr_map(|| self.0.review_status()))
+    }
+
+    fn check_auth(&self) -> StackResult<()> {
+        self.0.check_auth()
+    }
+
+    fn pr_description(&self, branch: &str) -> StackResult<(String, String)> {
+        self.0.pr_description(branch)
+    }
+
+    fn set_pr_description(&self, branch: &str, title: &str, body: &str) -> StackResult<()> {
+        changed(self.0.set_pr_description(branch, title, body))
+    }
+
+    fn pr_reviewers(&self, branch: &str) -> Vec<String> {
+        self.0.pr_reviewers(branch)
+    }
+
+    fn publish(&self, branch: &str) -> StackResult<()> {
+        changed(self.0.publish(branch))
+    }
+
+    fn pr_head(&self, number: u64) -> StackResult<String> {
+        self.0.pr_head(number)
+    }
+
+    fn open_pr_bases(&self) -> StackResult<Vec<(String, String)>> {
+        self.0.open_pr_bases()
+    }
+
+    fn set_pr_base(&self, branch: &str, base: &str) -> StackResult<()> {
+        changed(self.0.set_pr_base(branch, base))
+    }
+
+    fn close_pr(&self, branch: &str) -> StackResult<()> {
+        changed(self.0.close_pr(branch))
+    }
+
+    fn rename_branch(&self, branch: &str, new: &str) -> StackResult<bool> {
+        changed(self.0.rename_branch(branch, new))
+    }
+
+    fn merge(&self, branch: &str, strategy: LandStrategy) -> StackResult<bool> {
+        changed(self.0.merge(branch, strategy))
+    }
+
+    fn enqueue(&self, branch: &str, strategy: LandStrategy) -> StackResult<()> {
+        changed(self.0.enqueue(branch, strategy))
+    }
+}
@@ -0,0 +1,139 @@
+//! Gerrit, where each commit is a change.
+
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+
+use serde_json::json;
+
+use crate::config::trunk;
+use crate::error::{StackError, StackResult};
+use crate::events::emit;
+use crate::forge::{Forge, SubmitOptions};
+use crate::git::{commit_messages, get_remote, offline, trace};
+use crate::info;
+use crate::metadata::get_parent;
+use crate::pr::PrInfo;
+
+/// Gerrit: every commit is a change, pushed to `refs/for/<target>`.
+///
+/// Each branch is pushed bottom-up on top of its already-pushed parent, so
+/// Gerrit links the changes into a relation chain that mirrors the stack.
+pub struct Gerrit;
+
+impl Forge for Gerrit {
+    fn submit(&self, branches: &[String], opts: &SubmitOptions) -> StackResult<()> {
+        if opts.no_push {
+            return Err(push_is_review("--no-push"));
+        }
+        if !opts.assignees.is_empty() {
+            println!("Warning: Gerrit has no assignees; ignoring --assignee");
+        }
+
+        // Reviewers and labels (as hashtags) travel as push options, and
+        // drafts as work-in-progress changes
+        let push_opts: Vec<String> = opts
+            .reviewers
+            .iter()
+            .map(|r| format!("r={}", r))
+            .chain(opts.labels.iter().map(|l| format!("hashtag={}", l)))
+            .chain(opts.draft.then(|| "wip".to_string()))
+            .collect();
+        let suffix = if push_opts.is_empty() {
+            String::new()
+        } else {
+            format!("%{}", push_opts.join(","))
+        };
+
+        for branch in branches {
+            let parent = get_parent(branch).unwrap_or_else(trunk);
+            let missing = commit_messages(&parent, branch)?
+                .iter()
+                .filter(|m| !m.lines().any(|l| l.starts_with("Change-Id: ")))
+                .count();
+            if missing > 0 {
+                return Err(StackError::Forge(format!(
+                    "{} has {} commit(s) without a Change-Id. Install Gerrit's commit-msg hook and amend them.",
+                    branch, missing
+                )));
+            }
+
+            let target = gerrit_target(branch);
+            let remote = get_remote(branch);
+            info!("Pushing {} for review on {}...", branch, target);
+            push_for_review(
+                &remote,
+                &format!("{}:refs/for/{}{}", branch, target, suffix),
+            )?;
+            emit("pushed", json!({ "branch": branch, "remote": remote }));
+        }
+        Ok(())
+    }
+
+    fn push(&self, _branches: &[String], _opts: &SubmitOptions) -> StackResult<()> {
+        Err(push_is_review("--push-only"))
+    }
+
+    fn review_status(&self) -> HashMap<String, PrInfo> {
+        HashMap::new()
+    }
+}
+
+/// Pushing to `refs/for/` is what updates a change, so one can't happen
+/// without the other.
+fn push_is_review(flag: &str) -> StackError {
+    StackError::Usage(format!(
+        "On Gerrit, pushing is what submits a change for review; `stack submit {}` can't split them",
+        flag
+    ))
+}
+
+/// Branch the changes should land on: the root the stack grows from.
+pub fn gerrit_target(branch: &str) -> String {
+    let mut seen = vec![branch.to_string()];
+    while let Some(parent) = get_parent(&seen[seen.len() - 1]) {
+        if seen.contains(&parent) {
+            break;
+        }
+        seen.push(parent);
+    }
+    seen.pop().unwrap_or_default()
+}
+
+/// Push a refspec to Gerrit, echoing the `remote:` lines (change URLs).
+/// Re-pushing an unchanged branch is reported rather than treated as failure.
+pub fn push_for_review(remote: &str, refspec: &str) -> StackResult<()> {
+    if offline() {
+        return Err(StackError::Other(
+            "Not pushing to Gerrit while offline (--offline or stack.offline)".to_string(),
+        ));
+    }
+    trace("git", &["push", remote, refspec]);
+    let output = Command::new("git")
+        .args(["push", remote, refspec])
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    for line in stderr.lines() {
+        if let Some(msg) = line.strip_prefix("remote:")
+            && !msg.trim().is_empty()
+        {
+            info!("   {}", msg.trim());
+        }
+    }
+
+    if output.status.success() {
+        Ok(())
+    } else if stderr.contains("no new changes") {
+        info!("   No new changes");
+        Ok(())
+    } else {
+        eprintln!("{}", stderr);
+        Err(StackError::Git(format!(
+            "Command failed: git push {} {}",
+            remote, refspec
+        )))
+    }
+}
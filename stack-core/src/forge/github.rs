@@ -0,0 +1,296 @@
+//! GitHub through the `gh` CLI.
+
+use std::collections::HashMap;
+
+use serde_json::{Value, json};
+
+use crate::config::{LandStrategy, trunk};
+use crate::error::{StackError, StackResult};
+use crate::events::{emit, pr_number};
+use crate::forge::github_api::env_token;
+use crate::forge::{
+    Forge, SubmitOptions, SubmitTarget, gh, new_pr_message, push_stack, reviewer_list,
+    submit_target,
+};
+use crate::git::{get_current_branch, remote_slug, run_command, try_command};
+use crate::info;
+use crate::metadata::get_parent;
+use crate::pr::{PrInfo, get_pr_map, invalidate_pr_cache};
+use crate::ui::Spinner;
+
+/// GitHub through the `gh` CLI: one PR per branch, based on its parent.
+pub struct GitHub;
+
+impl Forge for GitHub {
+    fn submit(&self, branches: &[String], opts: &SubmitOptions) -> StackResult<()> {
+        let targets = push_stack(branches, opts)?;
+
+        // Bottom-up, so each PR's base branch already has its own PR
+        for (branch, target) in &targets {
+            submit_pr(branch, target, opts)?;
+        }
+        Ok(())
+    }
+
+    fn review_status(&self) -> HashMap<String, PrInfo> {
+        get_pr_map()
+    }
+
+    fn check_auth(&self) -> StackResult<()> {
+        // gh uses these tokens itself when they are set
+        if env_token().is_some() || try_command("gh", &["auth", "token"]).is_some() {
+            return Ok(());
+        }
+        Err(StackError::Forge(
+            "gh is not logged in to GitHub: run `gh auth login`, or set GITHUB_TOKEN to use the built-in API client"
+                .to_string(),
+        ))
+    }
+
+    fn pr_description(&self, branch: &str) -> StackResult<(String, String)> {
+        let target = submit_target(branch)?;
+        let raw = gh(
+            &target,
+            &["pr", "view", &target.head, "--json", "title,body"],
+        )?;
+        let pr: Value = serde_json::from_str(&raw)?;
+        Ok((
+            pr["title"].as_str().unwrap_or_default().to_string(),
+            pr["body"].as_str().unwrap_or_default().to_string(),
+        ))
+    }
+
+    fn set_pr_description(&self, branch: &str, title: &str, body: &str) -> StackResult<()> {
+        let target = submit_target(branch)?;
+        gh(
+            &target,
+            &["pr", "edit", &target.head, "--title", title, "--body", body],
+        )?;
+        Ok(())
+    }
+
+    fn pr_reviewers(&self, branch: &str) -> Vec<String> {
+        let Ok(target) = submit_target(branch) else {
+            return Vec::new();
+        };
+        let Some(pr) = gh(
+            &target,
+            &[
+                "pr",
+                "view",
+                &target.head,
+                "--json",
+                "reviewRequests,latestReviews",
+            ],
+        )
+        .ok()
+        .and_then(|raw| serde_json::from_str::<Value>(&raw).ok()) else {
+            return Vec::new();
+        };
+
+        let reviews: Vec<(String, String)> = pr["latestReviews"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|r| {
+                let name = r["author"]["login"].as_str()?;
+                Some((name.to_string(), r["state"].as_str()?.to_string()))
+            })
+            .collect();
+        // Users have a login, teams a name
+        let requested: Vec<String> = pr["reviewRequests"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|r| r["login"].as_str().or(r["name"].as_str()))
+            .map(str::to_string)
+            .collect();
+        reviewer_list(&reviews, &requested)
+    }
+
+    fn publish(&self, branch: &str) -> StackResult<()> {
+        let target = submit_target(branch)?;
+        gh(&target, &["pr", "ready", &target.head])?;
+        invalidate_pr_cache();
+        Ok(())
+    }
+
+    fn pr_head(&self, number: u64) -> StackResult<String> {
+        let target = submit_target(&get_current_branch()?)?;
+        gh(
+            &target,
+            &[
+                "pr",
+                "view",
+                &number.to_string(),
+                "--json",
+                "headRefName",
+                "--jq",
+                ".headRefName",
+            ],
+        )
+    }
+
+    fn set_pr_base(&self, branch: &str, base: &str) -> StackResult<()> {
+        let target = submit_target(branch)?;
+        if gh(&target, &["pr", "view", &target.head]).is_err() {
+            return Ok(());
+        }
+        gh(&target, &["pr", "edit", &target.head, "--base", base])?;
+        invalidate_pr_cache();
+        Ok(())
+    }
+
+    fn close_pr(&self, branch: &str) -> StackResult<()> {
+        let target = submit_target(branch)?;
+        if gh(&target, &["pr", "view", &target.head]).is_err() {
+            return Ok(());
+        }
+        gh(&target, &["pr", "close", &target.head])?;
+        invalidate_pr_cache();
+        Ok(())
+    }
+
+    fn enqueue(&self, branch: &str, strategy: LandStrategy) -> StackResult<()> {
+        let target = submit_target(branch)?;
+        let method = match strategy {
+            LandStrategy::Squash => "--squash",
+            LandStrategy::Merge => "--merge",
+            LandStrategy::Rebase => "--rebase",
+        };
+        // With a merge queue on trunk, `--auto` adds the PR to it
+        gh(&target, &["pr", "merge", &target.head, "--auto", method])?;
+        invalidate_pr_cache();
+        Ok(())
+    }
+
+    fn rename_branch(&self, branch: &str, new: &str) -> StackResult<bool> {
+        // GitHub retargets the branch's PR and the PRs based on it
+        let target = submit_target(branch)?;
+        let repo = match &target.repo {
+            Some(_) => remote_slug(&target.push_remote)?,
+            None => "{owner}/{repo}".to_string(),
+        };
+        run_command(
+            "gh",
+            &[
+                "api",
+                "-X",
+                "POST",
+                &format!("repos/{}/branches/{}/rename", repo, branch),
+                "-f",
+                &format!("new_name={}", new),
+            ],
+        )?;
+        invalidate_pr_cache();
+        Ok(true)
+    }
+
+    fn open_pr_bases(&self) -> StackResult<Vec<(String, String)>> {
+        let target = submit_target(&get_current_branch()?)?;
+        let out = gh(
+            &target,
+            &[
+                "pr",
+                "list",
+                "--state",
+                "open",
+                "--limit",
+                "200",
+                "--json",
+                "headRefName,baseRefName",
+                "--jq",
+                ".[] | [.headRefName, .baseRefName] | @tsv",
+            ],
+        )?;
+        Ok(out
+            .lines()
+            .filter_map(|l| l.split_once('\t'))
+            .map(|(head, base)| (head.to_string(), base.to_string()))
+            .collect())
+    }
+}
+
+pub fn submit_pr(branch: &str, target: &SubmitTarget, opts: &SubmitOptions) -> StackResult<()> {
+    let parent = get_parent(branch).unwrap_or_else(trunk);
+
+    // gh spells the current user `@me`
+    let assignees: Vec<String> = opts
+        .assignees
+        .iter()
+        .map(|a| {
+            if a == "me" {
+                "@me".to_string()
+            } else {
+                a.clone()
+            }
+        })
+        .collect();
+
+    // Check if PR already exists
+    let pr_exists = gh(target, &["pr", "view", &target.head]).is_ok();
+
+    if pr_exists {
+        let mut gh_args = vec!["pr", "edit", &target.head, "--base", &parent];
+        for reviewer in &opts.reviewers {
+            gh_args.extend_from_slice(&["--add-reviewer", reviewer]);
+        }
+        for label in &opts.labels {
+            gh_args.extend_from_slice(&["--add-label", label]);
+        }
+        for assignee in &assignees {
+            gh_args.extend_from_slice(&["--add-assignee", assignee]);
+        }
+        let spinner = Spinner::start(&format!("Updating PR for {}", branch));
+        gh(target, &gh_args)?;
+        drop(spinner);
+        invalidate_pr_cache();
+        info!("Updated {} PR base to {}", branch, parent);
+        emit("pr_updated", json!({ "branch": branch, "base": parent }));
+    } else {
+        info!("Creating PR for {} against {}...", branch, parent);
+
+        let (title, body) = new_pr_message(branch, &parent, opts)?;
+
+        let mut gh_args = vec![
+            "pr",
+            "create",
+            "--base",
+            &parent,
+            "--head",
+            &target.head,
+            "--title",
+            &title,
+        ];
+
+        if body.is_empty() {
+            gh_args.extend_from_slice(&["--body", ""]);
+        } else {
+            gh_args.extend_from_slice(&["--body", &body])
+        }
+        if opts.draft {
+            gh_args.push("--draft");
+        }
+        for reviewer in &opts.reviewers {
+            gh_args.extend_from_slice(&["--reviewer", reviewer]);
+        }
+        for label in &opts.labels {
+            gh_args.extend_from_slice(&["--label", label]);
+        }
+        for assignee in &assignees {
+            gh_args.extend_from_slice(&["--assignee", assignee]);
+        }
+
+        let spinner = Spinner::start(&format!("Opening PR for {}", branch));
+        let url = gh(target, &gh_args)?;
+        drop(spinner);
+        invalidate_pr_cache();
+        info!("PR created!");
+        emit(
+            "pr_created",
+            json!({ "branch": branch, "number": pr_number(&url), "url": url.trim() }),
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,410 @@
+//! GitHub through its REST API, for machines without `gh`.
+
+use std::collections::HashMap;
+use std::env;
+
+use serde_json::{Value, json};
+
+use crate::config::{LandStrategy, setting, trunk};
+use crate::error::{StackError, StackResult};
+use crate::events::emit;
+use crate::forge::{
+    Forge, SubmitOptions, SubmitTarget, new_pr_message, push_stack, reviewer_list, submit_target,
+};
+use crate::git::{get_current_branch, remote_slug, try_command};
+use crate::http::{http_json, percent_encode};
+use crate::info;
+use crate::metadata::get_parent;
+use crate::pr::PrInfo;
+
+const MISSING_TOKEN: &str = "No GitHub credentials: install gh (https://cli.github.com) and run `gh auth login`, or set GITHUB_TOKEN to use the built-in API client";
+
+/// `GITHUB_TOKEN`, else `GH_TOKEN`.
+pub fn env_token() -> Option<String> {
+    env::var("GITHUB_TOKEN")
+        .or_else(|_| env::var("GH_TOKEN"))
+        .ok()
+        .filter(|t| !t.is_empty())
+}
+
+/// GitHub through its REST API, for machines without `gh`.
+///
+/// Authenticates with `GITHUB_TOKEN`/`GH_TOKEN`, falling back to
+/// `gh auth token`. `stack.github-api` points it at GitHub Enterprise.
+pub struct GitHubApi {
+    pub api: String,
+    pub token: Option<String>,
+}
+
+impl GitHubApi {
+    pub fn new() -> StackResult<Self> {
+        let token = env_token()
+            .or_else(|| try_command("gh", &["auth", "token"]))
+            .filter(|t| !t.is_empty());
+
+        Ok(GitHubApi {
+            api: setting("github-api")
+                .unwrap_or_else(|| "https://api.github.com".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            token,
+        })
+    }
+
+    fn auth(&self) -> StackResult<String> {
+        match &self.token {
+            Some(token) => Ok(format!("Bearer {}", token)),
+            None => Err(StackError::Forge(MISSING_TOKEN.to_string())),
+        }
+    }
+
+    fn request(&self, method: &str, path: &str, body: Option<&Value>) -> StackResult<Value> {
+        http_json(
+            method,
+            &format!("{}{}", self.api, path),
+            &self.auth()?,
+            body,
+        )
+    }
+
+    /// Run a GraphQL `mutation` on the PR with node ID `id`, failing with
+    /// the first error GitHub reports. Enterprise serves GraphQL next to
+    /// `/api/v3`.
+    fn graphql(&self, mutation: &str, id: &Value) -> StackResult<()> {
+        let url = match self.api.strip_suffix("/v3") {
+            Some(base) => format!("{}/graphql", base),
+            None => format!("{}/graphql", self.api),
+        };
+        let query = json!({ "query": mutation, "variables": { "id": id } });
+        let response = http_json("POST", &url, &self.auth()?, Some(&query))?;
+        match response["errors"][0]["message"].as_str() {
+            Some(error) => Err(StackError::Forge(error.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// `OWNER/REPO` that holds the PRs for `target`.
+    fn repo(&self, target: &SubmitTarget) -> StackResult<String> {
+        match &target.repo {
+            Some(repo) => Ok(repo.clone()),
+            None => remote_slug(&target.push_remote),
+        }
+    }
+
+    fn open_pr(&self, repo: &str, target: &SubmitTarget) -> StackResult<Option<Value>> {
+        // The pulls API only filters on qualified `owner:branch` heads
+        let head = if target.head.contains(':') {
+            target.head.clone()
+        } else {
+            let owner = repo.split('/').next().unwrap_or_default();
+            format!("{}:{}", owner, target.head)
+        };
+        let prs = self.request(
+            "GET",
+            &format!(
+                "/repos/{}/pulls?state=open&head={}",
+                repo,
+                percent_encode(&head)
+            ),
+            None,
+        )?;
+        Ok(prs.as_array().and_then(|p| p.first()).cloned())
+    }
+
+    /// Add reviewers, labels, and assignees to PR `number` (additive, like
+    /// `gh pr edit --add-*`).
+    fn apply_options(&self, repo: &str, number: &Value, opts: &SubmitOptions) -> StackResult<()> {
+        if !opts.reviewers.is_empty() {
+            self.request(
+                "POST",
+                &format!("/repos/{}/pulls/{}/requested_reviewers", repo, number),
+                Some(&json!({ "reviewers": opts.reviewers })),
+            )?;
+        }
+        if !opts.labels.is_empty() {
+            self.request(
+                "POST",
+                &format!("/repos/{}/issues/{}/labels", repo, number),
+                Some(&json!({ "labels": opts.labels })),
+            )?;
+        }
+        if !opts.assignees.is_empty() {
+            let mut assignees = Vec::new();
+            for assignee in &opts.assignees {
+                if assignee == "me" || assignee == "@me" {
+                    let user = self.request("GET", "/user", None)?;
+                    assignees.push(user["login"].as_str().unwrap_or_default().to_string());
+                } else {
+                    assignees.push(assignee.clone());
+                }
+            }
+            self.request(
+                "POST",
+                &format!("/repos/{}/issues/{}/assignees", repo, number),
+                Some(&json!({ "assignees": assignees })),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Forge for GitHubApi {
+    fn submit(&self, branches: &[String], opts: &SubmitOptions) -> StackResult<()> {
+        // Fail before pushing anything
+        self.auth()?;
+
+        let targets = push_stack(branches, opts)?;
+
+        for (branch, target) in &targets {
+            let parent = get_parent(branch).unwrap_or_else(trunk);
+            let repo = self.repo(target)?;
+
+            if let Some(pr) = self.open_pr(&repo, target)? {
+                if pr["base"]["ref"] != parent.as_str() {
+                    self.request(
+                        "PATCH",
+                        &format!("/repos/{}/pulls/{}", repo, pr["number"]),
+                        Some(&json!({ "base": parent })),
+                    )?;
+                }
+                self.apply_options(&repo, &pr["number"], opts)?;
+                info!("Updated {} PR base to {}", branch, parent);
+                emit("pr_updated", json!({ "branch": branch, "base": parent }));
+                continue;
+            }
+
+            info!("Creating PR for {} against {}...", branch, parent);
+            let (title, body) = new_pr_message(branch, &parent, opts)?;
+            let created = self.request(
+                "POST",
+                &format!("/repos/{}/pulls", repo),
+                Some(&json!({
+                    "title": title,
+                    "body": body,
+                    "head": target.head,
+                    "base": parent,
+                    "draft": opts.draft,
+                })),
+            )?;
+            self.apply_options(&repo, &created["number"], opts)?;
+            info!(
+                "PR created: {}",
+                created["html_url"].as_str().unwrap_or_default()
+            );
+            emit(
+                "pr_created",
+                json!({ "branch": branch, "number": created["number"], "url": created["html_url"] }),
+            );
+        }
+        Ok(())
+    }
+
+    fn pr_description(&self, branch: &str) -> StackResult<(String, String)> {
+        let target = submit_target(branch)?;
+        let pr = self
+            .open_pr(&self.repo(&target)?, &target)?
+            .ok_or_else(|| StackError::Forge(format!("No open PR for {}", branch)))?;
+        Ok((
+            pr["title"].as_str().unwrap_or_default().to_string(),
+            pr["body"].as_str().unwrap_or_default().to_string(),
+        ))
+    }
+
+    fn set_pr_description(&self, branch: &str, title: &str, body: &str) -> StackResult<()> {
+        let target = submit_target(branch)?;
+        let repo = self.repo(&target)?;
+        let pr = self
+            .open_pr(&repo, &target)?
+            .ok_or_else(|| StackError::Forge(format!("No open PR for {}", branch)))?;
+        self.request(
+            "PATCH",
+            &format!("/repos/{}/pulls/{}", repo, pr["number"]),
+            Some(&json!({ "title": title, "body": body })),
+        )?;
+        Ok(())
+    }
+
+    fn pr_reviewers(&self, branch: &str) -> Vec<String> {
+        let Ok(target) = submit_target(branch) else {
+            return Vec::new();
+        };
+        let Ok(repo) = self.repo(&target) else {
+            return Vec::new();
+        };
+        let Ok(Some(pr)) = self.open_pr(&repo, &target) else {
+            return Vec::new();
+        };
+
+        let reviews: Vec<(String, String)> = self
+            .request(
+                "GET",
+                &format!("/repos/{}/pulls/{}/reviews", repo, pr["number"]),
+                None,
+            )
+            .ok()
+            .and_then(|r| r.as_array().cloned())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|r| {
+                let name = r["user"]["login"].as_str()?;
+                Some((name.to_string(), r["state"].as_str()?.to_string()))
+            })
+            .collect();
+        let users = pr["requested_reviewers"].as_array().into_iter().flatten();
+        let teams = pr["requested_teams"].as_array().into_iter().flatten();
+        let requested: Vec<String> = users
+            .filter_map(|u| u["login"].as_str())
+            .chain(teams.filter_map(|t| t["name"].as_str()))
+            .map(str::to_string)
+            .collect();
+        reviewer_list(&reviews, &requested)
+    }
+
+    fn publish(&self, branch: &str) -> StackResult<()> {
+        let target = submit_target(branch)?;
+        let pr = self
+            .open_pr(&self.repo(&target)?, &target)?
+            .ok_or_else(|| StackError::Forge(format!("No open PR for {}", branch)))?;
+
+        // REST can only create drafts; undrafting is GraphQL-only
+        self.graphql(
+            "mutation($id: ID!) { markPullRequestReadyForReview(input: {pullRequestId: $id}) { clientMutationId } }",
+            &pr["node_id"],
+        )
+        .map_err(|e| {
+            StackError::Forge(format!("Could not mark the PR for {} ready: {}", branch, e))
+        })
+    }
+
+    fn enqueue(&self, branch: &str, strategy: LandStrategy) -> StackResult<()> {
+        let target = submit_target(branch)?;
+        let pr = self
+            .open_pr(&self.repo(&target)?, &target)?
+            .ok_or_else(|| StackError::Forge(format!("No open PR for {}", branch)))?;
+        let method = match strategy {
+            LandStrategy::Squash => "SQUASH",
+            LandStrategy::Merge => "MERGE",
+            LandStrategy::Rebase => "REBASE",
+        };
+        // Trunk's merge queue when it has one, auto-merge otherwise
+        self.graphql(
+            "mutation($id: ID!) { enqueuePullRequest(input: {pullRequestId: $id}) { clientMutationId } }",
+            &pr["node_id"],
+        )
+        .or_else(|_| {
+            self.graphql(
+                &format!(
+                    "mutation($id: ID!) {{ enablePullRequestAutoMerge(input: {{pullRequestId: $id, mergeMethod: {}}}) {{ clientMutationId }} }}",
+                    method
+                ),
+                &pr["node_id"],
+            )
+        })
+        .map_err(|e| StackError::Forge(format!("Could not queue the PR for {}: {}", branch, e)))
+    }
+
+    fn pr_head(&self, number: u64) -> StackResult<String> {
+        let repo = self.repo(&submit_target(&get_current_branch()?)?)?;
+        let pr = self.request("GET", &format!("/repos/{}/pulls/{}", repo, number), None)?;
+        Ok(pr["head"]["ref"].as_str().unwrap_or_default().to_string())
+    }
+
+    fn set_pr_base(&self, branch: &str, base: &str) -> StackResult<()> {
+        let target = submit_target(branch)?;
+        let repo = self.repo(&target)?;
+        if let Some(pr) = self.open_pr(&repo, &target)?
+            && pr["base"]["ref"] != base
+        {
+            self.request(
+                "PATCH",
+                &format!("/repos/{}/pulls/{}", repo, pr["number"]),
+                Some(&json!({ "base": base })),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn close_pr(&self, branch: &str) -> StackResult<()> {
+        let target = submit_target(branch)?;
+        let repo = self.repo(&target)?;
+        if let Some(pr) = self.open_pr(&repo, &target)? {
+            self.request(
+                "PATCH",
+                &format!("/repos/{}/pulls/{}", repo, pr["number"]),
+                Some(&json!({ "state": "closed" })),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn rename_branch(&self, branch: &str, new: &str) -> StackResult<bool> {
+        let target = submit_target(branch)?;
+        self.request(
+            "POST",
+            &format!(
+                "/repos/{}/branches/{}/rename",
+                remote_slug(&target.push_remote)?,
+                percent_encode(branch)
+            ),
+            Some(&json!({ "new_name": new })),
+        )?;
+        Ok(true)
+    }
+
+    fn open_pr_bases(&self) -> StackResult<Vec<(String, String)>> {
+        let repo = self.repo(&submit_target(&get_current_branch()?)?)?;
+        let prs = self.request(
+            "GET",
+            &format!("/repos/{}/pulls?state=open&per_page=100", repo),
+            None,
+        )?;
+        Ok(prs
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|pr| Some((pr["head"]["ref"].as_str()?, pr["base"]["ref"].as_str()?)))
+            .map(|(head, base)| (head.to_string(), base.to_string()))
+            .collect())
+    }
+
+    fn check_auth(&self) -> StackResult<()> {
+        self.auth().map(|_| ())
+    }
+
+    fn review_status(&self) -> HashMap<String, PrInfo> {
+        let current = get_current_branch().unwrap_or_default();
+        let Ok(repo) = submit_target(&current).and_then(|t| self.repo(&t)) else {
+            return HashMap::new();
+        };
+        let Ok(prs) = self.request(
+            "GET",
+            &format!("/repos/{}/pulls?state=all&per_page=100", repo),
+            None,
+        ) else {
+            return HashMap::new();
+        };
+
+        let mut map = HashMap::new();
+        for pr in prs.as_array().into_iter().flatten() {
+            let (Some(branch), Some(number)) = (pr["head"]["ref"].as_str(), pr["number"].as_u64())
+            else {
+                continue;
+            };
+            let state = if !pr["merged_at"].is_null() {
+                "MERGED".to_string()
+            } else {
+                pr["state"].as_str().unwrap_or_default().to_uppercase()
+            };
+            // Newest first, like `gh pr list`
+            map.entry(branch.to_string()).or_insert(PrInfo {
+                number,
+                state,
+                review: String::new(),
+                checks: Vec::new(),
+                url: pr["html_url"].as_str().unwrap_or_default().to_string(),
+                draft: pr["draft"] == true,
+            });
+        }
+        map
+    }
+}
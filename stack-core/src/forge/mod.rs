@@ -0,0 +1,508 @@
+//! Code review hosts: GitHub (through `gh` or its REST API), Gerrit, and
+//! Bitbucket Cloud.
+
+pub mod bitbucket;
+pub mod cache;
+pub mod gerrit;
+pub mod github;
+pub mod github_api;
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::json;
+
+use crate::config::{LandStrategy, setting, setting_all, trunk};
+use crate::drafts::{save_description, saved_description};
+use crate::engine::{RestackPlan, Stack};
+use crate::error::{StackError, StackResult, err};
+use crate::events::emit;
+use crate::forge::bitbucket::Bitbucket;
+use crate::forge::cache::ForgeCache;
+use crate::forge::gerrit::Gerrit;
+use crate::forge::github::GitHub;
+use crate::forge::github_api::GitHubApi;
+use crate::git::{
+    ahead_behind, commit_messages, ensure_clean_worktree, get_current_branch, get_remote, git,
+    git_streamed, is_ancestor, remote_slug, remote_url, rev_parse, run_command, try_command,
+};
+use crate::per_commit::pr_message;
+use crate::pr::PrInfo;
+use crate::ui::{Spinner, interactive, prompt, prompt_pr};
+use crate::{info, report};
+
+/// Where `submit` pushes a branch and where its PR lives.
+pub struct SubmitTarget {
+    pub push_remote: String,
+    /// `OWNER/REPO` passed as `--repo` when PRs go to a different remote
+    pub repo: Option<String>,
+    /// PR head: `branch`, or `owner:branch` for cross-repo PRs
+    pub head: String,
+}
+
+/// Resolve `stack.push-remote` / `stack.pr-remote` for `branch`. Without them
+/// both sides use the branch's remote and gh infers the repository.
+pub fn submit_target(branch: &str) -> StackResult<SubmitTarget> {
+    let push_remote = setting("push-remote").unwrap_or_else(|| get_remote(branch));
+    let pr_remote = match setting("pr-remote") {
+        Some(r) if r != push_remote => r,
+        _ => {
+            return Ok(SubmitTarget {
+                push_remote,
+                repo: None,
+                head: branch.to_string(),
+            });
+        }
+    };
+
+    let fork = remote_slug(&push_remote)?;
+    let owner = fork.split('/').next().unwrap_or_default();
+    Ok(SubmitTarget {
+        head: format!("{}:{}", owner, branch),
+        repo: Some(remote_slug(&pr_remote)?),
+        push_remote,
+    })
+}
+
+/// Run `gh` with `args`, adding `--repo` when the target needs it.
+pub fn gh(target: &SubmitTarget, args: &[&str]) -> StackResult<String> {
+    let mut full = args.to_vec();
+    if let Some(repo) = &target.repo {
+        full.extend_from_slice(&["--repo", repo]);
+    }
+    run_command("gh", &full)
+}
+
+/// PR metadata applied by `submit`: `--reviewer`, `--label`, and `--assignee`
+/// flags plus the repo defaults in `stack.reviewer`, `stack.label`, and
+/// `stack.assignee` (multi-valued git config keys). New PRs open as drafts
+/// when `stack.draft` is `true`.
+#[derive(Default)]
+pub struct SubmitOptions {
+    pub reviewers: Vec<String>,
+    pub labels: Vec<String>,
+    pub assignees: Vec<String>,
+    /// Overwrite remote branches that someone else pushed to.
+    pub force: bool,
+    /// Open new PRs as drafts, for `stack publish` to mark ready later.
+    pub draft: bool,
+    /// Title and describe new PRs from their branch's single commit, as
+    /// `submit --per-commit` does, instead of asking.
+    pub from_commits: bool,
+    /// Update reviews from what is already on the remote, pushing nothing,
+    /// as `submit --no-push` does.
+    pub no_push: bool,
+}
+
+impl SubmitOptions {
+    /// The repo defaults for each key, followed by any extra `reviewers`,
+    /// `labels`, and `assignees` given on the command line.
+    pub fn with_defaults(
+        reviewers: Vec<String>,
+        labels: Vec<String>,
+        assignees: Vec<String>,
+    ) -> Self {
+        let collect = |extra: Vec<String>, key: &str| {
+            let mut values = setting_all(key);
+            for value in extra {
+                if !values.contains(&value) {
+                    values.push(value);
+                }
+            }
+            values
+        };
+
+        SubmitOptions {
+            reviewers: collect(reviewers, "reviewer"),
+            labels: collect(labels, "label"),
+            assignees: collect(assignees, "assignee"),
+            force: false,
+            draft: setting("draft").as_deref() == Some("true"),
+            from_commits: false,
+            no_push: false,
+        }
+    }
+}
+
+/// Title and description for `branch`'s new PR against `parent`: the one
+/// saved by an earlier attempt, or else a new one, saved before the caller
+/// tries to create the PR with it.
+pub fn new_pr_message(
+    branch: &str,
+    parent: &str,
+    opts: &SubmitOptions,
+) -> StackResult<(String, String)> {
+    if opts.from_commits
+        && let Some(message) = commit_messages(parent, branch)?.into_iter().next()
+    {
+        return Ok(pr_message(&message));
+    }
+    if let Some(saved) = saved_description(branch) {
+        info!("Using the description saved for {}", branch);
+        return Ok(saved);
+    }
+    let (title, body) = prompt_pr(branch, parent)?;
+    save_description(branch, &title, &body)?;
+    Ok((title, body))
+}
+
+/// `pr_reviewers` entries: everyone who reviewed, with their latest verdict
+/// (`APPROVED`, `CHANGES_REQUESTED`, ...), then whoever is still requested.
+pub(crate) fn reviewer_list(reviews: &[(String, String)], requested: &[String]) -> Vec<String> {
+    let mut verdicts: Vec<(&str, &str)> = Vec::new();
+    for (name, state) in reviews {
+        match verdicts.iter_mut().find(|(n, _)| n == name) {
+            // A comment doesn't take back an approval
+            Some(entry) if state != "COMMENTED" => entry.1 = state,
+            Some(_) => {}
+            None => verdicts.push((name, state)),
+        }
+    }
+
+    let mut out: Vec<String> = verdicts
+        .iter()
+        .map(|(name, state)| format!("{} ({})", name, state.to_lowercase().replace('_', " ")))
+        .collect();
+    for name in requested {
+        if !verdicts.iter().any(|(n, _)| n == name) {
+            out.push(format!("{} (requested)", name));
+        }
+    }
+    out
+}
+
+/// A code review host: where `submit` sends branches and `log` reads status.
+pub trait Forge {
+    /// Push `branches` (ordered bottom-up) and create or update their reviews.
+    fn submit(&self, branches: &[String], opts: &SubmitOptions) -> StackResult<()>;
+
+    /// Push `branches` without creating or updating their reviews, as
+    /// `submit --push-only` does.
+    fn push(&self, branches: &[String], opts: &SubmitOptions) -> StackResult<()> {
+        push_stack(branches, opts).map(|_| ())
+    }
+
+    /// Review state per branch name. Best effort: empty when unavailable.
+    fn review_status(&self) -> HashMap<String, PrInfo>;
+
+    /// Fail with what to do about it when the forge's CLI or credentials
+    /// are missing, before a command pushes anything.
+    fn check_auth(&self) -> StackResult<()> {
+        Ok(())
+    }
+
+    /// Title and description of `branch`'s open PR.
+    fn pr_description(&self, _branch: &str) -> StackResult<(String, String)> {
+        Err(StackError::Forge(
+            "This forge does not support editing PR descriptions".to_string(),
+        ))
+    }
+
+    fn set_pr_description(&self, _branch: &str, _title: &str, _body: &str) -> StackResult<()> {
+        Err(StackError::Forge(
+            "This forge does not support editing PR descriptions".to_string(),
+        ))
+    }
+
+    /// Who is on `branch`'s open PR, as `name (approved)`, `name (requested)`
+    /// and so on. Best effort: empty when unavailable.
+    fn pr_reviewers(&self, _branch: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Mark `branch`'s draft PR ready for review.
+    fn publish(&self, _branch: &str) -> StackResult<()> {
+        Err(StackError::Forge(
+            "This forge does not support draft PRs".to_string(),
+        ))
+    }
+
+    /// Head branch of PR `number`.
+    fn pr_head(&self, _number: u64) -> StackResult<String> {
+        Err(StackError::Forge(
+            "This forge does not support looking up PRs by number".to_string(),
+        ))
+    }
+
+    /// `(head, base)` branches of every open review.
+    fn open_pr_bases(&self) -> StackResult<Vec<(String, String)>> {
+        Err(StackError::Forge(
+            "This forge does not support listing open PRs".to_string(),
+        ))
+    }
+
+    /// Point `branch`'s open review at `base`, if it has one. Forges without
+    /// review bases have nothing to do.
+    fn set_pr_base(&self, _branch: &str, _base: &str) -> StackResult<()> {
+        Ok(())
+    }
+
+    /// Close `branch`'s open review without merging it, if it has one.
+    fn close_pr(&self, _branch: &str) -> StackResult<()> {
+        Ok(())
+    }
+
+    /// Rename `branch` on the remote to `new`, taking its review and the
+    /// reviews based on it along. Returns `false` when the forge can't, and
+    /// `cmd_rename` pushes the new name and deletes the old one instead.
+    fn rename_branch(&self, _branch: &str, _new: &str) -> StackResult<bool> {
+        Ok(false)
+    }
+
+    /// Merge `branch`'s review into trunk on the server using `strategy`.
+    /// Returns `false` when the forge leaves landing to the local merge in
+    /// `cmd_land`.
+    fn merge(&self, _branch: &str, _strategy: LandStrategy) -> StackResult<bool> {
+        Ok(false)
+    }
+
+    /// Hand `branch`'s PR to the merge queue (or auto-merge) to merge into
+    /// trunk with `strategy` once its checks pass. `land --merge-queue`
+    /// watches `review_status` for it to merge.
+    fn enqueue(&self, _branch: &str, _strategy: LandStrategy) -> StackResult<()> {
+        Err(StackError::Forge(
+            "This forge does not support merge queues".to_string(),
+        ))
+    }
+}
+
+/// Forge selected by `stack.forge`. Without it, Bitbucket is picked for
+/// bitbucket.org remotes and GitHub for everything else. GitHub goes through
+/// `gh` when it is installed and the built-in API client otherwise. PR
+/// state is fetched once per command (see `ForgeCache`).
+pub fn get_forge() -> StackResult<Box<dyn Forge>> {
+    let forge = setting("forge").unwrap_or_else(|| {
+        let remote = get_remote(&trunk());
+        match remote_url(&remote) {
+            Ok(url) if url.contains("bitbucket.org") => "bitbucket".to_string(),
+            _ => "github".to_string(),
+        }
+    });
+
+    let forge: Box<dyn Forge> = match forge.as_str() {
+        "github" if try_command("gh", &["--version"]).is_some() => Box::new(GitHub),
+        "github" | "github-api" => Box::new(GitHubApi::new()?),
+        "gerrit" => Box::new(Gerrit),
+        "bitbucket" => Box::new(Bitbucket::new(&get_remote(&trunk()))?),
+        other => {
+            return Err(StackError::Metadata(format!(
+                "Unknown forge '{}' in the forge setting",
+                other
+            )));
+        }
+    };
+    Ok(Box::new(ForgeCache(forge)))
+}
+
+/// `get_forge` for commands that need to talk to the forge, checked for
+/// credentials up front. Read-only views such as `log` use `get_forge` and
+/// show no PRs instead.
+pub fn authenticated_forge() -> StackResult<Box<dyn Forge>> {
+    let forge = get_forge()?;
+    forge.check_auth()?;
+    Ok(forge)
+}
+
+/// Resolve each branch's submit target, reconcile branches that changed on
+/// the remote, and push them all. With `no_push`, only check that they are
+/// all on the remote already.
+pub fn push_stack(
+    branches: &[String],
+    opts: &SubmitOptions,
+) -> StackResult<Vec<(String, SubmitTarget)>> {
+    let mut targets = Vec::new();
+    for branch in branches {
+        targets.push((branch.clone(), submit_target(branch)?));
+    }
+    if opts.no_push {
+        let unpushed: Vec<&str> = targets
+            .iter()
+            .filter(|(branch, target)| {
+                let remote_ref = format!("refs/heads/{}", branch);
+                try_command(
+                    "git",
+                    &["ls-remote", "--exit-code", &target.push_remote, &remote_ref],
+                )
+                .is_none()
+            })
+            .map(|(branch, _)| branch.as_str())
+            .collect();
+        if !unpushed.is_empty() {
+            return Err(err(&format!(
+                "Not on the remote yet: {}. Submit without --no-push to push them.",
+                unpushed.join(", ")
+            )));
+        }
+        return Ok(targets);
+    }
+    check_remote_branches(&targets, opts.force)?;
+    push_branches(&targets)?;
+    Ok(targets)
+}
+
+/// Catch branches someone else pushed to since we last fetched them, which
+/// `--force-with-lease` would reject, and settle each one before pushing:
+/// rebase the local branch onto the remote one, overwrite the remote (always
+/// with `force`), or stop.
+///
+/// Branches that changed are fetched, so the lease checks against what was
+/// settled here.
+pub fn check_remote_branches(targets: &[(String, SubmitTarget)], force: bool) -> StackResult<()> {
+    let current = get_current_branch()?;
+    for (branch, target) in targets {
+        let remote = &target.push_remote;
+        let tracking = format!("{}/{}", remote, branch);
+        let remote_ref = format!("refs/heads/{}", branch);
+        // Unreachable: let the push report it, and check the rest
+        let Some(listed) = try_command("git", &["ls-remote", remote, &remote_ref]) else {
+            eprintln!(
+                "Warning: could not check {} on {} for changes pushed by someone else",
+                branch, remote
+            );
+            continue;
+        };
+        let Some(remote_tip) = listed
+            .lines()
+            .filter_map(|l| l.split_once('\t'))
+            .find(|(_, name)| *name == remote_ref)
+            .map(|(sha, _)| sha.to_string())
+        else {
+            continue;
+        };
+        if rev_parse(&tracking).ok().as_ref() == Some(&remote_tip) {
+            continue;
+        }
+
+        git(&["fetch", "--quiet", remote, &remote_ref])?;
+        if is_ancestor(&remote_tip, branch)? {
+            continue;
+        }
+
+        let (ahead, behind) = ahead_behind(branch, &remote_tip)?;
+        if ahead == 0 {
+            report!(
+                "{} has {} commit(s) that {} doesn't; pushing would drop them.",
+                tracking,
+                behind,
+                branch
+            );
+        } else {
+            report!(
+                "{} has diverged from {}: the remote has {} commit(s) you don't, and you have {} it doesn't.",
+                branch,
+                tracking,
+                behind,
+                ahead
+            );
+        }
+
+        if force {
+            info!("Overwriting {}", tracking);
+            continue;
+        }
+        let answer = if interactive() {
+            prompt(&format!(
+                "[r]ebase {} onto {}, [o]verwrite {}, or [a]bort? ",
+                branch, tracking, tracking
+            ))?
+        } else {
+            String::new()
+        };
+        match answer.to_lowercase().as_str() {
+            "r" | "rebase" => rebase_onto_remote(branch, &tracking, &current)?,
+            "o" | "overwrite" => info!("Overwriting {}", tracking),
+            _ => {
+                return Err(StackError::Git(format!(
+                    "Not pushing: {} changed on the remote. Rerun with --force to overwrite it.",
+                    branch
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replay the remote's new commits on `branch` on top of it, skipping the
+/// ones it already has, then restack its children and go back to `current`.
+fn rebase_onto_remote(branch: &str, tracking: &str, current: &str) -> StackResult<()> {
+    ensure_clean_worktree("rebasing")?;
+    info!("Rebasing {} onto {}...", branch, tracking);
+    if git_streamed(&["rebase", branch, tracking]).is_err() {
+        let _ = git(&["rebase", "--abort"]);
+        let _ = git(&["checkout", "--quiet", current]);
+        return Err(StackError::Conflict(format!(
+            "{} and {} conflict. Merge them by hand, or rerun with --force to overwrite the remote.",
+            branch, tracking
+        )));
+    }
+    git(&["checkout", "--quiet", "-B", branch])?;
+
+    RestackPlan::above(&Stack::load()?, branch, &HashSet::new())?.execute(current)?;
+    if !current.is_empty() {
+        git(&["checkout", "--quiet", current])?;
+    }
+    Ok(())
+}
+
+/// Push every branch with one `git push` per remote, making each the
+/// upstream of its local branch so plain `git pull` and `git push` work. If
+/// the batch is rejected, retry branch by branch so one bad ref doesn't hide
+/// the rest.
+pub fn push_branches(targets: &[(String, SubmitTarget)]) -> StackResult<()> {
+    let mut by_remote: Vec<(&str, Vec<&str>)> = Vec::new();
+    for (branch, target) in targets {
+        match by_remote
+            .iter_mut()
+            .find(|(remote, _)| *remote == target.push_remote)
+        {
+            Some((_, branches)) => branches.push(branch),
+            None => by_remote.push((&target.push_remote, vec![branch])),
+        }
+    }
+
+    let mut failed = Vec::new();
+    for (remote, branches) in by_remote {
+        info!("Pushing {} to {}...", branches.join(", "), remote);
+
+        let mut push_args = vec!["push", "--force-with-lease", "--set-upstream", remote];
+        push_args.extend_from_slice(&branches);
+        let spinner = Spinner::start(&format!("Pushing to {}", remote));
+        let pushed = git_streamed(&push_args).is_ok();
+        drop(spinner);
+        if pushed {
+            for branch in branches {
+                emit("pushed", json!({ "branch": branch, "remote": remote }));
+            }
+            continue;
+        }
+        if branches.len() == 1 {
+            failed.push(branches[0]);
+            continue;
+        }
+
+        info!("Batch push failed, pushing branches individually...");
+        for branch in branches {
+            let _spinner = Spinner::start(&format!("Pushing {}", branch));
+            if git_streamed(&[
+                "push",
+                "--force-with-lease",
+                "--set-upstream",
+                remote,
+                branch,
+            ])
+            .is_err()
+            {
+                failed.push(branch);
+            } else {
+                emit("pushed", json!({ "branch": branch, "remote": remote }));
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(StackError::Git(format!(
+            "Failed to push: {}",
+            failed.join(", ")
+        )));
+    }
+    Ok(())
+}
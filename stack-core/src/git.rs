@@ -0,0 +1,884 @@
+//! Running git, and reading the repository through libgit2.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+
+use git2::{BranchType, Oid, Repository};
+
+use crate::config::setting;
+use crate::error::{StackError, StackResult, err};
+use crate::process::normalize_newlines;
+use crate::retry::{RetryPolicy, is_transient};
+use crate::ui::{Verbosity, verbosity, write_streamed};
+
+/// Echo a command to stderr at `--verbose`, the way a shell would run it.
+pub fn trace(cmd: &str, args: &[&str]) {
+    if verbosity() < Verbosity::Verbose {
+        return;
+    }
+    let mut line = format!("> {}", cmd);
+    for arg in args {
+        if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || "'\"$\\".contains(c)) {
+            line.push_str(&format!(" '{}'", arg.replace('\'', "'\\''")));
+        } else {
+            line.push(' ');
+            line.push_str(arg);
+        }
+    }
+    eprintln!("{}", line);
+}
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Keep off the network for the rest of the run (`--offline`).
+pub fn set_offline() {
+    OFFLINE.store(true, Ordering::Relaxed);
+}
+
+/// Whether to stay off the network: `--offline`, or `stack.offline = true`.
+/// Pushes, fetches and forge calls then fail (or, for best-effort lookups,
+/// come back empty) without being tried.
+pub fn offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed) || setting("offline").as_deref() == Some("true")
+}
+
+fn offline_error(cmd: &str, args: &[&str]) -> StackError {
+    err(&format!(
+        "Not running {} while offline (--offline or stack.offline)",
+        command_label(cmd, args)
+    ))
+}
+
+pub fn run_command(cmd: &str, args: &[&str]) -> StackResult<String> {
+    if talks_to_remote(cmd, args) && offline() {
+        return Err(offline_error(cmd, args));
+    }
+    let retry = talks_to_remote(cmd, args).then(RetryPolicy::from_config);
+    let mut attempt = 1;
+    loop {
+        trace(cmd, args);
+        let output = Command::new(cmd)
+            .args(args)
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::piped())
+            .stdout(Stdio::piped())
+            .output()
+            .map_err(|e| spawn_error(cmd, e))?;
+
+        if output.status.success() {
+            return Ok(normalize_newlines(&String::from_utf8_lossy(&output.stdout))
+                .trim()
+                .to_string());
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if let Some(retry) = retry
+            && retry.retries_after(attempt)
+            && is_transient(&stderr)
+        {
+            retry.wait(&command_label(cmd, args), &stderr, attempt, None);
+            attempt += 1;
+            continue;
+        }
+        eprintln!("{}", stderr);
+        return Err(command_failed(cmd, args));
+    }
+}
+
+/// Like `run_command`, for commands that take a while: their output reaches
+/// the terminal as it is written rather than after they exit. Stdout is still
+/// captured and returned.
+pub fn run_streamed(cmd: &str, args: &[&str]) -> StackResult<String> {
+    if talks_to_remote(cmd, args) && offline() {
+        return Err(offline_error(cmd, args));
+    }
+    let retry = talks_to_remote(cmd, args).then(RetryPolicy::from_config);
+    let quiet = verbosity() == Verbosity::Quiet;
+    let mut attempt = 1;
+    loop {
+        trace(cmd, args);
+        let mut child = Command::new(cmd)
+            .args(args)
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| spawn_error(cmd, e))?;
+
+        let stdout = child.stdout.take().map(|r| tee(r, false, quiet));
+        let stderr = child.stderr.take().map(|r| tee(r, true, quiet));
+        let status = child.wait()?;
+        let captured = stdout.map(|t| t.join().unwrap_or_default());
+        let errors = stderr.map(|t| t.join().unwrap_or_default());
+
+        if status.success() {
+            return Ok(
+                normalize_newlines(&String::from_utf8_lossy(&captured.unwrap_or_default()))
+                    .trim()
+                    .to_string(),
+            );
+        }
+        let errors = String::from_utf8_lossy(&errors.unwrap_or_default()).into_owned();
+        if let Some(retry) = retry
+            && retry.retries_after(attempt)
+            && is_transient(&errors)
+        {
+            retry.wait(&command_label(cmd, args), &errors, attempt, None);
+            attempt += 1;
+            continue;
+        }
+        // Quiet runs kept the output back; show it now that it matters
+        if quiet {
+            eprintln!("{}", errors);
+        }
+        return Err(command_failed(cmd, args));
+    }
+}
+
+/// `args` past any leading `-C <dir>` options.
+fn subcommand<'a>(args: &'a [&'a str]) -> &'a [&'a str] {
+    let mut rest = args;
+    while let ["-C", _, tail @ ..] = rest {
+        rest = tail;
+    }
+    rest
+}
+
+/// Whether `cmd` talks to a remote, which makes its failures worth retrying
+/// when they look transient: every `gh` call but `--version`, and git's
+/// transfers.
+fn talks_to_remote(cmd: &str, args: &[&str]) -> bool {
+    match cmd {
+        "gh" => args != ["--version"],
+        "git" => matches!(
+            subcommand(args).first(),
+            Some(&("push" | "fetch" | "pull" | "ls-remote"))
+        ),
+        _ => false,
+    }
+}
+
+/// `git push` or `gh pr create`, for naming a command in messages.
+fn command_label(cmd: &str, args: &[&str]) -> String {
+    let words: Vec<&str> = subcommand(args)
+        .iter()
+        .take_while(|a| !a.starts_with('-'))
+        .take(if cmd == "gh" { 2 } else { 1 })
+        .copied()
+        .collect();
+    format!("`{} {}`", cmd, words.join(" "))
+}
+
+/// Copy a child's pipe to our own stdout or stderr as it arrives (unless
+/// `quiet`), keeping what went by.
+fn tee(mut pipe: impl Read + Send + 'static, stderr: bool, quiet: bool) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut captured = Vec::new();
+        let mut buf = [0; 4096];
+        while let Ok(n) = pipe.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            if !quiet {
+                write_streamed(&buf[..n], stderr);
+            }
+            captured.extend_from_slice(&buf[..n]);
+        }
+        captured
+    })
+}
+
+fn spawn_error(cmd: &str, e: io::Error) -> StackError {
+    match e.kind() {
+        io::ErrorKind::NotFound if cmd != "git" => {
+            StackError::Forge(format!("{} is not installed", cmd))
+        }
+        _ => StackError::Io(e),
+    }
+}
+
+fn command_failed(cmd: &str, args: &[&str]) -> StackError {
+    let message = format!("Command failed: {} {}", cmd, args.join(" "));
+    if cmd == "git" {
+        StackError::Git(message)
+    } else {
+        StackError::Forge(message)
+    }
+}
+
+/// Like `run_command`, but failures are silent. For best-effort lookups.
+pub fn try_command(cmd: &str, args: &[&str]) -> Option<String> {
+    if talks_to_remote(cmd, args) && offline() {
+        return None;
+    }
+    trace(cmd, args);
+    let output = Command::new(cmd)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .stdout(Stdio::piped())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+    Some(
+        normalize_newlines(&String::from_utf8_lossy(&output.stdout))
+            .trim()
+            .to_string(),
+    )
+}
+
+pub fn git(args: &[&str]) -> StackResult<String> {
+    run_command("git", args)
+}
+
+/// `git` through `run_streamed`. Transfers get `--progress`, which git
+/// otherwise drops once its stderr is a pipe.
+pub fn git_streamed(args: &[&str]) -> StackResult<String> {
+    let mut args = args.to_vec();
+    if matches!(args.first(), Some(&("push" | "fetch" | "pull"))) && io::stderr().is_terminal() {
+        args.insert(1, "--progress");
+    }
+    run_streamed("git", &args)
+}
+
+pub fn git_passthrough(args: &[&str]) -> StackResult<()> {
+    trace("git", args);
+    let status = Command::new("git")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()?;
+
+    if !status.success() {
+        return Err(StackError::Git("Git command failed".to_string()));
+    }
+    Ok(())
+}
+
+// Local reads go through libgit2 instead of spawning `git`. The repository is
+// reopened per call so config written by earlier steps is always visible.
+pub fn open_repo() -> StackResult<Repository> {
+    Ok(Repository::open_from_env()?)
+}
+
+/// Per-repository state directory (`.git/stack`), shared by all worktrees.
+pub fn stack_dir() -> StackResult<PathBuf> {
+    let dir = open_repo()?.commondir().join("stack");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Name of the checked-out branch, or an empty string on detached HEAD
+/// (matching `git branch --show-current`).
+pub fn get_current_branch() -> StackResult<String> {
+    let repo = open_repo()?;
+    let head = repo.find_reference("HEAD")?;
+    Ok(head
+        .symbolic_target()?
+        .and_then(|t| t.strip_prefix("refs/heads/"))
+        .unwrap_or_default()
+        .to_string())
+}
+
+/// Puts the user back where a command started if it fails partway, having
+/// checked other branches out to rebase or land them. A command that
+/// stopped on a conflict is left where it stopped, with a note of where it
+/// began, so `stack continue` can finish it. `disarm` once it succeeds.
+pub struct CheckoutGuard {
+    branch: String,
+    head: Option<String>,
+    armed: bool,
+}
+
+impl CheckoutGuard {
+    pub fn new() -> StackResult<Self> {
+        Ok(CheckoutGuard {
+            branch: get_current_branch()?,
+            head: rev_parse("HEAD").ok(),
+            armed: true,
+        })
+    }
+
+    pub fn disarm(mut self) {
+        self.armed = false;
+    }
+
+    fn start(&self) -> String {
+        match &self.head {
+            Some(head) if self.branch.is_empty() => format!("{} (detached)", &head[..7]),
+            _ => self.branch.clone(),
+        }
+    }
+
+    fn restore(&self) {
+        let Ok(current) = get_current_branch() else {
+            return;
+        };
+        let here = if current.is_empty() {
+            rev_parse("HEAD").ok() == self.head
+        } else {
+            current == self.branch
+        };
+        if here {
+            return;
+        }
+        let current = if current.is_empty() {
+            "a detached HEAD".to_string()
+        } else {
+            current
+        };
+        if let Ok(Some(op)) = operation_in_progress() {
+            eprintln!(
+                "Left on {} for the {} in progress; you started on {}.",
+                current,
+                op,
+                self.start()
+            );
+            return;
+        }
+        let restored = if self.branch.is_empty() {
+            self.head
+                .as_ref()
+                .is_some_and(|head| git(&["checkout", "--quiet", "--detach", head]).is_ok())
+        } else {
+            branch_exists(&self.branch).unwrap_or(false)
+                && git(&["checkout", "--quiet", &self.branch]).is_ok()
+        };
+        if restored {
+            eprintln!("Returned to {}, where this started.", self.start());
+        } else {
+            eprintln!(
+                "Warning: could not return to {}; left on {}",
+                self.start(),
+                current
+            );
+        }
+    }
+}
+
+impl Drop for CheckoutGuard {
+    fn drop(&mut self) {
+        if self.armed {
+            self.restore();
+        }
+    }
+}
+
+/// The checked-out branch, or an error saying that `stack <command>` needs
+/// one when HEAD is detached.
+pub fn require_current_branch(command: &str) -> StackResult<String> {
+    let branch = get_current_branch()?;
+    if branch.is_empty() {
+        let at = rev_parse("HEAD").map(|oid| format!(" at {}", &oid[..7]));
+        return Err(err(&format!(
+            "HEAD is detached{}, and `stack {}` needs a branch checked out (`git switch <branch>`).",
+            at.unwrap_or_default(),
+            command
+        )));
+    }
+    Ok(branch)
+}
+
+pub fn git_config(key: &str) -> Option<String> {
+    let config = open_repo().ok()?.config().ok()?;
+    config.get_string(key).ok().filter(|v| !v.is_empty())
+}
+
+/// A boolean git config key, in any of the spellings git takes.
+pub fn git_config_bool(key: &str) -> Option<bool> {
+    let config = open_repo().ok()?.config().ok()?;
+    config.get_bool(key).ok()
+}
+
+/// Every value of a multi-valued key (`git config --add`), in order.
+pub fn git_config_all(key: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let Some(config) = open_repo().ok().and_then(|r| r.config().ok()) else {
+        return values;
+    };
+    if let Ok(mut entries) = config.multivar(key, None) {
+        while let Some(Ok(entry)) = entries.next() {
+            if let Ok(value) = entry.value() {
+                values.push(value.to_string());
+            }
+        }
+    }
+    values
+}
+
+pub fn set_config(key: &str, value: &str) -> StackResult<()> {
+    open_repo()?.config()?.set_str(key, value)?;
+    Ok(())
+}
+
+pub fn unset_config(key: &str) -> StackResult<()> {
+    open_repo()?.config()?.remove(key)?;
+    Ok(())
+}
+
+pub fn local_branches() -> StackResult<Vec<String>> {
+    let repo = open_repo()?;
+    let mut names = Vec::new();
+    for branch in repo.branches(Some(git2::BranchType::Local))? {
+        let (branch, _) = branch?;
+        if let Some(name) = branch.name()? {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// A local branch's tip as listed by `git for-each-ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchHead {
+    pub oid: String,
+    pub short_oid: String,
+    pub subject: String,
+    /// `remote/branch` it tracks, if any.
+    pub upstream: Option<String>,
+    /// Committer date of the tip, in seconds since the epoch.
+    pub committed: u64,
+}
+
+impl BranchHead {
+    /// `<short sha> <subject>`, as `commit_summary` prints it.
+    pub fn summary(&self) -> String {
+        format!("{} {}", self.short_oid, self.subject)
+    }
+}
+
+/// Every local branch's tip, subject and upstream from one `for-each-ref`.
+pub fn branch_heads() -> StackResult<HashMap<String, BranchHead>> {
+    let out = git(&[
+        "for-each-ref",
+        "--format=%(refname)%00%(objectname)%00%(objectname:short)%00%(upstream:short)%00%(committerdate:unix)%00%(subject)",
+        "refs/heads",
+    ])?;
+    let mut heads = HashMap::new();
+    for line in out.lines() {
+        let fields: Vec<&str> = line.splitn(6, '\0').collect();
+        let [refname, oid, short_oid, upstream, committed, subject] = fields[..] else {
+            continue;
+        };
+        let Some(name) = refname.strip_prefix("refs/heads/") else {
+            continue;
+        };
+        heads.insert(
+            name.to_string(),
+            BranchHead {
+                oid: oid.to_string(),
+                short_oid: short_oid.to_string(),
+                subject: subject.to_string(),
+                upstream: (!upstream.is_empty()).then(|| upstream.to_string()),
+                committed: committed.parse().unwrap_or(0),
+            },
+        );
+    }
+    Ok(heads)
+}
+
+pub fn rev_parse(rev: &str) -> StackResult<String> {
+    let repo = open_repo()?;
+    Ok(repo
+        .revparse_single(rev)?
+        .peel_to_commit()?
+        .id()
+        .to_string())
+}
+
+/// Abbreviated hash and subject of the commit `rev` points at.
+pub fn commit_summary(rev: &str) -> StackResult<String> {
+    let repo = open_repo()?;
+    let object = repo.revparse_single(rev)?;
+    let short = object.short_id()?;
+    let commit = object.peel_to_commit()?;
+    Ok(format!(
+        "{} {}",
+        short.as_str()?,
+        commit.summary()?.unwrap_or_default()
+    ))
+}
+
+pub fn commit_message(rev: &str) -> StackResult<String> {
+    let repo = open_repo()?;
+    let commit = repo.revparse_single(rev)?.peel_to_commit()?;
+    Ok(commit.message()?.trim().to_string())
+}
+
+/// Messages of the commits in `base..branch`, oldest first.
+pub fn commit_messages(base: &str, branch: &str) -> StackResult<Vec<String>> {
+    let repo = open_repo()?;
+    let mut messages = Vec::new();
+    for oid in commit_ids(base, branch)? {
+        let commit = repo.find_commit(oid)?;
+        messages.push(commit.message()?.to_string());
+    }
+    Ok(messages)
+}
+
+/// Commits on `branch` that are not on `base`, oldest first.
+pub fn commit_ids(base: &str, branch: &str) -> StackResult<Vec<Oid>> {
+    let repo = open_repo()?;
+    let mut walk = repo.revwalk()?;
+    walk.push(repo.revparse_single(branch)?.peel_to_commit()?.id())?;
+    walk.hide(repo.revparse_single(base)?.peel_to_commit()?.id())?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+    Ok(walk.collect::<Result<_, _>>()?)
+}
+
+/// Whether a commit message marks a commit for `git rebase --autosquash`.
+pub fn is_fixup(message: &str) -> bool {
+    ["fixup! ", "squash! ", "amend! "]
+        .iter()
+        .any(|p| message.starts_with(p))
+}
+
+/// Equivalent of `git merge-base --is-ancestor ancestor rev`.
+pub fn is_ancestor(ancestor: &str, rev: &str) -> StackResult<bool> {
+    let repo = open_repo()?;
+    let ancestor = repo.revparse_single(ancestor)?.peel_to_commit()?.id();
+    let rev = repo.revparse_single(rev)?.peel_to_commit()?.id();
+    Ok(ancestor == rev || repo.graph_descendant_of(rev, ancestor)?)
+}
+
+/// Remote used for `branch`: its configured upstream remote, then the
+/// `stack.remote` setting, then `origin`.
+pub fn get_remote(branch: &str) -> String {
+    git_config(&format!("branch.{}.remote", branch))
+        .or_else(|| setting("remote"))
+        .unwrap_or_else(|| "origin".to_string())
+}
+
+pub fn remote_url(remote: &str) -> StackResult<String> {
+    Ok(open_repo()?.find_remote(remote)?.url()?.to_string())
+}
+
+/// `OWNER/REPO` for a GitHub remote, parsed from its URL
+/// (`git@github.com:owner/repo.git` or `https://github.com/owner/repo`).
+pub fn remote_slug(remote: &str) -> StackResult<String> {
+    let url = remote_url(remote)?;
+    let path = url.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let path = match path.split_once("://") {
+        Some((_, rest)) => rest.split_once('/').map(|(_, p)| p),
+        None => path.split_once(':').map(|(_, p)| p),
+    };
+
+    let segments: Vec<&str> = path.unwrap_or("").split('/').collect();
+    if segments.len() < 2 || segments.iter().any(|s| s.is_empty()) {
+        return Err(StackError::Forge(format!(
+            "Cannot determine repository from {} URL: {}",
+            remote, url
+        )));
+    }
+    let n = segments.len();
+    Ok(format!("{}/{}", segments[n - 2], segments[n - 1]))
+}
+
+/// The remote-tracking branch `branch` follows: its upstream, which `submit`
+/// sets when it pushes, else `<remote>/<branch>`.
+pub fn tracking_branch(branch: &str, remote: &str) -> String {
+    open_repo()
+        .ok()
+        .and_then(|repo| {
+            let name = repo
+                .branch_upstream_name(&format!("refs/heads/{}", branch))
+                .ok()?;
+            std::str::from_utf8(&name)
+                .ok()?
+                .strip_prefix("refs/remotes/")
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| format!("{}/{}", remote, branch))
+}
+
+/// Commits on `branch` not on `base`, and on `base` not on `branch`.
+pub fn ahead_behind(branch: &str, base: &str) -> StackResult<(usize, usize)> {
+    let repo = open_repo()?;
+    let branch = repo.revparse_single(branch)?.peel_to_commit()?.id();
+    let base = repo.revparse_single(base)?.peel_to_commit()?.id();
+    Ok(repo.graph_ahead_behind(branch, base)?)
+}
+
+/// Files changed and lines added and removed between two commits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffStat {
+    pub files: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl DiffStat {
+    /// Read a `--shortstat` line such as ` 2 files changed, 5 insertions(+)`.
+    fn parse(line: &str) -> Self {
+        let mut stat = DiffStat::default();
+        for part in line.split(',') {
+            let mut words = part.split_whitespace();
+            let (Some(n), Some(what)) = (words.next(), words.next()) else {
+                continue;
+            };
+            let n = n.parse().unwrap_or(0);
+            if what.starts_with("file") {
+                stat.files = n;
+            } else if what.starts_with("insertion") {
+                stat.insertions = n;
+            } else if what.starts_with("deletion") {
+                stat.deletions = n;
+            }
+        }
+        stat
+    }
+}
+
+impl std::fmt::Display for DiffStat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.files == 0 {
+            return write!(f, "no changes");
+        }
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        write!(
+            f,
+            "{} file{} changed, {} insertion{}(+), {} deletion{}(-)",
+            self.files,
+            plural(self.files),
+            self.insertions,
+            plural(self.insertions),
+            self.deletions,
+            plural(self.deletions)
+        )
+    }
+}
+
+/// The `DiffStat` from `base` to `rev` for each pair, in order. One
+/// `git diff-tree --stdin` run answers them all, where `git diff
+/// --shortstat` would take a process per pair.
+pub fn diff_stats(pairs: &[(String, String)]) -> StackResult<Vec<DiffStat>> {
+    if pairs.is_empty() {
+        return Ok(Vec::new());
+    }
+    // diff-tree reads object ids, not revisions
+    let repo = open_repo()?;
+    let mut input = String::new();
+    for (base, rev) in pairs {
+        let base = repo.revparse_single(base)?.peel_to_commit()?.id();
+        let rev = repo.revparse_single(rev)?.peel_to_commit()?.id();
+        input.push_str(&format!("{} {}\n", rev, base));
+    }
+
+    let args = ["diff-tree", "--stdin", "--always", "--shortstat"];
+    trace("git", &args);
+    let mut child = Command::new("git")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| spawn_error("git", e))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // Written from a thread so a full stdout pipe can't stall both sides
+    let writer = thread::spawn(move || io::Write::write_all(&mut stdin, input.as_bytes()));
+    let output = child.wait_with_output()?;
+    let _ = writer.join();
+    if !output.status.success() {
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+        return Err(command_failed("git", &args));
+    }
+
+    // `--always` gives every pair an id line, followed by its shortstat
+    // unless the trees are the same
+    let mut stats: Vec<DiffStat> = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if line.starts_with(' ') {
+            if let Some(last) = stats.last_mut() {
+                *last = DiffStat::parse(line);
+            }
+        } else if !line.is_empty() {
+            stats.push(DiffStat::default());
+        }
+    }
+    Ok(stats)
+}
+
+/// When the first commit in `base..rev` was authored, as a Unix time, for
+/// each pair in order; `None` for an empty range. The repository is opened
+/// once and walked here, where `git log` would take a process per pair.
+pub fn first_commit_times(pairs: &[(String, String)]) -> StackResult<Vec<Option<u64>>> {
+    let repo = open_repo()?;
+    let mut times = Vec::new();
+    for (base, rev) in pairs {
+        let mut walk = repo.revwalk()?;
+        walk.push(repo.revparse_single(rev)?.peel_to_commit()?.id())?;
+        walk.hide(repo.revparse_single(base)?.peel_to_commit()?.id())?;
+        walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+        let first = match walk.next() {
+            Some(oid) => Some(repo.find_commit(oid?)?.author().when().seconds()),
+            None => None,
+        };
+        times.push(first.and_then(|t| u64::try_from(t).ok()));
+    }
+    Ok(times)
+}
+
+/// What a trial merge made: its tree, and the files that conflicted.
+pub struct TrialMerge {
+    pub tree: String,
+    pub conflicts: Vec<String>,
+}
+
+/// Merge `theirs` into `ours` from `base` in memory, touching no worktree,
+/// index or ref, the way rebasing `base..theirs` onto `ours` would come
+/// out. Conflicted files take `theirs`' side in the tree, so later trial
+/// merges on top of it still have something to go on.
+pub fn trial_merge(base: &str, ours: &str, theirs: &str) -> StackResult<TrialMerge> {
+    let repo = open_repo()?;
+    let tree = |rev: &str| repo.revparse_single(rev)?.peel_to_tree();
+    let mut index = repo.merge_trees(&tree(base)?, &tree(ours)?, &tree(theirs)?, None)?;
+
+    let mut conflicts = Vec::new();
+    let mut resolved = Vec::new();
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        let keep = conflict.their.is_some();
+        let Some(entry) = conflict.their.or(conflict.our).or(conflict.ancestor) else {
+            continue;
+        };
+        let path = String::from_utf8_lossy(&entry.path).into_owned();
+        conflicts.push(path.clone());
+        resolved.push((path, entry, keep));
+    }
+    for (path, mut entry, keep) in resolved {
+        index.conflict_remove(Path::new(&path))?;
+        if keep {
+            // Back to stage 0, as a resolved entry
+            entry.flags &= !0x3000;
+            index.add(&entry)?;
+        }
+    }
+    Ok(TrialMerge {
+        tree: index.write_tree_to(&repo)?.to_string(),
+        conflicts,
+    })
+}
+
+/// The git command left unfinished in the worktree (`rebase`, `merge`, ...),
+/// as used in `git <command> --continue`.
+pub fn operation_in_progress() -> StackResult<Option<&'static str>> {
+    use git2::RepositoryState::*;
+    Ok(match open_repo()?.state() {
+        Clean | Bisect => None,
+        Merge => Some("merge"),
+        Revert | RevertSequence => Some("revert"),
+        CherryPick | CherryPickSequence => Some("cherry-pick"),
+        Rebase | RebaseInteractive | RebaseMerge => Some("rebase"),
+        ApplyMailbox | ApplyMailboxOrRebase => Some("am"),
+    })
+}
+
+/// Number of changed (staged or unstaged) and untracked files.
+pub fn worktree_changes() -> StackResult<(usize, usize)> {
+    let repo = open_repo()?;
+    let mut opts = git2::StatusOptions::new();
+    opts.include_untracked(true).include_ignored(false);
+
+    let statuses = repo.statuses(Some(&mut opts))?;
+    let untracked = statuses
+        .iter()
+        .filter(|e| e.status() == git2::Status::WT_NEW)
+        .count();
+    Ok((statuses.len() - untracked, untracked))
+}
+
+/// `core.hooksPath` made absolute, when it is relative, for git commands
+/// run in another worktree: a relative path names a directory in the
+/// worktree git runs in, and a fresh one lacks untracked hooks such as
+/// husky's.
+pub fn absolute_hooks_path() -> StackResult<Option<String>> {
+    let Some(path) = git_config("core.hooksPath") else {
+        return Ok(None);
+    };
+    if path.starts_with('~') || Path::new(&path).is_absolute() {
+        return Ok(None);
+    }
+    Ok(Some(repo_root()?.join(path).to_string_lossy().into_owned()))
+}
+
+/// Top of the worktree (the git dir itself for bare repositories).
+pub fn repo_root() -> StackResult<PathBuf> {
+    let repo = open_repo()?;
+    Ok(repo.workdir().unwrap_or(repo.commondir()).to_path_buf())
+}
+
+/// Worktrees other than this one, keyed by the branch each has checked out.
+pub fn other_worktrees() -> StackResult<HashMap<String, PathBuf>> {
+    let here = repo_root()?.canonicalize()?;
+    let mut worktrees = HashMap::new();
+    let mut path: Option<PathBuf> = None;
+    for line in git(&["worktree", "list", "--porcelain"])?.lines() {
+        if let Some(p) = line.strip_prefix("worktree ") {
+            path = Some(PathBuf::from(p));
+        } else if let Some(branch) = line.strip_prefix("branch refs/heads/")
+            && let Some(p) = &path
+            && p.canonicalize().ok().as_ref() != Some(&here)
+        {
+            worktrees.insert(branch.to_string(), p.clone());
+        }
+    }
+    Ok(worktrees)
+}
+
+/// Whether tracked files in the worktree at `dir` have uncommitted changes.
+pub fn worktree_is_dirty(dir: &Path) -> StackResult<bool> {
+    let dir = dir.to_string_lossy();
+    let status = git(&["-C", &dir, "status", "--porcelain", "--untracked-files=no"])?;
+    Ok(!status.is_empty())
+}
+
+/// Fail with `DirtyTree` when tracked files have uncommitted changes.
+pub fn ensure_clean_worktree(action: &str) -> StackResult<()> {
+    let (changed, _) = worktree_changes()?;
+    if changed > 0 {
+        return Err(StackError::DirtyTree(format!(
+            "You have uncommitted changes; commit or stash them before {}",
+            action
+        )));
+    }
+    Ok(())
+}
+
+/// Whether git is new enough (2.38) for `rebase --update-refs`.
+pub fn git_supports_update_refs() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *SUPPORTED.get_or_init(|| {
+        let version = try_command("git", &["--version"]).unwrap_or_default();
+        let mut parts = version
+            .trim_start_matches("git version ")
+            .split('.')
+            .map(|p| p.parse::<u32>().unwrap_or(0));
+        let major = parts.next().unwrap_or(0);
+        let minor = parts.next().unwrap_or(0);
+        (major, minor) >= (2, 38)
+    })
+}
+
+/// Whether the index differs from HEAD.
+pub fn has_staged_changes() -> StackResult<bool> {
+    let repo = open_repo()?;
+    let head = repo.head()?.peel_to_tree()?;
+    let diff = repo.diff_tree_to_index(Some(&head), None, None)?;
+    Ok(diff.deltas().len() > 0)
+}
+
+/// Whether `name` is a local branch (not a tag, commit or remote ref).
+pub fn branch_exists(name: &str) -> StackResult<bool> {
+    Ok(open_repo()?.find_branch(name, BranchType::Local).is_ok())
+}
@@ -0,0 +1,45 @@
+//! User hooks around `submit` and `land`.
+
+use std::process::Stdio;
+
+use crate::config::setting_all;
+use crate::error::{StackResult, err};
+use crate::git::repo_root;
+use crate::info;
+use crate::process::{shell_command, shell_path};
+
+/// Run the `name` hook: the `.stack/hooks/<name>` script at the top of the
+/// worktree, then each `hook.<name>` command from config. Hooks see the
+/// branches involved in `STACK_BRANCHES` and as arguments. A failing hook is
+/// an error; callers skip hooks entirely for `--no-verify`.
+pub fn run_hook(name: &str, branches: &[String]) -> StackResult<()> {
+    let root = repo_root()?;
+
+    let mut commands = Vec::new();
+    let script = root.join(".stack").join("hooks").join(name);
+    if script.is_file() {
+        commands.push(shell_path(&script));
+    }
+    commands.extend(setting_all(&format!("hook.{}", name)));
+
+    for command in commands {
+        info!("Running {} hook: {}", name, command);
+        let status = shell_command(&command, name)
+            .args(branches)
+            .current_dir(&root)
+            .env("STACK_HOOK", name)
+            .env("STACK_BRANCHES", branches.join(" "))
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()?;
+
+        if !status.success() {
+            return Err(err(&format!(
+                "{} hook failed (use --no-verify to skip hooks)",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
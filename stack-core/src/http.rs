@@ -0,0 +1,124 @@
+//! Small JSON-over-HTTP helpers for the REST forges.
+
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::error::{StackError, StackResult};
+use crate::git::{offline, trace};
+use crate::retry::{RetryPolicy, is_transient};
+
+/// Send a JSON request and parse the JSON response (`Null` when empty).
+/// Non-2xx responses become errors carrying the response body. Dropped
+/// connections, 5xx responses and rate limits are retried per `RetryPolicy`,
+/// waiting as long as a `Retry-After` header asks (up to a minute).
+pub fn http_json(method: &str, url: &str, auth: &str, body: Option<&Value>) -> StackResult<Value> {
+    if offline() {
+        return Err(StackError::Forge(format!(
+            "Not calling {} while offline (--offline or stack.offline)",
+            url
+        )));
+    }
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let retry = RetryPolicy::from_config();
+    let what = format!("{} {}", method, url);
+    let mut attempt = 1;
+
+    let (status, text) = loop {
+        trace(method, &[url]);
+        let request = ureq::http::Request::builder()
+            .method(method)
+            .uri(url)
+            .header("Authorization", auth)
+            .header("Accept", "application/json")
+            .header("User-Agent", "stack");
+        let response = match body {
+            Some(body) => agent.run(
+                request
+                    .header("Content-Type", "application/json")
+                    .body(serde_json::to_vec(body)?)?,
+            ),
+            None => agent.run(request.body(())?),
+        };
+
+        let response = match response {
+            Ok(response) => response,
+            Err(
+                e @ (ureq::Error::Io(_)
+                | ureq::Error::Timeout(_)
+                | ureq::Error::HostNotFound
+                | ureq::Error::ConnectionFailed
+                | ureq::Error::BodyStalled),
+            ) if retry.retries_after(attempt) => {
+                retry.wait(&what, &e.to_string(), attempt, None);
+                attempt += 1;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get("Retry-After")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .map(|secs: u64| Duration::from_secs(secs.min(60)));
+        let text = response.into_body().read_to_string()?;
+        let throttled = status.as_u16() == 429
+            || (status.as_u16() == 403 && is_transient(&text))
+            || status.is_server_error();
+        if throttled && retry.retries_after(attempt) {
+            retry.wait(&what, status.as_str(), attempt, retry_after);
+            attempt += 1;
+            continue;
+        }
+        break (status, text);
+    };
+
+    if !status.is_success() {
+        return Err(StackError::Forge(format!(
+            "{} {} failed with {}: {}",
+            method, url, status, text
+        )));
+    }
+    if text.trim().is_empty() {
+        return Ok(Value::Null);
+    }
+    Ok(serde_json::from_str(&text)?)
+}
+
+pub fn percent_encode(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+pub fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
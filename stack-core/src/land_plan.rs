@@ -0,0 +1,141 @@
+//! The plan of a `stack land` in progress, saved as it goes so a land that
+//! stops halfway (a rejected push, a failed merge) can be finished with
+//! `stack land --continue` instead of being worked out again from a stack
+//! it has already half changed.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+use crate::config::LandStrategy;
+use crate::error::{StackResult, err};
+use crate::git::stack_dir;
+
+const PLAN_FILE: &str = "land-plan.json";
+
+/// Everything `land` decided before it started changing things, and how
+/// far it got.
+pub struct LandPlan {
+    /// Bottom-up.
+    pub branches: Vec<String>,
+    /// Squash commit message per branch when squash-landing.
+    pub messages: Vec<String>,
+    pub strategy: LandStrategy,
+    /// Whether trunk is checked out in the current worktree for the merges,
+    /// rather than in a temporary one.
+    pub in_place: bool,
+    /// Delete landed branches and their metadata locally.
+    pub delete_local: bool,
+    /// Delete landed branches on the remote.
+    pub delete_remote: bool,
+    /// Add a `Signed-off-by` trailer to the commits landing makes.
+    pub signoff: bool,
+    /// Run the `post-land` hook at the end.
+    pub verify: bool,
+    /// With `--merge-queue`, how long each PR may take to get through the
+    /// queue. `None` merges locally (or through `Forge::merge`).
+    pub merge_queue: Option<Duration>,
+    /// Each branch's children before landing, when they all still existed.
+    pub children: HashMap<String, Vec<String>>,
+    /// Branches merged into trunk so far.
+    pub merged: Vec<String>,
+    /// Branches merged and cleaned up.
+    pub done: Vec<String>,
+}
+
+fn plan_path() -> StackResult<PathBuf> {
+    Ok(stack_dir()?.join(PLAN_FILE))
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|a| {
+            a.iter()
+                .filter_map(|v| v.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl LandPlan {
+    /// The saved plan, if a land is in progress.
+    pub fn load() -> StackResult<Option<Self>> {
+        let text = match fs::read_to_string(plan_path()?) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let plan: Value = serde_json::from_str(&text)
+            .map_err(|e| err(&format!("Unreadable land plan ({}): {}", PLAN_FILE, e)))?;
+
+        let strategy = plan["strategy"]
+            .as_str()
+            .and_then(LandStrategy::from_name)
+            .ok_or_else(|| {
+                err(&format!(
+                    "Unreadable land plan ({}): no strategy",
+                    PLAN_FILE
+                ))
+            })?;
+        let children = plan["children"]
+            .as_object()
+            .map(|o| o.iter().map(|(k, v)| (k.clone(), strings(v))).collect())
+            .unwrap_or_default();
+        let flag = |key: &str| plan[key].as_bool().unwrap_or(false);
+
+        Ok(Some(LandPlan {
+            branches: strings(&plan["branches"]),
+            messages: strings(&plan["messages"]),
+            strategy,
+            in_place: flag("in_place"),
+            delete_local: flag("delete_local"),
+            delete_remote: flag("delete_remote"),
+            signoff: flag("signoff"),
+            verify: flag("verify"),
+            merge_queue: plan["merge_queue_timeout"]
+                .as_u64()
+                .map(Duration::from_secs),
+            children,
+            merged: strings(&plan["merged"]),
+            done: strings(&plan["done"]),
+        }))
+    }
+
+    /// Write the plan out, progress included.
+    pub fn save(&self) -> StackResult<()> {
+        let plan = json!({
+            "branches": self.branches,
+            "messages": self.messages,
+            "strategy": self.strategy.name(),
+            "in_place": self.in_place,
+            "delete_local": self.delete_local,
+            "delete_remote": self.delete_remote,
+            "signoff": self.signoff,
+            "verify": self.verify,
+            "merge_queue_timeout": self.merge_queue.map(|t| t.as_secs()),
+            "children": self.children,
+            "merged": self.merged,
+            "done": self.done,
+        });
+        fs::write(plan_path()?, plan.to_string())?;
+        Ok(())
+    }
+
+    /// Forget the plan, once the land finished or was given up.
+    pub fn clear() -> StackResult<()> {
+        match fs::remove_file(plan_path()?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// `branch`'s children before landing.
+    pub fn children(&self, branch: &str) -> &[String] {
+        self.children.get(branch).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
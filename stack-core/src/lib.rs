@@ -0,0 +1,30 @@
+//! The library behind the `stack` CLI: branch metadata, git plumbing, code
+//! review hosts, and the restack engine, usable from other tools.
+
+pub mod absorb;
+pub mod alias;
+pub mod autostash;
+pub mod config;
+pub mod drafts;
+pub mod engine;
+pub mod error;
+pub mod events;
+pub mod footer;
+pub mod forge;
+pub mod git;
+pub mod hooks;
+pub mod http;
+pub mod land_plan;
+pub mod limits;
+pub mod lock;
+pub mod metadata;
+pub mod naming;
+pub mod per_commit;
+pub mod pr;
+pub mod process;
+pub mod recent;
+pub mod restack_progress;
+pub mod retry;
+pub mod snapshot;
+pub mod test_results;
+pub mod ui;
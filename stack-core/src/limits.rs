@@ -0,0 +1,75 @@
+//! Size limits on submitted branches, so stacks stay a pile of small PRs:
+//! `stack.max-files` caps the files a branch changes and `stack.max-lines`
+//! the lines it adds and removes, both against its parent. Going over only
+//! warns unless `stack.size-limit = block`.
+
+use crate::config::setting;
+use crate::error::{StackResult, err};
+use crate::git::diff_stats;
+use crate::metadata::own_commits_base;
+
+/// Check each of `branches` against the configured limits, warning about
+/// the ones over them, or failing in `block` mode. Callers skip this for
+/// `--no-verify`, as they do hooks.
+pub fn check_size_limits(branches: &[String], command: &str) -> StackResult<()> {
+    let limit = |key: &str| -> StackResult<Option<usize>> {
+        setting(key)
+            .map(|v| {
+                v.parse()
+                    .map_err(|_| err(&format!("stack.{} must be a number, not '{}'", key, v)))
+            })
+            .transpose()
+    };
+    let (max_files, max_lines) = (limit("max-files")?, limit("max-lines")?);
+    if max_files.is_none() && max_lines.is_none() {
+        return Ok(());
+    }
+    let block = match setting("size-limit").as_deref() {
+        None | Some("warn") => false,
+        Some("block") => true,
+        Some(other) => {
+            return Err(err(&format!(
+                "Unknown stack.size-limit '{}'; use warn or block",
+                other
+            )));
+        }
+    };
+
+    let pairs: Vec<(String, String)> = branches
+        .iter()
+        .map(|b| (own_commits_base(b), b.clone()))
+        .collect();
+    let mut problems = Vec::new();
+    for (branch, stat) in branches.iter().zip(diff_stats(&pairs)?) {
+        let lines = stat.insertions + stat.deletions;
+        let mut over = Vec::new();
+        if let Some(max) = max_files.filter(|max| stat.files > *max) {
+            over.push(format!("{} files (max {})", stat.files, max));
+        }
+        if let Some(max) = max_lines.filter(|max| lines > *max) {
+            over.push(format!("{} lines changed (max {})", lines, max));
+        }
+        if !over.is_empty() {
+            problems.push(format!("{}: {}", branch, over.join(", ")));
+        }
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    let advice = "Consider splitting them into smaller stacked branches.";
+    if block {
+        return Err(err(&format!(
+            "Not running `stack {}`: over the size limit:\n  {}\n{} (use --no-verify to skip this check)",
+            command,
+            problems.join("\n  "),
+            advice
+        )));
+    }
+    eprintln!(
+        "Warning: over the size limit:\n  {}\n{}",
+        problems.join("\n  "),
+        advice
+    );
+    Ok(())
+}
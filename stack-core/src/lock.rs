@@ -0,0 +1,81 @@
+//! A lock file that keeps two mutating stack commands, say a `restack` in
+//! each of two terminals, from interleaving their checkouts and rebases.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
+
+use crate::error::{StackResult, err};
+use crate::git::open_repo;
+
+/// Set for the commands `stack foreach` runs, which may call stack while the
+/// lock is held on their behalf.
+pub const LOCK_HELD_ENV: &str = "STACK_LOCK_HELD";
+
+/// Whether `command` only reads state: it takes no lock, and runs even in
+/// the middle of an unfinished rebase.
+pub fn is_read_only(command: &str) -> bool {
+    matches!(
+        command,
+        "log" | "status" | "stats" | "diff" | "config" | "snapshots" | "serve"
+    )
+}
+
+/// `.git/stack.lock`, shared by every worktree of the repository.
+pub fn lock_path() -> StackResult<PathBuf> {
+    Ok(open_repo()?.commondir().join("stack.lock"))
+}
+
+/// Held while a mutating command runs; the lock file goes when it drops.
+pub struct Lock {
+    path: Option<PathBuf>,
+}
+
+impl Lock {
+    /// Take the lock for `command`, or fail naming whoever holds it.
+    pub fn acquire(command: &str) -> StackResult<Self> {
+        if env::var_os(LOCK_HELD_ENV).is_some() {
+            return Ok(Lock { path: None });
+        }
+
+        let path = lock_path()?;
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                writeln!(file, "{} {}", process::id(), command)?;
+                Ok(Lock { path: Some(path) })
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let holder = fs::read_to_string(&path).unwrap_or_default();
+                let holder = match holder.trim().split_once(' ') {
+                    Some((pid, command)) => format!(" (`stack {}`, pid {})", command, pid),
+                    None => String::new(),
+                };
+                Err(err(&format!(
+                    "Another stack operation is in progress{}. If it is no longer running, rerun with --force-unlock.",
+                    holder
+                )))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Remove a lock left behind by a stack process that died. Returns whether
+/// there was one.
+pub fn force_unlock() -> StackResult<bool> {
+    match fs::remove_file(lock_path()?) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
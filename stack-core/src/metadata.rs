@@ -0,0 +1,356 @@
+//! Per-branch stack metadata: the parent a branch is stacked on, the parent
+//! commit it was last rebased onto, its place among its siblings, and a
+//! note to self.
+//!
+//! Stack structure lives in local git config, which doesn't leave the clone.
+//! `submit` also records each branch's parent and base in a blob under
+//! `refs/stack-meta/<branch>` and pushes it, so `stack fetch-meta` in another
+//! clone can rebuild the same stack.
+
+use std::str;
+
+use git2::{BranchType, Oid};
+use serde_json::{Value, json};
+
+use crate::config::{setting, trunk};
+use crate::error::{StackResult, err};
+use crate::git::{
+    branch_exists, get_remote, git, git_config, is_ancestor, offline, open_repo, rev_parse,
+    set_config, try_command, unset_config,
+};
+use crate::info;
+
+/// A branch and the stack metadata recorded for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Branch {
+    pub name: String,
+    /// The branch it is stacked on; `None` outside a stack.
+    pub parent: Option<String>,
+    /// The parent commit it was last rebased onto.
+    pub base: Option<String>,
+    /// Pinned with `stack freeze`: restack leaves it alone.
+    pub frozen: bool,
+    /// Where it sorts among its siblings, lowest first: its creation time
+    /// until `stack reorder-children` numbers them.
+    pub order: Option<u64>,
+    /// Set with `stack note`, shown in `log` and `status`.
+    pub note: Option<String>,
+}
+
+impl Branch {
+    pub fn new(name: &str) -> Self {
+        Branch {
+            name: name.to_string(),
+            parent: None,
+            base: None,
+            frozen: false,
+            order: None,
+            note: None,
+        }
+    }
+
+    pub fn load(name: &str) -> Self {
+        Branch {
+            name: name.to_string(),
+            parent: get_parent(name),
+            base: get_base(name),
+            frozen: is_frozen(name),
+            order: get_order(name),
+            note: get_note(name),
+        }
+    }
+}
+
+pub fn get_parent(branch: &str) -> Option<String> {
+    git_config(&format!("branch.{}.stack-parent", branch))
+}
+
+/// `branch`'s parent, or an error for a branch stack doesn't track.
+pub fn require_parent(branch: &str) -> StackResult<String> {
+    get_parent(branch).ok_or_else(|| {
+        err(&format!(
+            "{} is not part of a stack. Start one with `stack new`, or adopt existing branches with `stack onboard`.",
+            branch
+        ))
+    })
+}
+
+/// Commit `branch` was last stacked on (its parent's tip at the time).
+pub fn get_base(branch: &str) -> Option<String> {
+    git_config(&format!("branch.{}.stack-base", branch))
+}
+
+pub fn set_base(branch: &str, rev: &str) -> StackResult<()> {
+    set_config(&format!("branch.{}.stack-base", branch), &rev_parse(rev)?)
+}
+
+/// Stack `branch` on `parent`, from `base` if that is a revision of the
+/// branch (Graphite's, say), else from where the branch forked off.
+pub fn adopt(branch: &str, parent: &str, base: Option<String>) -> StackResult<()> {
+    set_config(&format!("branch.{}.stack-parent", branch), parent)?;
+    let base = match base {
+        Some(base) if is_ancestor(&base, branch).unwrap_or(false) => Some(base),
+        _ => {
+            let repo = open_repo()?;
+            repo.merge_base(
+                repo.revparse_single(branch)?.peel_to_commit()?.id(),
+                repo.revparse_single(parent)?.peel_to_commit()?.id(),
+            )
+            .ok()
+            .map(|oid| oid.to_string())
+        }
+    };
+    if let Some(base) = base {
+        set_config(&format!("branch.{}.stack-base", branch), &base)?;
+    }
+    Ok(())
+}
+
+pub fn is_frozen(branch: &str) -> bool {
+    git_config(&format!("branch.{}.stack-frozen", branch)).as_deref() == Some("true")
+}
+
+pub fn set_frozen(branch: &str, frozen: bool) -> StackResult<()> {
+    let key = format!("branch.{}.stack-frozen", branch);
+    if frozen {
+        set_config(&key, "true")
+    } else {
+        let _ = unset_config(&key);
+        Ok(())
+    }
+}
+
+pub fn get_order(branch: &str) -> Option<u64> {
+    git_config(&format!("branch.{}.stack-order", branch)).and_then(|o| o.parse().ok())
+}
+
+pub fn set_order(branch: &str, order: u64) -> StackResult<()> {
+    set_config(
+        &format!("branch.{}.stack-order", branch),
+        &order.to_string(),
+    )
+}
+
+pub fn get_note(branch: &str) -> Option<String> {
+    git_config(&format!("branch.{}.stack-note", branch))
+}
+
+/// Attach `note` to `branch`, or clear its note with `None`.
+pub fn set_note(branch: &str, note: Option<&str>) -> StackResult<()> {
+    let key = format!("branch.{}.stack-note", branch);
+    match note {
+        Some(note) => set_config(&key, note),
+        None => {
+            let _ = unset_config(&key);
+            Ok(())
+        }
+    }
+}
+
+/// Where `branch`'s own commits start: its recorded base while that is
+/// still in its history, so a parent that moved on doesn't leak in, and
+/// otherwise its parent.
+pub fn own_commits_base(branch: &str) -> String {
+    get_base(branch)
+        .filter(|b| is_ancestor(b, branch).unwrap_or(false))
+        .unwrap_or_else(|| get_parent(branch).unwrap_or_else(trunk))
+}
+
+pub const META_REFS: &str = "refs/stack-meta/";
+
+/// Remote that metadata refs are pushed to and fetched from.
+pub fn meta_remote() -> String {
+    setting("push-remote").unwrap_or_else(|| get_remote(&trunk()))
+}
+
+/// Write `refs/stack-meta/<branch>` from the branch's local config.
+pub fn write_meta(branch: &str) -> StackResult<()> {
+    let Some(parent) = get_parent(branch) else {
+        return Ok(());
+    };
+    let meta = json!({
+        "parent": parent,
+        "base": get_base(branch),
+        "order": get_order(branch),
+    });
+
+    let repo = open_repo()?;
+    let blob = repo.blob(meta.to_string().as_bytes())?;
+    repo.reference(
+        &format!("{}{}", META_REFS, branch),
+        blob,
+        true,
+        "stack: update metadata",
+    )?;
+    Ok(())
+}
+
+/// Record and push metadata for `branches`. Best effort: a remote that
+/// rejects the refs shouldn't fail the submit.
+pub fn push_meta(branches: &[String]) -> StackResult<()> {
+    if setting("push-meta").as_deref() == Some("false") {
+        return Ok(());
+    }
+
+    let mut refspecs = Vec::new();
+    for branch in branches {
+        write_meta(branch)?;
+        refspecs.push(format!("+{0}{1}:{0}{1}", META_REFS, branch));
+    }
+
+    let mut args = vec!["push", "--quiet", "--no-verify"];
+    let remote = meta_remote();
+    args.push(&remote);
+    args.extend(refspecs.iter().map(String::as_str));
+    if try_command("git", &args).is_none() {
+        eprintln!("Warning: could not push stack metadata to {}", remote);
+    }
+    Ok(())
+}
+
+/// Drop `branch`'s metadata locally and on the remote.
+pub fn delete_meta(branch: &str) {
+    if delete_local_meta(branch) {
+        let name = format!("{}{}", META_REFS, branch);
+        let _ = try_command(
+            "git",
+            &["push", "--quiet", &meta_remote(), "--delete", &name],
+        );
+    }
+}
+
+/// Drop `branch`'s metadata ref in this clone only. Returns whether there
+/// was one.
+pub fn delete_local_meta(branch: &str) -> bool {
+    let name = format!("{}{}", META_REFS, branch);
+    let Ok(repo) = open_repo() else {
+        return false;
+    };
+    match repo.find_reference(&name) {
+        Ok(mut reference) => reference.delete().is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Branches that have a metadata ref in this clone.
+pub fn meta_branches() -> StackResult<Vec<String>> {
+    let repo = open_repo()?;
+    let mut branches = Vec::new();
+    for reference in repo.references_glob(&format!("{}*", META_REFS))? {
+        if let Ok(name) = reference?.name() {
+            branches.push(name.trim_start_matches(META_REFS).to_string());
+        }
+    }
+    Ok(branches)
+}
+
+/// Fetch everyone's metadata refs and adopt them: set parents and bases, and
+/// create local branches for stacked branches that only exist on the remote.
+pub fn import_meta(quiet: bool) -> StackResult<()> {
+    let remote = meta_remote();
+    let refspec = format!("+{0}*:{0}*", META_REFS);
+    if try_command("git", &["fetch", "--quiet", &remote, &refspec]).is_none() {
+        return Err(err(&format!(
+            "Could not fetch stack metadata from {}",
+            remote
+        )));
+    }
+
+    let repo = open_repo()?;
+    let mut imported = 0;
+    for reference in repo.references_glob(&format!("{}*", META_REFS))? {
+        let reference = reference?;
+        let (Ok(name), Some(target)) = (reference.name(), reference.target()) else {
+            continue;
+        };
+        let branch = name.trim_start_matches(META_REFS).to_string();
+        // One bad entry shouldn't keep the rest out
+        let Some(meta) = repo
+            .find_blob(target)
+            .ok()
+            .and_then(|blob| serde_json::from_slice::<Value>(blob.content()).ok())
+            .filter(|meta| meta["parent"].is_string())
+        else {
+            eprintln!("Warning: skipping {}: it isn't valid stack metadata", name);
+            continue;
+        };
+        let parent = meta["parent"].as_str().unwrap_or_default();
+
+        if !branch_exists(&branch)? {
+            let tracking = format!("{}/{}", remote, branch);
+            if repo.find_branch(&tracking, BranchType::Remote).is_err() {
+                continue;
+            }
+            git(&["branch", "--track", &branch, &tracking])?;
+        }
+
+        if get_parent(&branch).as_deref() != Some(parent) {
+            set_config(&format!("branch.{}.stack-parent", branch), parent)?;
+            if !quiet {
+                info!("{} -> {}", branch, parent);
+            }
+            imported += 1;
+        }
+        if let Some(base) = meta["base"].as_str() {
+            match Oid::from_str(base).and_then(|oid| repo.find_commit(oid)) {
+                Ok(_) => set_config(&format!("branch.{}.stack-base", branch), base)?,
+                Err(_) => eprintln!(
+                    "Warning: ignoring the base recorded for {}: {} isn't a commit here",
+                    branch, base
+                ),
+            }
+        }
+        if let Some(order) = meta["order"].as_u64() {
+            set_order(&branch, order)?;
+        }
+    }
+
+    if !quiet {
+        info!(
+            "Imported {} stack relationship(s) from {}.",
+            imported, remote
+        );
+    }
+    Ok(())
+}
+
+/// Pull in shared metadata first when `auto-fetch-meta` is on, unless
+/// offline.
+pub fn auto_import_meta() {
+    if setting("auto-fetch-meta").as_deref() == Some("true")
+        && !offline()
+        && let Err(e) = import_meta(true)
+    {
+        eprintln!("Warning: {}", e);
+    }
+}
+
+/// Parents recorded by Graphite in `refs/branch-metadata/<branch>`, with the
+/// parent revision each branch was last restacked on.
+pub fn graphite_parents() -> StackResult<Vec<(String, String, Option<String>)>> {
+    let repo = open_repo()?;
+    let mut parents = Vec::new();
+    for reference in repo.references_glob("refs/branch-metadata/*")? {
+        let reference = reference?;
+        let (Ok(name), Some(target)) = (reference.name(), reference.target()) else {
+            continue;
+        };
+        let branch = name.trim_start_matches("refs/branch-metadata/").to_string();
+        let Some(meta) = repo
+            .find_blob(target)
+            .ok()
+            .and_then(|blob| serde_json::from_slice::<Value>(blob.content()).ok())
+        else {
+            eprintln!(
+                "Warning: skipping {}: it isn't valid Graphite metadata",
+                name
+            );
+            continue;
+        };
+        if let Some(parent) = meta["parentBranchName"].as_str() {
+            let base = meta["parentBranchRevision"].as_str().map(str::to_string);
+            parents.push((branch, parent.to_string(), base));
+        }
+    }
+    Ok(parents)
+}
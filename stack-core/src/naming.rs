@@ -0,0 +1,90 @@
+//! New branch names from the configured prefix and template.
+
+use std::str;
+
+use crate::config::setting;
+use crate::error::{StackResult, err};
+use crate::git::{branch_exists, git_config};
+use crate::pr::unix_now;
+
+/// `name` with the configured `branch-prefix`, unless it already has it.
+pub fn branch_name(name: &str) -> String {
+    match setting("branch-prefix") {
+        Some(prefix) if !name.starts_with(&prefix) => format!("{}{}", prefix, name),
+        _ => name.to_string(),
+    }
+}
+
+/// Lowercase words of `text` joined by dashes, cut at a word boundary so
+/// long descriptions still make usable branch names.
+pub fn slugify(text: &str) -> String {
+    const MAX_LEN: usize = 50;
+
+    let mut slug = String::new();
+    let words = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty());
+    for word in words {
+        if !slug.is_empty() && slug.len() + word.len() >= MAX_LEN {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.extend(word.chars().flat_map(char::to_lowercase));
+    }
+    slug
+}
+
+/// Today's UTC date as `YYYY-MM-DD`.
+pub fn today() -> String {
+    date(unix_now())
+}
+
+/// The UTC date `secs` after the epoch, as `YYYY-MM-DD`.
+pub fn date(secs: u64) -> String {
+    // Civil-from-days, from Howard Hinnant's date algorithms
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Branch name for a change described as `description`, from the
+/// `branch-template` setting (default `{slug}`). The template may use
+/// `{slug}`, `{user}` and `{date}`. A numeric suffix keeps the name unique.
+pub fn templated_branch_name(description: &str) -> StackResult<String> {
+    let slug = slugify(description);
+    if slug.is_empty() {
+        return Err(err("Cannot make a branch name from an empty description"));
+    }
+
+    let template = setting("branch-template").unwrap_or_else(|| "{slug}".to_string());
+    let mut name = template
+        .replace("{slug}", &slug)
+        .replace("{date}", &today());
+    if name.contains("{user}") {
+        let user = setting("user")
+            .or_else(|| {
+                git_config("user.email").and_then(|e| e.split('@').next().map(str::to_string))
+            })
+            .or_else(|| git_config("user.name"))
+            .ok_or_else(|| err("{user} in branch-template needs user.email or stack.user"))?;
+        name = name.replace("{user}", &slugify(&user));
+    }
+
+    let name = branch_name(&name);
+    let mut unique = name.clone();
+    let mut n = 2;
+    while branch_exists(&unique)? {
+        unique = format!("{}-{}", name, n);
+        n += 1;
+    }
+    Ok(unique)
+}
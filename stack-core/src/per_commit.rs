@@ -0,0 +1,119 @@
+//! One PR per commit: `submit --per-commit` gives every commit on a branch
+//! a branch of its own, stacked in commit order, ghstack-style.
+//!
+//! Commits are told apart by a `Stack-Id` trailer added the first time they
+//! are submitted, so amending or reordering them updates the same PRs. The
+//! generated branches carry `stack-source` pointing at the branch they were
+//! made from, which keeps them out of the stack that `log` and `restack`
+//! work on.
+
+use crate::config::trunk;
+use crate::error::StackResult;
+use crate::git::{commit_ids, commit_messages, git, open_repo, set_config};
+use crate::metadata::{get_parent, own_commits_base, set_base};
+
+pub const STACK_ID_TRAILER: &str = "Stack-Id";
+
+/// The branch generated for the commit with `id` on `source`.
+pub fn commit_branch(source: &str, id: &str) -> String {
+    format!("stack/{}/{}", source, id)
+}
+
+/// The `Stack-Id` trailer of a commit message.
+pub fn stack_id(message: &str) -> Option<String> {
+    let prefix = format!("{}: ", STACK_ID_TRAILER);
+    message
+        .lines()
+        .rev()
+        .find_map(|l| l.strip_prefix(&prefix))
+        .map(|id| id.trim().to_string())
+}
+
+/// A commit message as a PR title and description, without the trailer.
+pub fn pr_message(message: &str) -> (String, String) {
+    let prefix = format!("{}: ", STACK_ID_TRAILER);
+    let mut lines = message.lines();
+    let title = lines.next().unwrap_or_default().to_string();
+    let body: Vec<&str> = lines.filter(|l| !l.starts_with(&prefix)).collect();
+    (title, body.join("\n").trim().to_string())
+}
+
+/// Give every commit of the checked-out `branch` a `Stack-Id`, rewriting the
+/// ones without. Returns whether anything was rewritten.
+pub fn ensure_stack_ids(branch: &str) -> StackResult<bool> {
+    let base = own_commits_base(branch);
+    if commit_messages(&base, branch)?
+        .iter()
+        .all(|m| stack_id(m).is_some())
+    {
+        return Ok(false);
+    }
+
+    // The pre-amend hash is as good a unique id as any
+    let script = format!(
+        "git log -1 --format=%B | grep -q '^{trailer}: ' || \
+         git commit --quiet --amend --no-edit --no-verify \
+         --trailer \"{trailer}: $(git rev-parse --short=12 HEAD)\"",
+        trailer = STACK_ID_TRAILER
+    );
+    git(&["rebase", "--quiet", "--exec", &script, &base])?;
+    Ok(true)
+}
+
+/// Point a generated branch at each commit of `source`, stacked in commit
+/// order on `source`'s parent, and drop the generated branches of commits
+/// that are gone. Returns `(current, stale)` branch names; stale ones are
+/// deleted locally, their remote branches and PRs are left to the caller.
+pub fn sync_commit_branches(source: &str) -> StackResult<(Vec<String>, Vec<String>)> {
+    let base = own_commits_base(source);
+    let repo = open_repo()?;
+    let mut parent = get_parent(source).unwrap_or_else(trunk);
+    let mut parent_commit = base.clone();
+    let mut current = Vec::new();
+
+    for oid in commit_ids(&base, source)? {
+        let message = repo.find_commit(oid)?.message()?.to_string();
+        let Some(id) = stack_id(&message) else {
+            continue;
+        };
+        let name = commit_branch(source, &id);
+        git(&["branch", "--force", &name, &oid.to_string()])?;
+        set_config(&format!("branch.{}.stack-parent", name), &parent)?;
+        set_base(&name, &parent_commit)?;
+        set_config(&format!("branch.{}.stack-source", name), source)?;
+
+        parent_commit = oid.to_string();
+        parent = name.clone();
+        current.push(name);
+    }
+
+    let mut stale = Vec::new();
+    for name in generated_branches(source)? {
+        // Deleting the branch drops its config section too
+        if !current.contains(&name) {
+            git(&["branch", "--quiet", "-D", &name])?;
+            stale.push(name);
+        }
+    }
+    Ok((current, stale))
+}
+
+/// Branches generated from `source`'s commits.
+pub fn generated_branches(source: &str) -> StackResult<Vec<String>> {
+    let config = open_repo()?.config()?;
+    let mut names = Vec::new();
+    let mut entries = config.entries(Some("branch\\..*\\.stack-source"))?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        if entry.value() == Ok(source)
+            && let Some(name) = entry
+                .name()
+                .ok()
+                .and_then(|k| k.strip_prefix("branch."))
+                .and_then(|k| k.strip_suffix(".stack-source"))
+        {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
@@ -0,0 +1,261 @@
+//! Pull request status, cached on disk and kept in memory for the rest of a
+//! command once fetched. Commands that change things refresh the cache
+//! once it is a minute old; read-only ones like `log` only ever read it.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fs, str};
+
+use crate::config::setting;
+use crate::git::{offline, remote_slug, stack_dir, try_command};
+
+pub const PR_CACHE_FILE: &str = "pr-cache";
+
+pub const PR_CACHE_TTL_SECS: u64 = 60;
+
+// Flatten each PR to `branch number state review checks url draft`, where
+// checks is a comma-separated list of check/status states.
+pub const PR_LIST_JQ: &str = r#".[] | [.headRefName, .number, .state, .reviewDecision,
+    ([.statusCheckRollup[]? | if .__typename == "CheckRun"
+        then (if .status == "COMPLETED" then .conclusion else "PENDING" end)
+        else .state end] | join(",")), .url, .isDraft] | @tsv"#;
+
+#[derive(Clone)]
+pub struct PrInfo {
+    pub number: u64,
+    /// `OPEN`, `MERGED`, or `CLOSED`
+    pub state: String,
+    /// `APPROVED`, `CHANGES_REQUESTED`, `REVIEW_REQUIRED`, or empty
+    pub review: String,
+    pub checks: Vec<String>,
+    pub url: String,
+    /// Open, but not ready for review yet.
+    pub draft: bool,
+}
+
+/// Check states that mean a check ran and failed.
+const FAILED_CHECKS: &[&str] = &[
+    "FAILURE",
+    "ERROR",
+    "CANCELLED",
+    "TIMED_OUT",
+    "ACTION_REQUIRED",
+    "STARTUP_FAILURE",
+];
+
+/// Check states that mean a check is done and didn't fail.
+const PASSED_CHECKS: &[&str] = &["SUCCESS", "NEUTRAL", "SKIPPED"];
+
+impl PrInfo {
+    pub fn ci_status(&self) -> Option<&'static str> {
+        if self.checks.is_empty() {
+            None
+        } else if self
+            .checks
+            .iter()
+            .any(|c| FAILED_CHECKS.contains(&c.as_str()))
+        {
+            Some("failing")
+        } else if self
+            .checks
+            .iter()
+            .all(|c| PASSED_CHECKS.contains(&c.as_str()))
+        {
+            Some("passing")
+        } else {
+            Some("pending")
+        }
+    }
+
+    /// How many checks have finished, passed or failed.
+    pub fn finished_checks(&self) -> usize {
+        self.checks
+            .iter()
+            .filter(|c| FAILED_CHECKS.contains(&c.as_str()) || PASSED_CHECKS.contains(&c.as_str()))
+            .count()
+    }
+
+    /// Short description for `stack log`, e.g. `#12 open, approved, CI passing`.
+    pub fn annotation(&self) -> String {
+        let state = if self.draft && self.state == "OPEN" {
+            "draft".to_string()
+        } else {
+            self.state.to_lowercase()
+        };
+        let mut parts = vec![format!("#{} {}", self.number, state)];
+        if !self.review.is_empty() {
+            parts.push(self.review.to_lowercase().replace('_', " "));
+        }
+        if let Some(ci) = self.ci_status() {
+            parts.push(format!("CI {}", ci));
+        }
+        parts.join(", ")
+    }
+}
+
+pub fn parse_pr_list(raw: &str) -> HashMap<String, PrInfo> {
+    let mut prs = HashMap::new();
+    for line in raw.lines() {
+        // Trailing empty fields may have been trimmed off the last line
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 3 {
+            continue;
+        }
+        let Ok(number) = fields[1].parse() else {
+            continue;
+        };
+        let field = |i: usize| fields.get(i).copied().unwrap_or_default();
+        // gh lists newest first; keep the most recent PR per branch
+        prs.entry(fields[0].to_string()).or_insert(PrInfo {
+            number,
+            state: fields[2].to_string(),
+            review: field(3).to_string(),
+            checks: field(4)
+                .split(',')
+                .filter(|c| !c.is_empty())
+                .map(str::to_string)
+                .collect(),
+            url: field(5).to_string(),
+            draft: field(6) == "true",
+        });
+    }
+    prs
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Whether this run answers PR state from the cache alone.
+static CACHED_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Keep the rest of the run off the forge for PR state: the last cached
+/// list is used however old it is, as when offline. For read-only commands,
+/// which leave refreshing it to the commands that change things.
+pub fn use_cached_prs() {
+    CACHED_ONLY.store(true, Ordering::Relaxed);
+}
+
+/// Set when this run changed a PR, so the cache is refreshed before it ends.
+static CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// The PR list in `parse_pr_list`'s format.
+fn format_pr_list(prs: &HashMap<String, PrInfo>) -> String {
+    let mut branches: Vec<&String> = prs.keys().collect();
+    branches.sort();
+    branches
+        .into_iter()
+        .map(|branch| {
+            let pr = &prs[branch];
+            format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                branch,
+                pr.number,
+                pr.state,
+                pr.review,
+                pr.checks.join(","),
+                pr.url,
+                pr.draft
+            )
+        })
+        .collect()
+}
+
+/// PR state for every branch from the on-disk cache while it is under a
+/// minute old, else from `fetch`, which then replaces it. Offline, or after
+/// `use_cached_prs`, the cached list is used however old, and `fetch` is
+/// never called.
+pub fn cached_pr_map(fetch: impl FnOnce() -> HashMap<String, PrInfo>) -> HashMap<String, PrInfo> {
+    let cache = stack_dir().ok().map(|d| d.join(PR_CACHE_FILE));
+    let cached_only = offline() || CACHED_ONLY.load(Ordering::Relaxed);
+
+    if let Some(contents) = cache.as_ref().and_then(|c| fs::read_to_string(c).ok())
+        && let Some((stamp, raw)) = contents.split_once('\n')
+        && stamp
+            .parse::<u64>()
+            .is_ok_and(|t| cached_only || unix_now().saturating_sub(t) < PR_CACHE_TTL_SECS)
+    {
+        return parse_pr_list(raw);
+    }
+    if cached_only {
+        return HashMap::new();
+    }
+
+    // Cache failures too, so a missing gh doesn't cost a spawn per call
+    let prs = fetch();
+    if let Some(cache) = cache {
+        let _ = fs::write(cache, format!("{}\n{}", unix_now(), format_pr_list(&prs)));
+    }
+    prs
+}
+
+/// PR state for every branch, keyed by head branch name, from a single
+/// `gh pr list`; empty when gh is unavailable.
+pub fn get_pr_map() -> HashMap<String, PrInfo> {
+    let mut args = vec![
+        "pr",
+        "list",
+        "--state",
+        "all",
+        "--limit",
+        "200",
+        "--json",
+        "headRefName,number,state,reviewDecision,statusCheckRollup,url,isDraft",
+        "--jq",
+        PR_LIST_JQ,
+    ];
+    let repo = setting("pr-remote").and_then(|r| remote_slug(&r).ok());
+    if let Some(repo) = &repo {
+        args.extend_from_slice(&["--repo", repo]);
+    }
+
+    parse_pr_list(&try_command("gh", &args).unwrap_or_default())
+}
+
+/// PR state fetched earlier in this run, until `invalidate_pr_cache`.
+static REMEMBERED: Mutex<Option<HashMap<String, PrInfo>>> = Mutex::new(None);
+
+/// The PR state fetched earlier in this run, or else `fetch`'s, which is
+/// then kept for the rest of it.
+pub fn remembered_pr_map(
+    fetch: impl FnOnce() -> HashMap<String, PrInfo>,
+) -> HashMap<String, PrInfo> {
+    let mut remembered = REMEMBERED.lock().unwrap_or_else(|e| e.into_inner());
+    remembered.get_or_insert_with(fetch).clone()
+}
+
+/// Forget cached PR state, after something changed a PR or to see the
+/// latest while waiting on one. The list on disk is only marked stale, so
+/// read-only commands keep showing it until it is fetched again.
+pub fn invalidate_pr_cache() {
+    *REMEMBERED.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    let Ok(cache) = stack_dir().map(|d| d.join(PR_CACHE_FILE)) else {
+        return;
+    };
+    if let Ok(contents) = fs::read_to_string(&cache)
+        && let Some((_, raw)) = contents.split_once('\n')
+    {
+        let _ = fs::write(&cache, format!("0\n{}", raw));
+    }
+}
+
+/// Forget cached PR state after a PR was opened, edited or merged, and have
+/// the run fetch it again before it ends.
+pub fn pr_changed() {
+    invalidate_pr_cache();
+    CHANGED.store(true, Ordering::Relaxed);
+}
+
+/// Whether this run changed a PR it hasn't fetched the state of since.
+pub fn pr_cache_changed() -> bool {
+    CHANGED.load(Ordering::Relaxed)
+        && REMEMBERED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_none()
+}
@@ -0,0 +1,77 @@
+//! Starting other programs the same way on every platform. Snippets from
+//! config (hooks, editors, browsers, `foreach` and `test` commands) run
+//! through one POSIX shell, which on Windows is the `sh.exe` that comes with
+//! Git for Windows, and text read back from children and files gets `\n`
+//! line endings whatever wrote it.
+
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// Overrides the shell snippets run through, for systems where it can't be
+/// found and for tests that want to see what runs.
+pub const SHELL_ENV: &str = "STACK_SHELL";
+
+/// The shell snippets run through: `STACK_SHELL`, else `sh` from PATH. On
+/// Windows, where `sh` is rarely on PATH, Git for Windows' own is tried
+/// first.
+pub fn shell() -> PathBuf {
+    static SHELL: OnceLock<PathBuf> = OnceLock::new();
+    SHELL
+        .get_or_init(|| {
+            if let Some(shell) = env::var_os(SHELL_ENV).filter(|s| !s.is_empty()) {
+                return PathBuf::from(shell);
+            }
+            if cfg!(windows)
+                && let Some(sh) = git_for_windows_sh()
+            {
+                return sh;
+            }
+            PathBuf::from("sh")
+        })
+        .clone()
+}
+
+/// `sh.exe` of the Git for Windows install that `git` on PATH belongs to.
+/// Its exec path is `<root>/mingw64/libexec/git-core`, and the shell lives
+/// in `<root>/usr/bin` (or `<root>/bin` in older installs).
+fn git_for_windows_sh() -> Option<PathBuf> {
+    let output = Command::new("git").arg("--exec-path").output().ok()?;
+    let exec_path = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    exec_path.ancestors().find_map(|dir| {
+        ["usr/bin/sh.exe", "bin/sh.exe"]
+            .iter()
+            .map(|sh| dir.join(sh))
+            .find(|sh| sh.is_file())
+    })
+}
+
+/// A command running `script` in the shell, where arguments added to it
+/// become `"$@"` and `name` is `$0`, so `script` can be a program with
+/// arguments of its own (`code --wait`).
+pub fn shell_command(script: &str, name: impl AsRef<OsStr>) -> Command {
+    let mut command = Command::new(shell());
+    command
+        .arg("-c")
+        .arg(format!("{} \"$@\"", script))
+        .arg(name);
+    command
+}
+
+/// `path` written for the shell: quoted, with forward slashes, which the
+/// Windows shell takes as well as backslashes but doesn't treat as escapes.
+pub fn shell_path(path: &Path) -> String {
+    let path = path.display().to_string();
+    if cfg!(windows) {
+        format!("\"{}\"", path.replace('\\', "/"))
+    } else {
+        format!("\"{}\"", path)
+    }
+}
+
+/// `text` with `\r\n` line endings turned into `\n`.
+pub fn normalize_newlines(text: &str) -> String {
+    text.replace("\r\n", "\n")
+}
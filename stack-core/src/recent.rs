@@ -0,0 +1,62 @@
+//! Stack branches visited lately, for `stack switch -` and `stack recent`.
+//! Each stack command notes the branch it starts and finishes on in
+//! `.git/stack/recent`, most recent first, one name per line.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::trunk;
+use crate::error::StackResult;
+use crate::git::{branch_exists, get_current_branch, stack_dir};
+use crate::metadata::get_parent;
+
+const RECENT_FILE: &str = "recent";
+
+/// How many branches the list keeps.
+const MAX_RECENT: usize = 10;
+
+fn recent_path() -> StackResult<PathBuf> {
+    Ok(stack_dir()?.join(RECENT_FILE))
+}
+
+fn read_recent() -> Vec<String> {
+    recent_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|text| text.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Move the checked-out branch to the front of the list if it is part of a
+/// stack. Best effort, since it runs around every command.
+pub fn record_visit() {
+    let Ok(branch) = get_current_branch() else {
+        return;
+    };
+    if branch.is_empty() || (branch != trunk() && get_parent(&branch).is_none()) {
+        return;
+    }
+    let mut recent = read_recent();
+    if recent.first() == Some(&branch) {
+        return;
+    }
+    recent.retain(|b| *b != branch);
+    recent.insert(0, branch);
+    recent.truncate(MAX_RECENT);
+    if let Ok(path) = recent_path() {
+        let _ = fs::write(path, recent.join("\n") + "\n");
+    }
+}
+
+/// Recently visited branches that still exist, most recent first, without
+/// the checked-out one.
+pub fn recent_branches() -> StackResult<Vec<String>> {
+    let current = get_current_branch()?;
+    let mut branches = Vec::new();
+    for branch in read_recent() {
+        if branch != current && branch_exists(&branch)? {
+            branches.push(branch);
+        }
+    }
+    Ok(branches)
+}
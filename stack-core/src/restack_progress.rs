@@ -0,0 +1,134 @@
+//! The rest of a restack that stopped on a conflict, saved so `stack
+//! continue` can run the rebases it still had to do, in other subtrees as
+//! well as above the branch that stopped, and finish on the branch the
+//! restack started from.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde_json::{Value, json};
+
+use crate::engine::{RestackPlan, RestackStep};
+use crate::error::{StackResult, err};
+use crate::git::stack_dir;
+
+const PROGRESS_FILE: &str = "restack-progress.json";
+
+/// What a stopped restack had left to do.
+pub struct RestackProgress {
+    /// Where to finish once the remaining rebases are done. Empty when the
+    /// restack started on a detached HEAD.
+    pub start_branch: String,
+    /// The branch whose rebase stopped, so a rebase git is left in for
+    /// other reasons isn't taken for this one.
+    pub stopped_on: String,
+    /// The rebases after the one that stopped, in order.
+    pub steps: Vec<RestackStep>,
+    /// Branches the restack treated as landed.
+    pub merged: Vec<String>,
+}
+
+fn progress_path() -> StackResult<PathBuf> {
+    Ok(stack_dir()?.join(PROGRESS_FILE))
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|a| {
+            a.iter()
+                .filter_map(|v| v.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn step_json(step: &RestackStep) -> Value {
+    match step {
+        RestackStep::Branch { branch, parent } => json!({ "branch": branch, "parent": parent }),
+        RestackStep::Chain { parent, branches } => {
+            json!({ "parent": parent, "branches": branches })
+        }
+    }
+}
+
+fn step_from_json(step: &Value) -> Option<RestackStep> {
+    let parent = step["parent"].as_str()?.to_string();
+    match step["branch"].as_str() {
+        Some(branch) => Some(RestackStep::Branch {
+            branch: branch.to_string(),
+            parent,
+        }),
+        None => {
+            let branches = strings(&step["branches"]);
+            (!branches.is_empty()).then_some(RestackStep::Chain { parent, branches })
+        }
+    }
+}
+
+impl RestackProgress {
+    /// The saved progress, if a restack stopped partway.
+    pub fn load() -> StackResult<Option<Self>> {
+        let text = match fs::read_to_string(progress_path()?) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let progress: Value = serde_json::from_str(&text).map_err(|e| {
+            err(&format!(
+                "Unreadable restack progress ({}): {}",
+                PROGRESS_FILE, e
+            ))
+        })?;
+        let steps = progress["steps"]
+            .as_array()
+            .map(|a| a.iter().map(step_from_json).collect::<Option<Vec<_>>>())
+            .unwrap_or(Some(Vec::new()))
+            .ok_or_else(|| {
+                err(&format!(
+                    "Unreadable restack progress ({}): bad step",
+                    PROGRESS_FILE
+                ))
+            })?;
+
+        Ok(Some(RestackProgress {
+            start_branch: progress["start_branch"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            stopped_on: progress["stopped_on"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            steps,
+            merged: strings(&progress["merged"]),
+        }))
+    }
+
+    pub fn save(&self) -> StackResult<()> {
+        let progress = json!({
+            "start_branch": self.start_branch,
+            "stopped_on": self.stopped_on,
+            "steps": self.steps.iter().map(step_json).collect::<Vec<_>>(),
+            "merged": self.merged,
+        });
+        fs::write(progress_path()?, progress.to_string())?;
+        Ok(())
+    }
+
+    /// Forget the progress, once the restack finished or a new one started.
+    pub fn clear() -> StackResult<()> {
+        match fs::remove_file(progress_path()?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// The remaining rebases, ready to run.
+    pub fn plan(&self) -> RestackPlan {
+        let merged: HashSet<String> = self.merged.iter().cloned().collect();
+        RestackPlan::new(self.steps.clone(), merged)
+    }
+}
@@ -0,0 +1,83 @@
+//! Retrying remote operations that fail for reasons that go away on their
+//! own: dropped connections, timeouts, 5xx responses, and rate limits.
+//! Anything else (rejected pushes, missing PRs, bad credentials) fails at
+//! once, since trying again would only fail the same way.
+//!
+//! `stack.retry-attempts` caps the attempts per operation (default 3) and
+//! `stack.retry-backoff` sets the seconds before the first retry (default
+//! 1), doubling for each one after.
+
+use std::thread;
+use std::time::Duration;
+
+use crate::config::setting;
+
+/// What stderr or a response says when a failure is worth retrying.
+const TRANSIENT: &[&str] = &[
+    "could not resolve host",
+    "connection refused",
+    "connection reset",
+    "timed out",
+    "the remote end hung up unexpectedly",
+    "early eof",
+    "unexpected disconnect",
+    "tls handshake",
+    "http 429",
+    "http 500",
+    "http 502",
+    "http 503",
+    "http 504",
+    "502 bad gateway",
+    "503 service unavailable",
+    "504 gateway timeout",
+    "rate limit",
+];
+
+/// Whether a failure that printed `message` is worth trying again.
+pub fn is_transient(message: &str) -> bool {
+    let message = message.to_lowercase();
+    TRANSIENT.iter().any(|t| message.contains(t))
+}
+
+/// How many times to try a remote operation, and how long to wait between.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_config() -> Self {
+        RetryPolicy {
+            attempts: setting("retry-attempts")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(3)
+                .max(1),
+            backoff: setting("retry-backoff")
+                .and_then(|s| s.parse::<f64>().ok())
+                .filter(|s| *s >= 0.0)
+                .map_or(Duration::from_secs(1), Duration::from_secs_f64),
+        }
+    }
+
+    /// Whether another attempt follows a failed `attempt` (1-based).
+    pub fn retries_after(&self, attempt: u32) -> bool {
+        attempt < self.attempts
+    }
+
+    /// Say why `what` is being retried, then wait out the backoff for the
+    /// retry after `attempt`, or `wait` when the server named one.
+    pub fn wait(&self, what: &str, reason: &str, attempt: u32, wait: Option<Duration>) {
+        let delay = wait.unwrap_or(self.backoff * 2u32.saturating_pow(attempt - 1));
+        let reason = reason.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+        eprintln!(
+            "Warning: {} failed ({}); retrying in {}s ({} of {} attempts)",
+            what,
+            reason.trim(),
+            delay.as_secs(),
+            attempt + 1,
+            self.attempts
+        );
+        thread::sleep(delay);
+    }
+}
@@ -0,0 +1,190 @@
+//! Named checkpoints of every stack, for undoing a reorganization that went
+//! wrong. `refs/stack-snapshots/<name>` points at a commit whose tree holds
+//! `snapshot.json`, each stacked branch's tip and metadata, and whose
+//! parents are those tips, so nothing a snapshot needs is garbage collected
+//! while it exists.
+
+use std::str;
+
+use git2::{Oid, Reference, Signature};
+use serde_json::{Value, json};
+
+use crate::engine::Stack;
+use crate::error::{StackResult, err};
+use crate::git::{open_repo, set_config, unset_config};
+use crate::metadata::{Branch, set_frozen, set_note, set_order};
+use crate::pr::unix_now;
+
+pub const SNAPSHOT_REFS: &str = "refs/stack-snapshots/";
+const SNAPSHOT_FILE: &str = "snapshot.json";
+
+/// A saved checkpoint: when it was taken, and each branch's tip (as a
+/// commit id) with its metadata.
+pub struct Snapshot {
+    pub name: String,
+    pub created: u64,
+    pub branches: Vec<(String, Branch)>,
+}
+
+fn snapshot_ref(name: &str) -> StackResult<String> {
+    let refname = format!("{}{}", SNAPSHOT_REFS, name);
+    if name.is_empty() || !Reference::is_valid_name(&refname) {
+        return Err(err(&format!("'{}' can't be a snapshot name", name)));
+    }
+    Ok(refname)
+}
+
+/// Save every stacked branch's tip and metadata as snapshot `name`.
+/// Returns how many branches it holds.
+pub fn save_snapshot(name: &str, stack: &Stack) -> StackResult<usize> {
+    let refname = snapshot_ref(name)?;
+    let repo = open_repo()?;
+    if repo.find_reference(&refname).is_ok() {
+        return Err(err(&format!("There is already a snapshot named {}", name)));
+    }
+
+    let mut branches: Vec<&Branch> = stack
+        .branches()
+        .filter(|b| b.parent.is_some() && stack.exists(&b.name))
+        .collect();
+    if branches.is_empty() {
+        return Err(err("No stacked branches to snapshot"));
+    }
+    branches.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut entries = Vec::new();
+    let mut tips = Vec::new();
+    for branch in &branches {
+        let Some(head) = stack.head(&branch.name) else {
+            continue;
+        };
+        entries.push(json!({
+            "name": branch.name,
+            "head": head.oid,
+            "parent": branch.parent,
+            "base": branch.base,
+            "frozen": branch.frozen,
+            "order": branch.order,
+            "note": branch.note,
+        }));
+        let tip = repo.find_commit(Oid::from_str(&head.oid)?)?;
+        if !tips.iter().any(|t: &git2::Commit| t.id() == tip.id()) {
+            tips.push(tip);
+        }
+    }
+    let state = json!({ "created": unix_now(), "branches": entries });
+
+    let mut tree = repo.treebuilder(None)?;
+    tree.insert(
+        SNAPSHOT_FILE,
+        repo.blob(state.to_string().as_bytes())?,
+        0o100644,
+    )?;
+    let tree = repo.find_tree(tree.write()?)?;
+    let signature = repo
+        .signature()
+        .or_else(|_| Signature::now("stack", "stack@localhost"))?;
+    let parents: Vec<&git2::Commit> = tips.iter().collect();
+    let commit = repo.commit(
+        None,
+        &signature,
+        &signature,
+        &format!("stack snapshot {}", name),
+        &tree,
+        &parents,
+    )?;
+    repo.reference(&refname, commit, false, "stack: snapshot")?;
+    Ok(entries.len())
+}
+
+/// Every saved snapshot, oldest first.
+pub fn snapshots() -> StackResult<Vec<Snapshot>> {
+    let repo = open_repo()?;
+    let mut names = Vec::new();
+    for reference in repo.references_glob(&format!("{}*", SNAPSHOT_REFS))? {
+        if let Ok(name) = reference?.name() {
+            names.push(name.trim_start_matches(SNAPSHOT_REFS).to_string());
+        }
+    }
+    let mut all = names
+        .iter()
+        .map(|name| load_snapshot(name))
+        .collect::<StackResult<Vec<_>>>()?;
+    all.sort_by(|a, b| (a.created, &a.name).cmp(&(b.created, &b.name)));
+    Ok(all)
+}
+
+pub fn load_snapshot(name: &str) -> StackResult<Snapshot> {
+    let repo = open_repo()?;
+    let reference = repo.find_reference(&snapshot_ref(name)?).map_err(|_| {
+        err(&format!(
+            "No snapshot named {}; see `stack snapshots`",
+            name
+        ))
+    })?;
+    let tree = reference.peel_to_commit()?.tree()?;
+    let entry = tree
+        .get_name(SNAPSHOT_FILE)
+        .ok_or_else(|| err(&format!("Snapshot {} has no {}", name, SNAPSHOT_FILE)))?;
+    let state: Value = serde_json::from_slice(repo.find_blob(entry.id())?.content())?;
+
+    let text = |v: &Value| v.as_str().map(str::to_string);
+    let branches = state["branches"]
+        .as_array()
+        .map(|entries| {
+            entries
+                .iter()
+                .filter_map(|e| {
+                    let name = text(&e["name"])?;
+                    let branch = Branch {
+                        parent: text(&e["parent"]),
+                        base: text(&e["base"]),
+                        frozen: e["frozen"].as_bool().unwrap_or(false),
+                        order: e["order"].as_u64(),
+                        note: text(&e["note"]),
+                        ..Branch::new(&name)
+                    };
+                    Some((text(&e["head"])?, branch))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(Snapshot {
+        name: name.to_string(),
+        created: state["created"].as_u64().unwrap_or_default(),
+        branches,
+    })
+}
+
+impl Snapshot {
+    /// Point `branch` back at its recorded tip and put its metadata back,
+    /// creating it again if it has been deleted since.
+    pub fn restore_branch(&self, tip: &str, branch: &Branch) -> StackResult<()> {
+        let repo = open_repo()?;
+        repo.reference(
+            &format!("refs/heads/{}", branch.name),
+            Oid::from_str(tip)?,
+            true,
+            &format!("stack: restore snapshot {}", self.name),
+        )?;
+
+        let key = |field: &str| format!("branch.{}.stack-{}", branch.name, field);
+        for (field, value) in [("parent", &branch.parent), ("base", &branch.base)] {
+            match value {
+                Some(value) => set_config(&key(field), value)?,
+                None => {
+                    let _ = unset_config(&key(field));
+                }
+            }
+        }
+        set_frozen(&branch.name, branch.frozen)?;
+        set_note(&branch.name, branch.note.as_deref())?;
+        match branch.order {
+            Some(order) => set_order(&branch.name, order)?,
+            None => {
+                let _ = unset_config(&key("order"));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+//! Results of `stack test`, cached per branch head so re-runs skip what was
+//! already tested, and the gate that lets `submit` and `land` insist on
+//! green results.
+//!
+//! The cache is `.git/stack/test-results`, one `<sha>\t<ok|failed>\t<command>`
+//! line per run. A result only counts for the exact commit and command it
+//! was recorded for.
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::config::setting;
+use crate::error::{StackResult, err};
+use crate::git::{rev_parse, stack_dir};
+
+const RESULTS_FILE: &str = "test-results";
+
+fn results_path() -> StackResult<PathBuf> {
+    Ok(stack_dir()?.join(RESULTS_FILE))
+}
+
+/// The command `stack test` runs by default (`stack.test-command`).
+pub fn test_command() -> Option<String> {
+    setting("test-command")
+}
+
+/// The last recorded result of `command` at commit `sha`: `Some(true)` for
+/// a pass, `Some(false)` for a failure, `None` if it never ran there.
+pub fn cached_result(sha: &str, command: &str) -> Option<bool> {
+    let text = fs::read_to_string(results_path().ok()?).ok()?;
+    text.lines().rev().find_map(|line| {
+        let mut fields = line.splitn(3, '\t');
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(s), Some(status), Some(c)) if s == sha && c == command => Some(status == "ok"),
+            _ => None,
+        }
+    })
+}
+
+/// Remember whether `command` passed at commit `sha`.
+pub fn record_result(sha: &str, command: &str, passed: bool) -> StackResult<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(results_path()?)?;
+    let status = if passed { "ok" } else { "failed" };
+    writeln!(file, "{}\t{}\t{}", sha, status, command)?;
+    Ok(())
+}
+
+/// With `stack.require-tests = true`, fail unless `stack test` passed on
+/// every one of `branches` at its current head. Callers skip this for
+/// `--no-verify`, as they do hooks.
+pub fn require_passing_tests(branches: &[String], command: &str) -> StackResult<()> {
+    if setting("require-tests").as_deref() != Some("true") {
+        return Ok(());
+    }
+    let Some(test) = test_command() else {
+        return Err(err(
+            "stack.require-tests is set, but stack.test-command isn't",
+        ));
+    };
+
+    let mut problems = Vec::new();
+    for branch in branches {
+        match cached_result(&rev_parse(branch)?, &test) {
+            Some(true) => {}
+            Some(false) => problems.push(format!("{} failed its tests", branch)),
+            None => problems.push(format!("{} hasn't been tested at its head", branch)),
+        }
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(err(&format!(
+        "Not running `stack {}` until `stack test` passes:\n  {}\n(use --no-verify to skip this check)",
+        command,
+        problems.join("\n  ")
+    )))
+}
@@ -0,0 +1,446 @@
+//! Prompts, pickers, and editor round trips.
+
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::config::setting;
+use crate::error::{StackError, StackResult, err};
+use crate::events::json_output;
+use crate::git::{commit_messages, git, open_repo, stack_dir};
+use crate::process::{normalize_newlines, shell_command};
+
+/// How much the CLI says: `--quiet` keeps only results and errors,
+/// `--verbose` adds every git and gh command it runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+static NON_INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// `--yes`: answer confirmations with yes and fail instead of waiting for
+/// any other input.
+pub fn set_non_interactive() {
+    NON_INTERACTIVE.store(true, Ordering::Relaxed);
+}
+
+/// Set to `1` to answer prompts from a stdin that isn't a terminal, for
+/// wrappers that pipe their answers in.
+pub const INTERACTIVE_ENV: &str = "STACK_INTERACTIVE";
+
+/// Whether prompts can be answered: not `--yes`, and stdin is a terminal
+/// (or `STACK_INTERACTIVE=1`). Otherwise they are treated as `--yes`.
+pub fn interactive() -> bool {
+    !NON_INTERACTIVE.load(Ordering::Relaxed)
+        && (io::stdin().is_terminal() || env::var(INTERACTIVE_ENV).as_deref() == Ok("1"))
+}
+
+/// What `log` and `status` color, each themeable through `stack.theme.<key>`
+/// with git-style specs such as `bold cyan` or `dim`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Paint {
+    Current,
+    Trunk,
+    Restack,
+    Merged,
+}
+
+impl Paint {
+    fn key(self) -> &'static str {
+        match self {
+            Paint::Current => "current",
+            Paint::Trunk => "trunk",
+            Paint::Restack => "restack",
+            Paint::Merged => "merged",
+        }
+    }
+
+    fn default_spec(self) -> &'static str {
+        match self {
+            Paint::Current => "bold cyan",
+            Paint::Trunk => "dim",
+            Paint::Restack => "yellow",
+            Paint::Merged => "green",
+        }
+    }
+}
+
+/// Whether output is colored: `stack.color` is `always`, `never`, or `auto`
+/// (the default), which colors a terminal unless `NO_COLOR` is set.
+pub fn color_enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| match setting("color").as_deref() {
+        Some("always") => true,
+        Some("never") => false,
+        _ => {
+            env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                && io::stdout().is_terminal()
+                && ansi_terminal()
+        }
+    })
+}
+
+/// Whether the terminal understands ANSI escapes. Everywhere but Windows it
+/// does; there, only the terminals that say so: Windows Terminal, mintty
+/// (Git Bash) and others that set `TERM`, and ConEmu.
+pub fn ansi_terminal() -> bool {
+    !cfg!(windows)
+        || env::var_os("WT_SESSION").is_some()
+        || env::var_os("TERM").is_some_and(|t| t != "dumb")
+        || env::var("ConEmuANSI").is_ok_and(|v| v == "ON")
+}
+
+/// ANSI SGR codes for a spec like `bold yellow`; unknown words are ignored.
+fn sgr_codes(spec: &str) -> String {
+    const COLORS: [&str; 8] = [
+        "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+    ];
+    spec.split_whitespace()
+        .filter_map(|word| match word {
+            "bold" => Some("1".to_string()),
+            "dim" => Some("2".to_string()),
+            "italic" => Some("3".to_string()),
+            "ul" | "underline" => Some("4".to_string()),
+            _ => {
+                let (bright, name) = match word.strip_prefix("bright") {
+                    Some(name) => (true, name),
+                    None => (false, word),
+                };
+                let i = COLORS.iter().position(|c| *c == name)?;
+                Some((if bright { 90 + i } else { 30 + i }).to_string())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// `text` in `paint`'s color, or as it is when color is off.
+pub fn paint(paint: Paint, text: &str) -> String {
+    if !color_enabled() || text.is_empty() {
+        return text.to_string();
+    }
+    let spec = setting(&format!("theme.{}", paint.key()));
+    let codes = sgr_codes(spec.as_deref().unwrap_or(paint.default_spec()));
+    if codes.is_empty() {
+        return text.to_string();
+    }
+    format!("\x1b[{}m{}\x1b[0m", codes, text)
+}
+
+/// `println!` for progress and other chatter that `--quiet` suppresses.
+/// Under `--output json` it goes to stderr, leaving stdout to the events.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::ui::verbosity() > $crate::ui::Verbosity::Quiet {
+            $crate::report!($($arg)*);
+        }
+    };
+}
+
+/// `println!` for results, which go to stderr under `--output json`.
+#[macro_export]
+macro_rules! report {
+    ($($arg:tt)*) => {
+        if $crate::events::json_output() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub fn prompt(message: &str) -> StackResult<String> {
+    if !interactive() {
+        return Err(StackError::Usage(format!(
+            "'{}' needs an answer, but input is disabled (--yes, or stdin isn't a terminal)",
+            message.trim()
+        )));
+    }
+    if json_output() {
+        eprint!("{}", message);
+    } else {
+        print!("{}", message);
+        io::stdout().flush()?;
+    }
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input)? == 0 {
+        return Err(StackError::Usage(format!(
+            "'{}' needs an answer, but stdin is closed",
+            message.trim()
+        )));
+    }
+    Ok(input.trim().to_string())
+}
+
+/// Ask a yes/no question, defaulting to no. `--yes` (or a stdin that isn't
+/// a terminal) answers it.
+pub fn confirm(message: &str) -> StackResult<bool> {
+    if !interactive() {
+        info!("{}y", message);
+        return Ok(true);
+    }
+    Ok(prompt(message)?.to_lowercase() == "y")
+}
+
+/// Numbered picker over `options`; returns the chosen one.
+pub fn pick(message: &str, options: &[String]) -> StackResult<String> {
+    Ok(options[pick_index(message, options)?].clone())
+}
+
+/// Numbered picker over `labels`; returns the index of the chosen one.
+pub fn pick_index(message: &str, labels: &[String]) -> StackResult<usize> {
+    report!("{}", message);
+    for (i, label) in labels.iter().enumerate() {
+        report!("  {}) {}", i + 1, label);
+    }
+
+    let answer = prompt(&format!("Select [1-{}]: ", labels.len()))?;
+    answer
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_sub(1))
+        .filter(|i| *i < labels.len())
+        .ok_or_else(|| err(&format!("Invalid selection: {}", answer)))
+}
+
+/// Open the user's editor (resolved like git: `GIT_EDITOR`, `core.editor`,
+/// `VISUAL`, `EDITOR`) on `initial` and return what they saved.
+pub fn edit_text(initial: &str) -> StackResult<String> {
+    if !interactive() {
+        return Err(StackError::Usage(
+            "This needs an editor, but input is disabled by --yes".to_string(),
+        ));
+    }
+    let path = stack_dir()?.join("PR_EDITMSG");
+    fs::write(&path, initial)?;
+
+    // Editors may carry arguments (`code --wait`), so let the shell split them
+    let editor = git(&["var", "GIT_EDITOR"])?;
+    let status = shell_command(&editor, &editor).arg(&path).status()?;
+    if !status.success() {
+        return Err(err(&format!("Editor '{}' exited with an error", editor)));
+    }
+
+    Ok(normalize_newlines(&fs::read_to_string(&path)?)
+        .trim()
+        .to_string())
+}
+
+/// Open `url` in `$BROWSER`, or the platform's default browser.
+pub fn open_url(url: &str) -> StackResult<()> {
+    let mut command = match env::var("BROWSER") {
+        Ok(browser) if !browser.is_empty() => shell_command(&browser, &browser),
+        _ if cfg!(target_os = "macos") => Command::new("open"),
+        _ if cfg!(windows) => {
+            let mut c = Command::new("cmd");
+            c.args(["/C", "start", ""]);
+            c
+        }
+        _ => Command::new("xdg-open"),
+    };
+    let status = command.arg(url).status()?;
+    if !status.success() {
+        return Err(err(&format!("Could not open {} in a browser", url)));
+    }
+    Ok(())
+}
+
+/// PR body template: `stack.pr-template` (relative to the repo root), else
+/// GitHub's usual `PULL_REQUEST_TEMPLATE.md` locations.
+pub fn pr_template() -> Option<String> {
+    let repo = open_repo().ok()?;
+    let root = repo.workdir()?;
+
+    let candidates = match setting("pr-template") {
+        Some(path) => vec![path],
+        None => [
+            ".github/PULL_REQUEST_TEMPLATE.md",
+            ".github/pull_request_template.md",
+            "PULL_REQUEST_TEMPLATE.md",
+            "pull_request_template.md",
+            "docs/PULL_REQUEST_TEMPLATE.md",
+            "docs/pull_request_template.md",
+        ]
+        .iter()
+        .map(|p| p.to_string())
+        .collect(),
+    };
+    candidates
+        .iter()
+        .find_map(|path| fs::read_to_string(root.join(path)).ok())
+        .map(|text| normalize_newlines(&text))
+}
+
+pub const SCISSORS: &str = "# ------------------------ >8 ------------------------";
+
+/// Edit a PR title and description together, `git commit` style: the first
+/// line is the title, the rest the description. Everything below the
+/// scissors line is ignored, so markdown `#` headings survive.
+pub fn edit_pr_message(title: &str, body: &str, context: &str) -> StackResult<(String, String)> {
+    let initial = format!(
+        "{}\n\n{}\n\n{}\n\
+         # Do not modify or remove the line above.\n\
+         # The first line is the PR title, the rest is its description.\n\
+         # Everything below the line above is ignored. An empty title aborts.\n\
+         #\n\
+         # {}\n",
+        title, body, SCISSORS, context
+    );
+    let text = edit_text(&initial)?;
+    let text = text.split(SCISSORS).next().unwrap_or_default();
+
+    let (title, body) = text.split_once('\n').unwrap_or((text, ""));
+    let title = title.trim();
+    if title.is_empty() {
+        return Err(err("Aborting: empty PR title"));
+    }
+    Ok((title.to_string(), body.trim().to_string()))
+}
+
+/// Starting title and description for a new PR: the branch's first commit
+/// subject and the repo's PR template.
+pub fn pr_defaults(branch: &str, parent: &str) -> (String, String) {
+    let title = commit_messages(parent, branch)
+        .ok()
+        .and_then(|m| m.first().and_then(|m| m.lines().next()).map(str::to_string))
+        .unwrap_or_default();
+    (title, pr_template().unwrap_or_default())
+}
+
+/// Title and description for a new PR, edited from `pr_defaults`. With
+/// `--yes` the defaults are used as they are.
+pub fn prompt_pr(branch: &str, parent: &str) -> StackResult<(String, String)> {
+    let (title, body) = pr_defaults(branch, parent);
+    if !interactive() && !title.is_empty() {
+        return Ok((title, body));
+    }
+
+    edit_pr_message(&title, &body, &format!("New PR: {} -> {}", branch, parent))
+}
+
+/// What is on the terminal right now, so streamed command output and the
+/// spinner line don't draw over each other.
+struct Terminal {
+    spinner: Option<String>,
+    /// Whether the spinner line is currently drawn.
+    drawn: bool,
+    /// Whether the cursor sits at the start of a line.
+    line_start: bool,
+    last_output: Instant,
+}
+
+static TERMINAL: Mutex<Option<Terminal>> = Mutex::new(None);
+
+fn with_terminal<T>(f: impl FnOnce(&mut Terminal) -> T) -> T {
+    let mut guard = TERMINAL.lock().unwrap_or_else(|e| e.into_inner());
+    let term = guard.get_or_insert_with(|| Terminal {
+        spinner: None,
+        drawn: false,
+        line_start: true,
+        last_output: Instant::now(),
+    });
+    f(term)
+}
+
+/// Write a chunk of a child's output, clearing the spinner line first.
+pub fn write_streamed(bytes: &[u8], to_stderr: bool) {
+    with_terminal(|term| {
+        if term.drawn {
+            eprint!("\r\x1b[2K");
+            term.drawn = false;
+        }
+        if to_stderr || json_output() {
+            let _ = io::stderr().write_all(bytes);
+            let _ = io::stderr().flush();
+        } else {
+            let _ = io::stdout().write_all(bytes);
+            let _ = io::stdout().flush();
+        }
+        term.line_start = matches!(bytes.last(), Some(b'\n' | b'\r'));
+        term.last_output = Instant::now();
+    });
+}
+
+/// An animated status line on stderr while a slow step runs, drawn only on
+/// a terminal and only while the step itself is quiet. It disappears when
+/// dropped.
+pub struct Spinner {
+    done: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Spinner {
+    pub fn start(message: &str) -> Spinner {
+        let done = Arc::new(AtomicBool::new(false));
+        if !io::stderr().is_terminal() || !ansi_terminal() || verbosity() == Verbosity::Quiet {
+            return Spinner { done, thread: None };
+        }
+
+        with_terminal(|term| term.spinner = Some(message.to_string()));
+        let stop = done.clone();
+        let thread = thread::spawn(move || {
+            const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+            let mut frame = 0;
+            while !stop.load(Ordering::Relaxed) {
+                with_terminal(|term| {
+                    let quiet = term.last_output.elapsed() > Duration::from_millis(300);
+                    if let Some(message) = &term.spinner
+                        && quiet
+                        && (term.line_start || term.drawn)
+                    {
+                        eprint!("\r\x1b[2K{} {}", FRAMES[frame % FRAMES.len()], message);
+                        let _ = io::stderr().flush();
+                        term.drawn = true;
+                    }
+                });
+                frame += 1;
+                thread::sleep(Duration::from_millis(80));
+            }
+        });
+        Spinner {
+            done,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        self.done.store(true, Ordering::Relaxed);
+        let _ = thread.join();
+        with_terminal(|term| {
+            if term.drawn {
+                eprint!("\r\x1b[2K");
+                let _ = io::stderr().flush();
+            }
+            term.spinner = None;
+            term.drawn = false;
+        });
+    }
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn aliases_expand_before_their_arguments() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.git(&["config", "stack.alias.sw", "switch"]);
+    repo.git(&["config", "stack.alias.home", "sw main"]);
+
+    repo.stack_ok(&["home"]);
+    assert_eq!(repo.current_branch(), "main");
+
+    repo.stack_ok(&["sw", "feat-a"]);
+    assert_eq!(repo.current_branch(), "feat-a");
+}
+
+#[test]
+fn aliases_cannot_replace_builtins_or_loop() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.git(&["config", "stack.alias.status", "switch main"]);
+    repo.git(&["config", "stack.alias.ping", "pong"]);
+    repo.git(&["config", "stack.alias.pong", "ping"]);
+
+    repo.stack_ok(&["status"]);
+    assert_eq!(repo.current_branch(), "feat-a");
+
+    let out = repo.stack(&["ping"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("Alias loop: ping -> pong -> ping"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn shell_aliases_and_external_commands_get_the_arguments() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "stack.alias.hi", "!echo hi from"]);
+    repo.install_program("stack-greet", "#!/bin/sh\necho \"greetings, $1\"\nexit 3\n");
+
+    let out = repo.stack_ok(&["hi", "alias"]);
+    assert_eq!(out.trim(), "hi from alias");
+
+    let out = repo.stack(&["greet", "world"]);
+    assert_eq!(out.status.code(), Some(3));
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout).trim(),
+        "greetings, world"
+    );
+
+    let out = repo.stack(&["no-such-command"]);
+    assert_eq!(out.status.code(), Some(2));
+}
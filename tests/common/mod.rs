@@ -0,0 +1,444 @@
+//! Throwaway repositories for end-to-end tests: a clone of a bare "remote",
+//! with a mock `gh` on PATH that keeps its PRs in files next to the repos.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use tempfile::TempDir;
+
+/// Stands in for the GitHub CLI. State lives under `$STACK_TEST_GH`:
+/// `calls` logs every invocation, `pr/<head>` holds each PR's base, and
+/// `prs.tsv` is what `gh pr list` prints for the status query. A
+/// `logged-out` file makes `gh auth token` fail, and each line of
+/// `fail-<cmd>-<sub>` fails one call with that line as the error.
+/// `reviews/<head>` is the reviews JSON `gh pr view` reports. Branch
+/// renames through `gh api` apply to the bare remote in `$STACK_TEST_REMOTE`,
+/// and `gh pr merge` squashes the head onto its `main` at once, like a merge
+/// queue with nothing else in it.
+const MOCK_GH: &str = r#"#!/bin/sh
+dir="$STACK_TEST_GH"
+echo "$*" >> "$dir/calls"
+[ "$1" = "--version" ] && { echo "gh version 0.0.0 (mock)"; exit 0; }
+sub="$1 $2"
+shift 2
+fail="$dir/fail-$(echo "$sub" | tr ' ' -)"
+if [ -s "$fail" ]; then
+    head -n 1 "$fail" >&2
+    sed -i 1d "$fail"
+    exit 1
+fi
+case "$sub" in
+"auth token")
+    [ -f "$dir/logged-out" ] && { echo "no oauth token found for github.com" >&2; exit 1; }
+    echo "gho_mock"
+    ;;
+"pr view")
+    [ -f "$dir/pr/$1" ] || { echo "no pull requests found for branch \"$1\"" >&2; exit 1; }
+    case "$*" in
+    *title,body*) printf '{"title":"%s","body":""}\n' "$1" ;;
+    *latestReviews*) cat "$dir/reviews/$1" 2>/dev/null ;;
+    esac
+    ;;
+"pr create")
+    while [ $# -gt 0 ]; do
+        case "$1" in --base) base="$2"; shift;; --head) head="$2"; shift;; --draft) draft=true;; esac
+        shift
+    done
+    mkdir -p "$dir/pr/$(dirname "$head")"
+    echo "$base" > "$dir/pr/$head"
+    n=$(($(cat "$dir/prs.tsv" 2>/dev/null | wc -l) + 1))
+    printf '%s\t%s\tOPEN\t\t\thttps://github.test/pr/%s\t%s\n' "$head" "$n" "$n" "${draft:-false}" >> "$dir/prs.tsv"
+    echo "https://github.test/pr/$n"
+    ;;
+"pr edit")
+    head="$1"
+    while [ $# -gt 0 ]; do
+        case "$1" in --base) echo "$2" > "$dir/pr/$head"; shift;; esac
+        shift
+    done
+    ;;
+"api -X")
+    # POST repos/OWNER/REPO/branches/OLD/rename -f new_name=NEW
+    old="${2#*/branches/}"
+    old="${old%/rename}"
+    new="${4#new_name=}"
+    git -C "$STACK_TEST_REMOTE" branch -m "$old" "$new" || exit 1
+    if [ -d "$dir/pr" ]; then
+        [ -f "$dir/pr/$old" ] && mv "$dir/pr/$old" "$dir/pr/$new"
+        for pr in "$dir"/pr/*; do
+            [ "$(cat "$pr")" = "$old" ] && echo "$new" > "$pr"
+        done
+    fi
+    [ -f "$dir/prs.tsv" ] && sed -i "s|^$old\t|$new\t|" "$dir/prs.tsv"
+    echo '{}'
+    ;;
+"pr close")
+    sed -i "s|^$1\t\([0-9]*\)\tOPEN\t|$1\t\1\tCLOSED\t|" "$dir/prs.tsv"
+    ;;
+"pr merge")
+    remote="$STACK_TEST_REMOTE"
+    commit=$(git -C "$remote" commit-tree "$1^{tree}" -p main -m "$1 (merge queue)") || exit 1
+    git -C "$remote" update-ref refs/heads/main "$commit"
+    sed -i "s|^$1\t\([0-9]*\)\tOPEN\t|$1\t\1\tMERGED\t|" "$dir/prs.tsv"
+    ;;
+"pr ready")
+    sed -i "/^$1\t/s/\ttrue$/\tfalse/" "$dir/prs.tsv"
+    ;;
+"pr list")
+    case "$*" in
+    *baseRefName*)
+        [ -d "$dir/pr" ] && (cd "$dir/pr" && find . -type f | sed 's|^\./||' | while read -r head; do
+            printf '%s\t%s\n' "$head" "$(cat "$head")"
+        done)
+        ;;
+    *) cat "$dir/prs.tsv" 2>/dev/null ;;
+    esac
+    ;;
+*)
+    echo "mock gh: unsupported command: $sub" >&2
+    exit 1
+    ;;
+esac
+"#;
+
+pub struct TestRepo {
+    _dir: TempDir,
+    /// The working clone every command runs in.
+    pub path: PathBuf,
+    /// The bare repository behind `origin`.
+    pub remote: PathBuf,
+    home: PathBuf,
+    bin: PathBuf,
+    gh: PathBuf,
+}
+
+impl TestRepo {
+    /// A clone of a fresh bare remote whose `main` has one commit.
+    pub fn new() -> Self {
+        let dir = TempDir::new().expect("create temp dir");
+        let root = dir.path().to_path_buf();
+        let repo = TestRepo {
+            path: root.join("work"),
+            remote: root.join("remote.git"),
+            home: root.join("home"),
+            bin: root.join("bin"),
+            gh: root.join("gh"),
+            _dir: dir,
+        };
+        for d in [&repo.home, &repo.bin, &repo.gh] {
+            fs::create_dir_all(d).unwrap();
+        }
+        let gh = repo.bin.join("gh");
+        fs::write(&gh, MOCK_GH).unwrap();
+        make_executable(&gh);
+
+        repo.run_git(&root, &["init", "-q", "--bare", "-b", "main", "remote.git"]);
+        repo.run_git(&root, &["init", "-q", "-b", "main", "work"]);
+        repo.git(&["remote", "add", "origin", repo.remote.to_str().unwrap()]);
+        repo.commit_file("README.md", "# test\n", "Initial commit");
+        repo.git(&["push", "-q", "-u", "origin", "main"]);
+        repo
+    }
+
+    fn command(&self, program: &Path, cwd: &Path) -> Command {
+        let mut cmd = Command::new(program);
+        let path = format!(
+            "{}:{}",
+            self.bin.display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+        cmd.current_dir(cwd)
+            .env("PATH", path)
+            .env("HOME", &self.home)
+            // Prompts read the answers each test pipes in
+            .env("STACK_INTERACTIVE", "1")
+            .env("XDG_CONFIG_HOME", self.home.join(".config"))
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_CONFIG_GLOBAL", self.home.join(".gitconfig"))
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .env("GIT_EDITOR", "true")
+            .env("STACK_TEST_GH", &self.gh)
+            .env("STACK_TEST_REMOTE", &self.remote)
+            .env_remove("GITHUB_TOKEN")
+            .env_remove("GH_TOKEN");
+        cmd
+    }
+
+    fn run_git(&self, cwd: &Path, args: &[&str]) -> String {
+        let out = self
+            .command(Path::new("git"), cwd)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "git {} failed:\n{}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr)
+        );
+        String::from_utf8_lossy(&out.stdout).trim().to_string()
+    }
+
+    /// Run git in the working clone, panicking if it fails.
+    pub fn git(&self, args: &[&str]) -> String {
+        self.run_git(&self.path, args)
+    }
+
+    /// Run git against the bare remote.
+    pub fn remote_git(&self, args: &[&str]) -> String {
+        self.run_git(&self.remote, args)
+    }
+
+    /// Run `stack` with `input` on stdin.
+    pub fn stack_with_input(&self, args: &[&str], input: &str) -> Output {
+        self.stack_with_env(args, input, &[])
+    }
+
+    /// Run `stack` with `input` on stdin and extra environment variables.
+    pub fn stack_with_env(&self, args: &[&str], input: &str, env: &[(&str, &str)]) -> Output {
+        let mut child = self
+            .command(Path::new(env!("CARGO_BIN_EXE_stack")), &self.path)
+            .args(args)
+            .envs(env.iter().copied())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // Commands that fail early never read stdin
+        let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+        child.wait_with_output().unwrap()
+    }
+
+    pub fn stack(&self, args: &[&str]) -> Output {
+        self.stack_with_input(args, "")
+    }
+
+    /// Run `stack` from `cwd` instead of the working clone.
+    pub fn stack_in(&self, cwd: &Path, args: &[&str]) -> Output {
+        self.command(Path::new(env!("CARGO_BIN_EXE_stack")), cwd)
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    }
+
+    /// Run `stack`, panicking unless it succeeds; returns stdout.
+    pub fn stack_ok(&self, args: &[&str]) -> String {
+        let out = self.stack(args);
+        assert_success(&out, args);
+        String::from_utf8_lossy(&out.stdout).to_string()
+    }
+
+    /// Write `name` and commit it on the current branch.
+    pub fn commit_file(&self, name: &str, contents: &str, message: &str) {
+        self.write_file(name, contents);
+        self.git(&["add", name]);
+        self.git(&["commit", "-q", "-m", message]);
+    }
+
+    pub fn write_file(&self, name: &str, contents: &str) {
+        let path = self.path.join(name);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(path, contents).unwrap();
+    }
+
+    /// Write an executable `name` in the working clone.
+    pub fn write_script(&self, name: &str, contents: &str) {
+        self.write_file(name, contents);
+        make_executable(&self.path.join(name));
+    }
+
+    /// `stack new <branch>` with one commit adding `<branch>.txt`.
+    pub fn new_branch(&self, branch: &str) {
+        self.write_file(&format!("{}.txt", branch), branch);
+        self.git(&["add", "."]);
+        self.stack_ok(&["new", branch, "-m", &format!("Add {}", branch)]);
+    }
+
+    pub fn current_branch(&self) -> String {
+        self.git(&["branch", "--show-current"])
+    }
+
+    pub fn parent(&self, branch: &str) -> Option<String> {
+        self.config(&format!("branch.{}.stack-parent", branch))
+    }
+
+    pub fn config(&self, key: &str) -> Option<String> {
+        let out = self
+            .command(Path::new("git"), &self.path)
+            .args(["config", "--get", key])
+            .output()
+            .unwrap();
+        out.status
+            .success()
+            .then(|| String::from_utf8_lossy(&out.stdout).trim().to_string())
+    }
+
+    pub fn branch_exists(&self, branch: &str) -> bool {
+        self.command(Path::new("git"), &self.path)
+            .args([
+                "rev-parse",
+                "--verify",
+                "-q",
+                &format!("refs/heads/{}", branch),
+            ])
+            .output()
+            .unwrap()
+            .status
+            .success()
+    }
+
+    pub fn is_ancestor(&self, ancestor: &str, descendant: &str) -> bool {
+        self.command(Path::new("git"), &self.path)
+            .args(["merge-base", "--is-ancestor", ancestor, descendant])
+            .status()
+            .unwrap()
+            .success()
+    }
+
+    /// Subjects of the commits in `range`, newest first.
+    pub fn subjects(&self, range: &str) -> Vec<String> {
+        self.git(&["log", "--format=%s", range])
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Base branch of the mock PR for `head`, if one was created.
+    pub fn pr_base(&self, head: &str) -> Option<String> {
+        fs::read_to_string(self.gh.join("pr").join(head))
+            .ok()
+            .map(|b| b.trim().to_string())
+    }
+
+    /// Make the mock `gh` act as if nobody ran `gh auth login`.
+    pub fn log_out_gh(&self) {
+        fs::write(self.gh.join("logged-out"), "").unwrap();
+    }
+
+    /// Fail the next calls of `gh <sub>` (`pr create`), one per message.
+    pub fn fail_gh(&self, sub: &str, messages: &[&str]) {
+        let mut text = messages.join("\n");
+        text.push('\n');
+        fs::write(
+            self.gh.join(format!("fail-{}", sub.replace(' ', "-"))),
+            text,
+        )
+        .unwrap();
+    }
+
+    /// Record an approving review of `head`'s PR by `login`.
+    pub fn approve_pr(&self, head: &str, login: &str) {
+        let path = self.gh.join("reviews").join(head);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(
+            path,
+            format!(
+                r#"{{"reviewRequests":[],"latestReviews":[{{"author":{{"login":"{}"}},"state":"APPROVED"}}]}}"#,
+                login
+            ),
+        )
+        .unwrap();
+    }
+
+    /// Take the mock `gh` off PATH, as on a machine without it.
+    pub fn uninstall_gh(&self) {
+        fs::remove_file(self.bin.join("gh")).unwrap();
+    }
+
+    /// Put an executable `script` called `name` on PATH.
+    pub fn install_program(&self, name: &str, script: &str) {
+        let path = self.bin.join(name);
+        fs::write(&path, script).unwrap();
+        make_executable(&path);
+    }
+
+    /// Every `gh` invocation so far, one per line.
+    pub fn gh_calls(&self) -> Vec<String> {
+        fs::read_to_string(self.gh.join("calls"))
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Report `head`'s PR as merged, as `gh pr list` would after a merge on
+    /// GitHub, and drop the cached PR status.
+    pub fn mark_pr_merged(&self, head: &str) {
+        self.set_pr_field(head, 2, "MERGED");
+    }
+
+    /// Report `head`'s PR as closed without merging.
+    pub fn mark_pr_closed(&self, head: &str) {
+        self.set_pr_field(head, 2, "CLOSED");
+    }
+
+    /// Report `checks` (comma-separated states) for `head`'s PR.
+    pub fn set_pr_checks(&self, head: &str, checks: &str) {
+        self.set_pr_field(head, 4, checks);
+    }
+
+    /// Whether `head`'s PR is a draft.
+    pub fn pr_is_draft(&self, head: &str) -> bool {
+        fs::read_to_string(self.gh.join("prs.tsv"))
+            .unwrap_or_default()
+            .lines()
+            .any(|row| row.starts_with(&format!("{}\t", head)) && row.ends_with("\ttrue"))
+    }
+
+    /// `OPEN`, `MERGED` or `CLOSED` for `head`'s PR.
+    pub fn pr_state(&self, head: &str) -> Option<String> {
+        fs::read_to_string(self.gh.join("prs.tsv"))
+            .unwrap_or_default()
+            .lines()
+            .map(|row| row.split('\t').collect::<Vec<_>>())
+            .find(|cols| cols.first() == Some(&head) && cols.len() > 2)
+            .map(|cols| cols[2].to_string())
+    }
+
+    fn set_pr_field(&self, head: &str, index: usize, value: &str) {
+        let list = self.gh.join("prs.tsv");
+        let rows = fs::read_to_string(&list).unwrap_or_default();
+        let mut out = String::new();
+        for row in rows.lines() {
+            let mut cols: Vec<&str> = row.split('\t').collect();
+            if cols.first() == Some(&head) && cols.len() > index {
+                cols[index] = value;
+            }
+            out.push_str(&cols.join("\t"));
+            out.push('\n');
+        }
+        fs::write(list, out).unwrap();
+        let _ = fs::remove_file(self.path.join(".git/stack/pr-cache"));
+    }
+}
+
+pub fn assert_success(out: &Output, args: &[&str]) {
+    assert!(
+        out.status.success(),
+        "stack {} failed ({}):\nstdout:\n{}\nstderr:\n{}",
+        args.join(" "),
+        out.status,
+        String::from_utf8_lossy(&out.stdout),
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) {}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn commands_on_detached_head_say_a_branch_is_needed() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.git(&["checkout", "-q", "--detach"]);
+
+    for args in [
+        &["new", "feat-b"][..],
+        &["restack"],
+        &["submit"],
+        &["squash"],
+    ] {
+        let out = repo.stack(args);
+        assert!(!out.status.success(), "stack {:?} succeeded", args);
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(stderr.contains("HEAD is detached at"), "{}", stderr);
+        assert!(
+            stderr.contains(&format!("`stack {}` needs a branch", args[0])),
+            "{}",
+            stderr
+        );
+    }
+    assert!(!repo.branch_exists("feat-b"));
+    assert!(repo.config("branch..stack-parent").is_none());
+}
+
+#[test]
+fn log_off_any_stack_shows_every_stack() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.git(&["checkout", "-q", "main"]);
+    repo.new_branch("other-a");
+    repo.git(&["checkout", "-q", "--detach"]);
+
+    let out = repo.stack_ok(&["log"]);
+    assert!(out.contains("feat-a"), "{}", out);
+    assert!(out.contains("other-a"), "{}", out);
+
+    repo.git(&["checkout", "-q", "-b", "scratch", "main"]);
+    let out = repo.stack_ok(&["log"]);
+    assert!(out.contains("feat-a"), "{}", out);
+}
+
+#[test]
+fn stack_commands_on_an_untracked_branch_explain_how_to_start_a_stack() {
+    let repo = TestRepo::new();
+    repo.git(&["checkout", "-q", "-b", "scratch"]);
+    repo.commit_file("a.txt", "a", "One");
+    repo.commit_file("b.txt", "b", "Two");
+
+    let out = repo.stack(&["squash"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("scratch is not part of a stack. Start one with `stack new`"),
+        "{}",
+        stderr
+    );
+    assert_eq!(repo.subjects("main..scratch"), ["Two", "One"]);
+}
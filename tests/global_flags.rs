@@ -0,0 +1,205 @@
+mod common;
+
+use std::fs;
+
+use common::TestRepo;
+
+#[test]
+fn dash_c_runs_against_another_directory() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    let parent_dir = repo.path.parent().unwrap();
+
+    let out = repo.stack_in(parent_dir, &["-C", "work", "status"]);
+    common::assert_success(&out, &["-C", "work", "status"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("Branch:   feat-a"));
+}
+
+#[test]
+fn dash_c_with_a_missing_directory_is_a_usage_error() {
+    let repo = TestRepo::new();
+    let out = repo.stack(&["-C", "does-not-exist", "log"]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn quiet_suppresses_progress_but_keeps_errors() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.commit_file("more.txt", "more", "More on feat-a");
+
+    let out = repo.stack(&["--quiet", "restack"]);
+    common::assert_success(&out, &["--quiet", "restack"]);
+    assert!(out.stdout.is_empty());
+    assert!(out.stderr.is_empty());
+    assert!(repo.is_ancestor("feat-a", "feat-b"));
+
+    let out = repo.stack(&["-q", "switch", "no-such-branch"]);
+    assert!(!out.status.success());
+    assert!(!out.stderr.is_empty());
+}
+
+#[test]
+fn verbose_echoes_git_commands() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.commit_file("more.txt", "more", "More on feat-a");
+
+    let out = repo.stack(&["-v", "restack"]);
+    common::assert_success(&out, &["-v", "restack"]);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("> git rebase --onto feat-a"), "{}", stderr);
+}
+
+#[test]
+fn yes_skips_the_land_confirmation() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+
+    repo.stack_ok(&["--yes", "land"]);
+
+    assert_eq!(repo.current_branch(), "main");
+    assert_eq!(
+        repo.remote_git(&["log", "-1", "--format=%s", "main"]),
+        "Add feat-a"
+    );
+}
+
+#[test]
+fn yes_fails_fast_when_input_is_needed() {
+    let repo = TestRepo::new();
+    repo.write_file("a.txt", "a");
+    repo.git(&["add", "."]);
+
+    let out = repo.stack_with_input(&["-y", "new"], "Add a\n");
+
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--yes"));
+    assert_eq!(repo.current_branch(), "main");
+}
+
+#[test]
+fn a_held_lock_blocks_mutating_commands_until_force_unlock() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    std::fs::write(repo.path.join(".git/stack.lock"), "4242 restack\n").unwrap();
+
+    let out = repo.stack(&["restack"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("Another stack operation is in progress (`stack restack`, pid 4242)"),
+        "{}",
+        stderr
+    );
+
+    // Read-only commands don't need it
+    repo.stack_ok(&["log"]);
+
+    repo.stack_ok(&["--force-unlock", "restack"]);
+    assert!(!repo.path.join(".git/stack.lock").exists());
+}
+
+#[test]
+fn foreach_commands_can_run_stack_under_its_lock() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    let stack = env!("CARGO_BIN_EXE_stack");
+
+    repo.stack_ok(&["foreach", "--", stack, "freeze"]);
+    assert_eq!(
+        repo.config("branch.feat-a.stack-frozen").as_deref(),
+        Some("true")
+    );
+    assert!(!repo.path.join(".git/stack.lock").exists());
+}
+
+#[test]
+fn shell_snippets_run_through_stack_shell() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    let shell = repo.path.join(".git/logging-sh");
+    fs::write(
+        &shell,
+        "#!/bin/sh\necho \"$2\" >> \"$(dirname \"$0\")/shell-log\"\nexec sh \"$@\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&shell, fs::Permissions::from_mode(0o755)).unwrap();
+    repo.git(&["config", "stack.hook.pre-submit", "echo hook"]);
+    let env = [("STACK_SHELL", shell.to_str().unwrap())];
+
+    let out = repo.stack_with_env(&["foreach", "--", "echo on $STACK_BRANCH"], "", &env);
+    common::assert_success(&out, &["foreach"]);
+    let out = repo.stack_with_env(&["submit"], "", &env);
+    common::assert_success(&out, &["submit"]);
+
+    let log = fs::read_to_string(repo.path.join(".git/shell-log")).unwrap();
+    // The editor for the new PR's message goes through it too
+    assert_eq!(
+        log,
+        "echo on $STACK_BRANCH \"$@\"\necho hook \"$@\"\ntrue \"$@\"\n"
+    );
+}
+
+#[test]
+fn editor_crlf_line_endings_are_normalized() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+
+    let out = repo.stack_with_env(
+        &["pr", "edit"],
+        "",
+        &[(
+            "GIT_EDITOR",
+            "printf 'New title\\r\\n\\r\\nFirst\\r\\nSecond\\r\\n' >",
+        )],
+    );
+    common::assert_success(&out, &["pr", "edit"]);
+
+    let calls = repo.gh_calls();
+    let edit = calls
+        .iter()
+        .rev()
+        .find(|c| c.starts_with("pr edit"))
+        .unwrap();
+    assert_eq!(edit, "pr edit feat-a --title New title --body First");
+    assert!(!calls.join("\n").contains('\r'));
+}
+
+#[test]
+fn offline_reads_from_the_last_pr_list_and_refuses_to_submit() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+    repo.stack_ok(&["log"]);
+    // However old the cached list is
+    let cache = repo.path.join(".git/stack/pr-cache");
+    let cached = fs::read_to_string(&cache).unwrap();
+    let (_, list) = cached.split_once('\n').unwrap();
+    fs::write(&cache, format!("0\n{}", list)).unwrap();
+    // Finding gh is fine; running it is not
+    let remote_calls = || {
+        repo.gh_calls()
+            .into_iter()
+            .filter(|c| c != "--version")
+            .count()
+    };
+    let calls = remote_calls();
+
+    let out = repo.stack_ok(&["--offline", "log"]);
+    assert!(out.contains("+1/-0 vs main  (#1 open)"), "{}", out);
+    repo.stack_ok(&["--offline", "status"]);
+    assert_eq!(remote_calls(), calls);
+
+    let out = repo.stack(&["--offline", "submit"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("needs the network"));
+    assert_eq!(remote_calls(), calls);
+}
@@ -0,0 +1,439 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn land_squashes_each_branch_into_trunk_and_cleans_up() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack"]);
+
+    let out = repo.stack_with_input(&["land"], "y\n");
+    common::assert_success(&out, &["land"]);
+
+    assert_eq!(repo.current_branch(), "main");
+    assert_eq!(
+        repo.remote_git(&["log", "--format=%s", "main"])
+            .lines()
+            .collect::<Vec<_>>(),
+        ["Add feat-b", "Add feat-a", "Initial commit"]
+    );
+    for branch in ["feat-a", "feat-b"] {
+        assert!(!repo.branch_exists(branch));
+        assert!(repo.parent(branch).is_none());
+        assert!(
+            repo.remote_git(&["branch", "--list", branch]).is_empty(),
+            "{} still on the remote",
+            branch
+        );
+    }
+}
+
+#[test]
+fn land_declined_at_the_prompt_changes_nothing() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+
+    let out = repo.stack_with_input(&["land"], "n\n");
+    common::assert_success(&out, &["land"]);
+
+    assert_eq!(repo.current_branch(), "feat-a");
+    assert_eq!(repo.subjects("main"), ["Initial commit"]);
+}
+
+#[test]
+fn land_refuses_a_dirty_worktree() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.write_file("feat-a.txt", "uncommitted");
+
+    let out = repo.stack_with_input(&["land"], "y\n");
+    assert_eq!(out.status.code(), Some(6));
+}
+
+#[test]
+fn land_a_lower_branch_leaves_the_checkout_alone() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.new_branch("feat-c");
+    repo.write_file("scratch.txt", "work in progress");
+    repo.stack_ok(&["submit", "--stack"]);
+
+    let out = repo.stack_with_input(&["land", "feat-b"], "y\n");
+    common::assert_success(&out, &["land", "feat-b"]);
+
+    assert_eq!(repo.current_branch(), "feat-c");
+    assert!(repo.path.join("scratch.txt").exists());
+    assert_eq!(
+        repo.remote_git(&["log", "--format=%s", "main"])
+            .lines()
+            .collect::<Vec<_>>(),
+        ["Add feat-b", "Add feat-a", "Initial commit"]
+    );
+    assert!(!repo.branch_exists("feat-a"));
+    assert!(!repo.branch_exists("feat-b"));
+    assert!(repo.branch_exists("feat-c"));
+    assert_eq!(repo.git(&["worktree", "list"]).lines().count(), 1);
+}
+
+#[test]
+fn land_range_must_start_above_landed_branches() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "main"]);
+
+    let out = repo.stack_with_input(&["land", "--from", "feat-b", "--to", "feat-b"], "y\n");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Land from feat-a"));
+
+    let out = repo.stack_with_input(&["land", "--from", "feat-a", "--to", "feat-b"], "y\n");
+    common::assert_success(&out, &["land", "--from", "feat-a", "--to", "feat-b"]);
+    assert_eq!(repo.current_branch(), "main");
+    assert_eq!(
+        repo.subjects("main"),
+        ["Add feat-b", "Add feat-a", "Initial commit"]
+    );
+}
+
+#[test]
+fn squash_land_keeps_every_commit_message() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.commit_file("fix.txt", "fix", "Fix the edge case\n\nDetails here.");
+
+    let out = repo.stack_with_input(&["land"], "y\n");
+    common::assert_success(&out, &["land"]);
+
+    assert_eq!(
+        repo.git(&["log", "-1", "--format=%B", "main"]),
+        "Add feat-a\n\n* Add feat-a\n\n* Fix the edge case\n\nDetails here."
+    );
+}
+
+#[test]
+fn land_edit_opens_the_squash_message_in_the_editor() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+
+    let out = repo.stack_with_env(
+        &["land", "--edit"],
+        "y\n",
+        &[("GIT_EDITOR", "sed -i.bak 1s/^/Reviewed:\\ /")],
+    );
+    common::assert_success(&out, &["land", "--edit"]);
+    assert_eq!(repo.subjects("main")[0], "Reviewed: Add feat-a");
+}
+
+#[test]
+fn land_no_delete_keeps_local_branches_and_keep_remote_the_remote_ones() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack"]);
+
+    let out = repo.stack_with_input(&["land", "feat-a", "--no-delete"], "y\n");
+    common::assert_success(&out, &["land", "feat-a", "--no-delete"]);
+    assert!(repo.branch_exists("feat-a"));
+    assert_eq!(repo.parent("feat-a").as_deref(), Some("main"));
+    assert!(repo.remote_git(&["branch", "--list", "feat-a"]).is_empty());
+
+    repo.git(&["checkout", "-q", "main"]);
+    repo.git(&["branch", "-q", "-D", "feat-a"]);
+    repo.git(&["config", "branch.feat-b.stack-parent", "main"]);
+    repo.git(&["checkout", "-q", "feat-b"]);
+    let out = repo.stack_with_input(&["land", "--keep-remote"], "y\n");
+    common::assert_success(&out, &["land", "--keep-remote"]);
+    assert!(!repo.branch_exists("feat-b"));
+    assert!(!repo.remote_git(&["branch", "--list", "feat-b"]).is_empty());
+}
+
+#[test]
+fn land_retargets_the_prs_left_on_a_landed_branch() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack"]);
+
+    let out = repo.stack_with_input(&["land", "feat-a"], "y\n");
+    common::assert_success(&out, &["land", "feat-a"]);
+
+    assert_eq!(repo.parent("feat-b").as_deref(), Some("main"));
+    assert_eq!(repo.pr_base("feat-b").as_deref(), Some("main"));
+    assert!(repo.remote_git(&["branch", "--list", "feat-a"]).is_empty());
+}
+
+#[test]
+fn land_renumbers_the_footers_of_the_prs_left_behind() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.new_branch("feat-c");
+    repo.stack_ok(&["submit", "--stack"]);
+    let before = repo.gh_calls().join("\n");
+    assert!(before.contains("part 2 of 3"), "{}", before);
+    assert!(!before.contains("part 1 of 2"), "{}", before);
+
+    repo.mark_pr_merged("feat-a");
+    let out = repo.stack_with_input(&["land", "feat-a"], "y\n");
+    common::assert_success(&out, &["land", "feat-a"]);
+
+    let after = repo.gh_calls().join("\n");
+    assert!(
+        after.contains("This PR is part 1 of 2 in a stack:"),
+        "{}",
+        after
+    );
+}
+
+#[test]
+fn land_wait_lands_once_checks_pass() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+    repo.set_pr_checks("feat-a", "SUCCESS,SKIPPED");
+
+    let out = repo.stack_with_input(&["land", "--wait"], "y\n");
+    common::assert_success(&out, &["land", "--wait"]);
+    assert!(!repo.branch_exists("feat-a"));
+}
+
+#[test]
+fn land_wait_stops_on_failing_checks() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+    repo.set_pr_checks("feat-a", "SUCCESS,FAILURE");
+
+    let out = repo.stack_with_input(&["land", "--wait"], "y\n");
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("Checks failed on feat-a"), "{}", stderr);
+    assert!(repo.branch_exists("feat-a"));
+}
+
+#[test]
+fn land_wait_gives_up_after_the_timeout() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+    repo.set_pr_checks("feat-a", "SUCCESS,PENDING");
+    repo.git(&["config", "stack.ci-poll-interval", "1"]);
+
+    let args = ["land", "--wait", "--timeout", "1s"];
+    let out = repo.stack_with_input(&args, "y\n");
+    assert!(!out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("Waiting for checks: feat-a (1 of 2 checks done)"),
+        "{}",
+        stdout
+    );
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("Timed out"), "{}", stderr);
+    assert!(repo.branch_exists("feat-a"));
+}
+
+#[test]
+fn land_merge_queue_queues_each_pr_and_restacks_the_next_onto_trunk() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack"]);
+    repo.git(&["config", "stack.ci-poll-interval", "0"]);
+
+    let out = repo.stack_with_input(&["land", "--merge-queue"], "y\n");
+    common::assert_success(&out, &["land", "--merge-queue"]);
+
+    assert_eq!(
+        repo.remote_git(&["log", "--format=%s", "main"])
+            .lines()
+            .collect::<Vec<_>>(),
+        [
+            "feat-b (merge queue)",
+            "feat-a (merge queue)",
+            "Initial commit"
+        ]
+    );
+    assert_eq!(
+        repo.remote_git(&["ls-tree", "--name-only", "main"])
+            .lines()
+            .collect::<Vec<_>>(),
+        ["README.md", "feat-a.txt", "feat-b.txt"]
+    );
+    let calls = repo.gh_calls();
+    let position = |call: &str| calls.iter().position(|c| c == call).unwrap();
+    assert!(
+        position("pr merge feat-a --auto --squash") < position("pr edit feat-b --base main"),
+        "{:?}",
+        calls
+    );
+    assert!(calls.contains(&"pr merge feat-b --auto --squash".to_string()));
+    assert_eq!(repo.current_branch(), "main");
+    assert!(!repo.branch_exists("feat-a") && !repo.branch_exists("feat-b"));
+}
+
+#[test]
+fn land_merge_queue_continue_queues_again_after_a_failed_enqueue() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+    repo.fail_gh("pr merge", &["merge queue is full"]);
+
+    let out = repo.stack_with_input(&["land", "--merge-queue"], "y\n");
+    assert!(!out.status.success());
+    assert!(repo.branch_exists("feat-a"));
+
+    let out = repo.stack(&["land", "--continue"]);
+    common::assert_success(&out, &["land", "--continue"]);
+    assert!(!repo.branch_exists("feat-a"));
+    assert_eq!(
+        repo.remote_git(&["log", "-1", "--format=%s", "main"])
+            .trim(),
+        "feat-a (merge queue)"
+    );
+}
+
+#[test]
+fn land_continue_finishes_a_land_that_stopped_partway() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack"]);
+
+    // The remote turns away pushes to main while `reject` exists
+    let hook = repo.remote.join("hooks/pre-receive");
+    std::fs::write(
+        &hook,
+        "#!/bin/sh\nwhile read old new ref; do\n  [ \"$ref\" = refs/heads/main ] && [ -f reject ] && exit 1\ndone\nexit 0\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::fs::write(repo.remote.join("reject"), "").unwrap();
+
+    let out = repo.stack_with_input(&["land"], "y\n");
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("stack land --continue"), "{}", stderr);
+    assert!(!repo.branch_exists("feat-a"));
+
+    // The half-landed stack isn't planned again
+    let stderr = String::from_utf8_lossy(&repo.stack(&["land"]).stderr).to_string();
+    assert!(
+        stderr.contains("A land of feat-a, feat-b is in progress"),
+        "{}",
+        stderr
+    );
+
+    std::fs::remove_file(repo.remote.join("reject")).unwrap();
+    let out = repo.stack_ok(&["land", "--continue"]);
+    assert!(out.contains("Done! Landed 2 branch(es)."), "{}", out);
+    assert_eq!(
+        repo.remote_git(&["log", "--format=%s", "main"])
+            .lines()
+            .collect::<Vec<_>>(),
+        ["Add feat-b", "Add feat-a", "Initial commit"]
+    );
+    assert!(!repo.path.join(".git/stack/land-plan.json").exists());
+}
+
+#[test]
+fn land_signs_off_and_adds_trailers_to_signed_squash_commits() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+    repo.approve_pr("feat-a", "octocat");
+
+    // Signs anything, the way gpg reports it to git
+    let gpg = repo.path.join(".git/fake-gpg");
+    std::fs::write(
+        &gpg,
+        "#!/bin/sh\ncat >/dev/null\necho '[GNUPG:] SIG_CREATED D 1 8 00 0 0' >&2\nprintf -- '-----BEGIN PGP SIGNATURE-----\\nfake\\n-----END PGP SIGNATURE-----\\n'\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&gpg, std::fs::Permissions::from_mode(0o755)).unwrap();
+    repo.git(&["config", "gpg.program", gpg.to_str().unwrap()]);
+    repo.git(&["config", "commit.gpgsign", "true"]);
+    repo.git(&["config", "--add", "stack.land-trailer", "Reviewed-by"]);
+    repo.git(&[
+        "config",
+        "--add",
+        "stack.land-trailer",
+        "Change-Type: feature",
+    ]);
+
+    let out = repo.stack_with_input(&["land", "--signoff"], "y\n");
+    common::assert_success(&out, &["land", "--signoff"]);
+
+    let commit = repo.remote_git(&["cat-file", "commit", "main"]);
+    assert!(
+        commit.contains("gpgsig -----BEGIN PGP SIGNATURE-----"),
+        "{}",
+        commit
+    );
+    assert!(
+        commit.ends_with(
+            "Add feat-a\n\nReviewed-by: octocat\nChange-Type: feature\nSigned-off-by: Test <test@example.com>"
+        ),
+        "{}",
+        commit
+    );
+}
+
+#[test]
+fn land_runs_commit_hooks_from_a_relative_hooks_path() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.commit_file("wip.txt", "wip", "WIP on feat-b");
+    repo.new_branch("feat-c");
+    repo.stack_ok(&["submit", "--stack"]);
+    // Untracked, so the worktree landing happens in doesn't have it
+    repo.write_script(
+        ".githooks/commit-msg",
+        "#!/bin/sh\ngrep -q WIP \"$1\" && exit 1\nprintf '\\nHooked: yes\\n' >> \"$1\"\n",
+    );
+    repo.git(&["config", "core.hooksPath", ".githooks"]);
+
+    let out = repo.stack_with_input(&["land", "feat-b"], "y\n");
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("Committing feat-b into main failed"),
+        "{}",
+        stderr
+    );
+
+    repo.stack_ok(&["land", "--continue", "--no-verify"]);
+    let first = repo.remote_git(&["log", "-1", "--format=%B", "main~1"]);
+    assert!(first.ends_with("Hooked: yes"), "{}", first);
+    let second = repo.remote_git(&["log", "-1", "--format=%B", "main"]);
+    assert!(second.starts_with("Add feat-b"), "{}", second);
+    assert!(!second.contains("Hooked"), "{}", second);
+}
+
+#[test]
+fn land_fills_in_the_message_template_from_the_pr() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+    repo.git(&[
+        "config",
+        "stack.land-message",
+        "{pr_title} (#{pr_number})\n\n{body}\n\nLanded from {branch}: {subject}",
+    ]);
+
+    let out = repo.stack_with_input(&["land"], "y\n");
+    common::assert_success(&out, &["land"]);
+
+    assert_eq!(
+        repo.remote_git(&["log", "-1", "--format=%B", "main"]),
+        "feat-a (#1)\n\nLanded from feat-a: Add feat-a"
+    );
+}
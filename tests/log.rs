@@ -0,0 +1,277 @@
+mod common;
+
+use std::fs;
+
+use common::TestRepo;
+
+#[test]
+fn log_format_mermaid_draws_the_stack_with_pr_links() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack"]);
+
+    let out = repo.stack_ok(&["log", "--format", "mermaid"]);
+
+    assert!(out.starts_with("graph TD\n"), "{}", out);
+    assert!(out.contains("    n0[\"main\"]\n"), "{}", out);
+    assert!(
+        out.contains("    n1[\"feat-a<br/>#35;1 open\"]\n"),
+        "{}",
+        out
+    );
+    assert!(out.contains("    n0 --> n1\n    n1 --> n2\n"), "{}", out);
+    assert!(
+        out.contains("    click n2 \"https://github.test/pr/2\" _blank\n"),
+        "{}",
+        out
+    );
+}
+
+#[test]
+fn log_format_dot_emits_a_digraph() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+
+    let out = repo.stack_ok(&["log", "--format=dot"]);
+
+    assert!(out.starts_with("digraph stack {\n"), "{}", out);
+    assert!(out.contains("    \"main\" -> \"feat-a\";\n"), "{}", out);
+    assert!(out.contains("    \"feat-a\" -> \"feat-b\";\n"), "{}", out);
+    assert!(out.contains("    \"feat-b\" [penwidth=3];\n"), "{}", out);
+    assert!(out.ends_with("}\n"), "{}", out);
+}
+
+#[test]
+fn log_rejects_unknown_formats() {
+    let repo = TestRepo::new();
+    let out = repo.stack(&["log", "--format", "svg"]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn log_colors_only_when_asked_and_follows_the_theme() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+
+    // Piped output stays plain
+    let out = repo.stack_ok(&["log"]);
+    assert!(!out.contains('\x1b'), "{}", out);
+
+    repo.git(&["config", "stack.color", "always"]);
+    repo.git(&["config", "stack.theme.current", "bold magenta"]);
+    let out = repo.stack_ok(&["log"]);
+    assert!(out.contains("\x1b[2mmain\x1b[0m"), "{:?}", out);
+    assert!(out.contains("\x1b[1;35mfeat-b ◀\x1b[0m"), "{:?}", out);
+
+    repo.git(&["checkout", "-q", "main"]);
+    repo.commit_file("trunk.txt", "trunk", "Trunk moves on");
+    let out = repo.stack_ok(&["log"]);
+    assert!(out.contains("\x1b[33m(needs restack)\x1b[0m"), "{:?}", out);
+}
+
+#[test]
+fn log_stat_shows_each_branch_against_its_parent() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.commit_file("lines.txt", "one\ntwo\nthree\n", "Add lines");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["new", "feat-c"]);
+
+    let out = repo.stack_ok(&["log", "--stat"]);
+
+    assert!(
+        out.contains("    2 files changed, 4 insertions(+), 0 deletions(-)\n"),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains("1 file changed, 1 insertion(+), 0 deletions(-)\n"),
+        "{}",
+        out
+    );
+    assert!(out.contains("no changes\n"), "{}", out);
+    assert!(!repo.stack_ok(&["log"]).contains("changed"));
+}
+
+#[test]
+fn notes_show_in_log_and_status_until_cleared() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["note", "waiting", "on schema review"]);
+    repo.stack_ok(&["note", "--branch", "feat-a", "rebase after the freeze"]);
+
+    let log = repo.stack_ok(&["log"]);
+    assert!(log.contains("Note: waiting on schema review\n"), "{}", log);
+    assert!(log.contains("Note: rebase after the freeze\n"), "{}", log);
+    let status = repo.stack_ok(&["status"]);
+    assert!(
+        status.contains("Note:     waiting on schema review\n"),
+        "{}",
+        status
+    );
+    assert_eq!(repo.stack_ok(&["note"]), "waiting on schema review\n");
+
+    repo.stack_ok(&["note", "--clear"]);
+    assert!(!repo.stack_ok(&["status"]).contains("Note:"));
+    assert_eq!(repo.stack_ok(&["note"]), "No note on feat-b.\n");
+}
+
+#[test]
+fn log_infers_the_parent_of_a_branch_made_outside_stack() {
+    let repo = TestRepo::new();
+    repo.git(&["checkout", "-q", "-b", "base"]);
+    repo.commit_file("base.txt", "base", "Add base");
+    repo.stack_ok(&["new", "feat-a"]);
+    repo.commit_file("feat-a.txt", "a", "Add feat-a");
+
+    let out = repo.stack_with_input(&["log"], "n\n");
+    common::assert_success(&out, &["log"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.starts_with("\nmain\n"), "{}", stdout);
+    assert!(
+        stdout.contains("base  +1/-0 vs main (inferred)"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("stack it on main?"), "{}", stdout);
+    assert_eq!(repo.parent("base"), None);
+
+    // A guess is never taken on --yes's say-so
+    let out = repo.stack_ok(&["-y", "log"]);
+    assert!(out.contains("(inferred)"), "{}", out);
+    assert!(!out.contains("stack it on main?"), "{}", out);
+    assert_eq!(repo.parent("base"), None);
+
+    repo.stack_with_input(&["log"], "y\n");
+    assert_eq!(repo.parent("base").as_deref(), Some("main"));
+    assert!(!repo.stack_ok(&["log"]).contains("(inferred)"));
+
+    repo.git(&["config", "--unset", "branch.base.stack-parent"]);
+    repo.stack_ok(&["log", "--adopt-inferred"]);
+    assert_eq!(repo.parent("base").as_deref(), Some("main"));
+}
+
+#[test]
+fn siblings_show_in_creation_order_until_reordered() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-z");
+    repo.git(&["checkout", "-q", "main"]);
+    repo.new_branch("feat-m");
+    repo.git(&["checkout", "-q", "main"]);
+    repo.new_branch("feat-a");
+    repo.git(&["checkout", "-q", "main"]);
+
+    let position = |out: &str, branch: &str| out.find(branch).unwrap();
+    let out = repo.stack_ok(&["log"]);
+    assert!(
+        position(&out, "feat-z") < position(&out, "feat-m"),
+        "{}",
+        out
+    );
+    assert!(
+        position(&out, "feat-m") < position(&out, "feat-a"),
+        "{}",
+        out
+    );
+
+    repo.stack_ok(&["reorder-children", "feat-a"]);
+
+    let out = repo.stack_ok(&["log"]);
+    assert!(
+        position(&out, "feat-a") < position(&out, "feat-z"),
+        "{}",
+        out
+    );
+    assert!(
+        position(&out, "feat-z") < position(&out, "feat-m"),
+        "{}",
+        out
+    );
+
+    let out = repo.stack(&["reorder-children", "feat-a", "feat-nope"]);
+    assert!(!out.status.success());
+}
+
+#[test]
+fn a_parent_loop_fails_log_land_and_restack_instead_of_hanging() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["config", "branch.feat-a.stack-parent", "feat-b"]);
+
+    for args in [&["log"][..], &["land"], &["restack"]] {
+        let out = repo.stack_with_input(args, "y\n");
+        assert_eq!(out.status.code(), Some(7), "stack {:?}", args);
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(
+            stderr.contains("in a loop: feat-a -> feat-b -> feat-a")
+                && stderr.contains("git config branch.feat-b.stack-parent <parent>"),
+            "{}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn stats_sums_up_every_stack() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack"]);
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.commit_file("more.txt", "more", "More on feat-a");
+    repo.git(&["checkout", "-q", "main"]);
+    repo.new_branch("feat-x");
+
+    let out = repo.stack_ok(&["stats"]);
+
+    assert!(
+        out.contains("Stacks:          2 (average depth 1.5, deepest 2)"),
+        "{}",
+        out
+    );
+    assert!(out.contains("Branches:        3 unlanded"), "{}", out);
+    assert!(
+        out.contains("Awaiting review: 2 (feat-a, feat-b)"),
+        "{}",
+        out
+    );
+    assert!(out.contains("Needs restack:   1 (feat-b)"), "{}", out);
+    assert!(
+        out.contains("Oldest unlanded: feat-a (0 days, #1 open)"),
+        "{}",
+        out
+    );
+}
+
+#[test]
+fn log_and_status_show_the_cached_prs_without_asking_the_forge() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    // Submitting leaves the new PR in the cache
+    repo.stack_ok(&["submit"]);
+    let cache = repo.path.join(".git/stack/pr-cache");
+    let cached = fs::read_to_string(&cache).unwrap();
+    let (_, list) = cached.split_once('\n').unwrap();
+    fs::write(&cache, format!("0\n{}", list)).unwrap();
+    let pr_lists = || {
+        repo.gh_calls()
+            .iter()
+            .filter(|c| c.starts_with("pr list"))
+            .count()
+    };
+    let before = pr_lists();
+
+    let out = repo.stack_ok(&["log"]);
+    assert!(out.contains("(#1 open)"), "{}", out);
+    repo.stack_ok(&["status"]);
+    repo.stack_ok(&["stats"]);
+    assert_eq!(pr_lists(), before);
+
+    repo.stack_ok(&["log", "--refresh"]);
+    assert_eq!(pr_lists(), before + 1);
+}
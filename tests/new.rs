@@ -0,0 +1,162 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn new_records_the_current_branch_as_parent() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+
+    assert_eq!(repo.current_branch(), "feat-b");
+    assert_eq!(repo.parent("feat-a").as_deref(), Some("main"));
+    assert_eq!(repo.parent("feat-b").as_deref(), Some("feat-a"));
+    assert_eq!(repo.subjects("main..feat-b"), ["Add feat-b", "Add feat-a"]);
+}
+
+#[test]
+fn new_with_parent_stacks_on_that_branch() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["new", "side", "--parent", "feat-a"]);
+
+    assert_eq!(repo.parent("side").as_deref(), Some("feat-a"));
+    assert!(repo.is_ancestor("feat-a", "side"));
+    assert!(!repo.is_ancestor("feat-b", "side"));
+}
+
+#[test]
+fn new_commit_with_nothing_staged_creates_no_branch() {
+    let repo = TestRepo::new();
+    let out = repo.stack(&["new", "empty", "-m", "Nothing here"]);
+
+    assert!(!out.status.success());
+    assert!(!repo.branch_exists("empty"));
+}
+
+#[test]
+fn insert_moves_children_onto_the_new_branch() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.write_file("mid.txt", "mid");
+    repo.git(&["add", "."]);
+    repo.stack_ok(&["insert", "mid", "-m", "Add mid"]);
+
+    assert_eq!(repo.parent("mid").as_deref(), Some("feat-a"));
+    assert_eq!(repo.parent("feat-b").as_deref(), Some("mid"));
+    assert_eq!(
+        repo.subjects("main..feat-b"),
+        ["Add feat-b", "Add mid", "Add feat-a"]
+    );
+}
+
+#[test]
+fn top_and_bottom_jump_to_the_ends_of_the_stack() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.new_branch("feat-c");
+    repo.git(&["checkout", "-q", "feat-b"]);
+
+    repo.stack_ok(&["top"]);
+    assert_eq!(repo.current_branch(), "feat-c");
+    repo.stack_ok(&["bottom"]);
+    assert_eq!(repo.current_branch(), "feat-a");
+    repo.git(&["checkout", "-q", "main"]);
+    repo.stack_ok(&["bottom"]);
+    assert_eq!(repo.current_branch(), "feat-a");
+}
+
+#[test]
+fn top_asks_which_tip_when_the_stack_branches() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.new_branch("feat-c");
+    repo.git(&["checkout", "-q", "feat-a"]);
+
+    let out = repo.stack_with_input(&["top"], "2\n");
+    common::assert_success(&out, &["top"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("1) feat-b"), "{}", stdout);
+    assert_eq!(repo.current_branch(), "feat-c");
+}
+
+#[test]
+fn switch_dash_and_recent_go_back_to_visited_branches() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.new_branch("feat-c");
+    repo.stack_ok(&["switch", "feat-a"]);
+
+    repo.stack_ok(&["switch", "-"]);
+    assert_eq!(repo.current_branch(), "feat-c");
+    repo.stack_ok(&["switch", "-"]);
+    assert_eq!(repo.current_branch(), "feat-a");
+
+    let out = repo.stack_with_input(&["recent"], "2\n");
+    common::assert_success(&out, &["recent"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("1) feat-c\n  2) feat-b\n"), "{}", stdout);
+    assert_eq!(repo.current_branch(), "feat-b");
+}
+
+#[test]
+fn clone_stack_copies_a_branch_and_its_descendants_under_new_names() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.new_branch("feat-c");
+    repo.stack_ok(&["submit", "--stack"]);
+
+    repo.stack_ok(&["clone-stack", "feat-b"]);
+
+    assert_eq!(repo.parent("feat-b-alt").as_deref(), Some("feat-a"));
+    assert_eq!(repo.parent("feat-c-alt").as_deref(), Some("feat-b-alt"));
+    assert_eq!(
+        repo.git(&["rev-parse", "feat-c-alt"]),
+        repo.git(&["rev-parse", "feat-c"])
+    );
+    assert_eq!(
+        repo.config("branch.feat-c-alt.stack-base"),
+        repo.config("branch.feat-c.stack-base")
+    );
+    assert_eq!(repo.parent("feat-c").as_deref(), Some("feat-b"));
+    assert_eq!(repo.current_branch(), "feat-c");
+    assert!(repo.pr_base("feat-b-alt").is_none());
+
+    repo.stack_ok(&["clone-stack", "feat-c", "--prefix", "try/", "--suffix", ""]);
+    assert_eq!(repo.parent("try/feat-c").as_deref(), Some("feat-b"));
+
+    let out = repo.stack(&["clone-stack", "feat-b"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("'feat-b-alt' already exists"));
+}
+
+#[test]
+fn onboard_skips_graphite_refs_that_are_not_metadata() {
+    let repo = TestRepo::new();
+    repo.git(&["checkout", "-q", "-b", "feat-a"]);
+    repo.commit_file("feat-a.txt", "feat-a", "Add feat-a");
+    repo.write_file("meta.json", r#"{"parentBranchName": "main"}"#);
+    let blob = repo.git(&["hash-object", "-w", "meta.json"]);
+    std::fs::remove_file(repo.path.join("meta.json")).unwrap();
+    repo.git(&["update-ref", "refs/branch-metadata/feat-a", &blob]);
+    let commit = repo.git(&["rev-parse", "main"]);
+    repo.git(&["update-ref", "refs/branch-metadata/bogus", &commit]);
+
+    let out = repo.stack(&["onboard"]);
+    common::assert_success(&out, &["onboard"]);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("skipping refs/branch-metadata/bogus"),
+        "{}",
+        stderr
+    );
+    assert_eq!(repo.parent("feat-a").as_deref(), Some("main"));
+}
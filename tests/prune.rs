@@ -0,0 +1,170 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn prune_moves_children_of_a_deleted_branch_down() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "main"]);
+    repo.git(&["branch", "-D", "feat-a"]);
+
+    let out = repo.stack_ok(&["prune"]);
+
+    assert!(out.contains("feat-a (branch no longer exists)"), "{}", out);
+    assert!(
+        out.contains("Moving feat-b onto main (was on feat-a)"),
+        "{}",
+        out
+    );
+    assert_eq!(repo.parent("feat-b").as_deref(), Some("main"));
+}
+
+#[test]
+fn prune_drops_entries_left_behind_by_a_deleted_ref() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.git(&["checkout", "-q", "main"]);
+    repo.git(&["update-ref", "-d", "refs/heads/feat-a"]);
+
+    repo.stack_ok(&["prune"]);
+
+    assert!(repo.parent("feat-a").is_none());
+    assert!(repo.config("branch.feat-a.stack-base").is_none());
+}
+
+#[test]
+fn prune_drops_branches_merged_into_trunk() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "main"]);
+    repo.git(&["merge", "-q", "--ff-only", "feat-a"]);
+    repo.git(&["push", "-q", "origin", "main"]);
+
+    let out = repo.stack_ok(&["prune"]);
+
+    assert!(out.contains("feat-a (merged into main)"), "{}", out);
+    assert!(repo.parent("feat-a").is_none());
+    assert_eq!(repo.parent("feat-b").as_deref(), Some("main"));
+    // The branch itself stays; only its metadata goes
+    assert!(repo.branch_exists("feat-a"));
+}
+
+#[test]
+fn prune_leaves_new_empty_branches() {
+    let repo = TestRepo::new();
+    repo.stack_ok(&["new", "feat-a"]);
+
+    let out = repo.stack_ok(&["prune"]);
+
+    assert!(out.contains("Nothing to prune."), "{}", out);
+    assert_eq!(repo.parent("feat-a").as_deref(), Some("main"));
+}
+
+#[test]
+fn prune_dry_run_changes_nothing() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "main"]);
+    repo.git(&["branch", "-D", "feat-a"]);
+
+    let out = repo.stack_ok(&["prune", "--dry-run"]);
+
+    assert!(out.contains("Would move feat-b onto main"), "{}", out);
+    assert_eq!(repo.parent("feat-b").as_deref(), Some("feat-a"));
+}
+
+#[test]
+fn fix_from_prs_restores_lost_and_wrong_parents() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.new_branch("feat-c");
+    repo.stack_ok(&["submit", "--stack"]);
+    repo.git(&["config", "--unset", "branch.feat-b.stack-parent"]);
+    repo.git(&["config", "branch.feat-c.stack-parent", "main"]);
+
+    let out = repo.stack_ok(&["fix", "--from-prs"]);
+
+    assert!(
+        out.contains("Setting feat-b -> feat-a (had no parent)"),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains("Setting feat-c -> feat-b (was main)"),
+        "{}",
+        out
+    );
+    assert!(!out.contains("feat-a ->"), "{}", out);
+    assert_eq!(repo.parent("feat-b").as_deref(), Some("feat-a"));
+    assert_eq!(repo.parent("feat-c").as_deref(), Some("feat-b"));
+    assert!(repo.config("branch.feat-b.stack-base").is_some());
+}
+
+#[test]
+fn tidy_lists_stale_closed_and_merged_branches() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-old");
+    repo.git(&[
+        "commit",
+        "-q",
+        "--amend",
+        "--no-edit",
+        "--date=2020-01-01T00:00:00",
+    ]);
+    repo.git(&["rebase", "-q", "--committer-date-is-author-date", "HEAD~1"]);
+    repo.git(&["checkout", "-q", "main"]);
+    repo.new_branch("feat-closed");
+    repo.git(&["checkout", "-q", "main"]);
+    repo.new_branch("feat-merged");
+    repo.stack_ok(&["submit"]);
+    repo.git(&["checkout", "-q", "feat-closed"]);
+    repo.stack_ok(&["submit"]);
+    repo.mark_pr_closed("feat-closed");
+    repo.mark_pr_merged("feat-merged");
+
+    let out = repo.stack_ok(&["tidy", "--dry-run"]);
+
+    assert!(
+        out.contains("No commits in 30 days:\n  feat-old (last commit "),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains("PRs closed without merging:\n  feat-closed (#2 closed)"),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains("Stacks merged into trunk:\n  feat-merged (1 branch(es) merged)"),
+        "{}",
+        out
+    );
+    assert!(repo.branch_exists("feat-old"));
+    assert_eq!(repo.parent("feat-merged").as_deref(), Some("main"));
+}
+
+#[test]
+fn tidy_deletes_what_it_found_and_moves_children_down() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack"]);
+    repo.mark_pr_closed("feat-a");
+    repo.git(&["checkout", "-q", "main"]);
+
+    let out = repo.stack_ok(&["-y", "tidy"]);
+
+    assert!(
+        out.contains("Moving feat-b onto main (was on feat-a)"),
+        "{}",
+        out
+    );
+    assert!(!repo.branch_exists("feat-a"));
+    assert!(repo.parent("feat-a").is_none());
+    assert_eq!(repo.parent("feat-b").as_deref(), Some("main"));
+}
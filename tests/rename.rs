@@ -0,0 +1,54 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn rename_moves_metadata_and_children_to_the_new_name() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+
+    repo.stack_ok(&["rename", "feat-x"]);
+
+    assert_eq!(repo.current_branch(), "feat-x");
+    assert!(!repo.branch_exists("feat-a"));
+    assert_eq!(repo.parent("feat-x").as_deref(), Some("main"));
+    assert!(repo.config("branch.feat-x.stack-base").is_some());
+    assert_eq!(repo.parent("feat-b").as_deref(), Some("feat-x"));
+}
+
+#[test]
+fn rename_renames_the_remote_branch_and_its_prs() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack"]);
+
+    repo.stack_ok(&["rename", "feat-a", "feat-x"]);
+
+    assert!(repo.remote_git(&["branch", "--list", "feat-a"]).is_empty());
+    assert_eq!(
+        repo.remote_git(&["rev-parse", "feat-x"]),
+        repo.git(&["rev-parse", "feat-x"])
+    );
+    assert_eq!(
+        repo.git(&["rev-parse", "--abbrev-ref", "feat-x@{upstream}"]),
+        "origin/feat-x"
+    );
+    assert_eq!(repo.pr_base("feat-x").as_deref(), Some("main"));
+    assert_eq!(repo.pr_base("feat-b").as_deref(), Some("feat-x"));
+    assert_eq!(repo.current_branch(), "feat-b");
+}
+
+#[test]
+fn rename_refuses_an_existing_name() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.git(&["branch", "feat-b"]);
+
+    let out = repo.stack(&["rename", "feat-b"]);
+
+    assert!(!out.status.success());
+    assert!(repo.branch_exists("feat-a"));
+}
@@ -0,0 +1,629 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn amend_restacks_children_onto_the_new_commit() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.new_branch("feat-c");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.write_file("feat-a.txt", "amended");
+    repo.git(&["add", "."]);
+    repo.stack_ok(&["amend"]);
+
+    assert_eq!(repo.current_branch(), "feat-a");
+    assert!(repo.is_ancestor("feat-a", "feat-b"));
+    assert!(repo.is_ancestor("feat-b", "feat-c"));
+    // The pre-amend commit is dropped, not replayed on top
+    assert_eq!(
+        repo.subjects("main..feat-c"),
+        ["Add feat-c", "Add feat-b", "Add feat-a"]
+    );
+}
+
+#[test]
+fn restack_follows_a_parent_that_moved() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.commit_file("more.txt", "more", "More on feat-a");
+    repo.stack_ok(&["restack"]);
+
+    assert!(repo.is_ancestor("feat-a", "feat-b"));
+    assert_eq!(
+        repo.subjects("main..feat-b"),
+        ["Add feat-b", "More on feat-a", "Add feat-a"]
+    );
+}
+
+#[test]
+fn restack_moves_children_of_a_merged_pr_onto_trunk() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack"]);
+
+    // Squash-merge feat-a on the host, then pull trunk
+    repo.git(&["checkout", "-q", "main"]);
+    repo.git(&["merge", "-q", "--squash", "feat-a"]);
+    repo.git(&["commit", "-q", "-m", "Add feat-a (#1)"]);
+    repo.git(&["push", "-q", "origin", "main"]);
+    repo.mark_pr_merged("feat-a");
+
+    repo.git(&["checkout", "-q", "feat-b"]);
+    repo.stack_ok(&["restack"]);
+
+    assert_eq!(repo.parent("feat-b").as_deref(), Some("main"));
+    assert_eq!(repo.subjects("main..feat-b"), ["Add feat-b"]);
+}
+
+#[test]
+fn restack_streams_git_output_as_it_runs() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.commit_file("more.txt", "more", "More on feat-a");
+
+    let out = repo.stack(&["restack"]);
+    common::assert_success(&out, &["restack"]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Successfully rebased"));
+}
+
+#[test]
+fn restack_rebases_branches_checked_out_in_other_worktrees_there() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.new_branch("feat-c");
+    let wt = repo.path.parent().unwrap().join("wt-c");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.git(&["worktree", "add", "-q", wt.to_str().unwrap(), "feat-c"]);
+    repo.commit_file("more.txt", "more", "More on feat-a");
+
+    repo.stack_ok(&["restack"]);
+
+    assert_eq!(repo.current_branch(), "feat-a");
+    assert!(repo.is_ancestor("feat-a", "feat-b"));
+    assert!(repo.is_ancestor("feat-b", "feat-c"));
+    assert!(wt.join("more.txt").exists());
+}
+
+#[test]
+fn restack_skips_a_dirty_worktree_branch() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    let wt = repo.path.parent().unwrap().join("wt-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.git(&["worktree", "add", "-q", wt.to_str().unwrap(), "feat-b"]);
+    std::fs::write(wt.join("feat-b.txt"), "in progress").unwrap();
+    repo.commit_file("more.txt", "more", "More on feat-a");
+
+    let out = repo.stack(&["restack"]);
+    common::assert_success(&out, &["restack"]);
+
+    assert!(String::from_utf8_lossy(&out.stderr).contains("skipping feat-b"));
+    assert!(!repo.is_ancestor("feat-a", "feat-b"));
+}
+
+#[test]
+fn restack_leaves_frozen_branches_alone() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.new_branch("feat-c");
+    repo.stack_ok(&["freeze", "feat-b"]);
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.commit_file("more.txt", "more", "More on feat-a");
+    let frozen_tip = repo.git(&["rev-parse", "feat-b"]);
+
+    let out = repo.stack(&["restack"]);
+    common::assert_success(&out, &["restack"]);
+
+    assert!(String::from_utf8_lossy(&out.stderr).contains("skipping feat-b: it is frozen"));
+    assert_eq!(repo.git(&["rev-parse", "feat-b"]), frozen_tip);
+    assert!(repo.is_ancestor("feat-b", "feat-c"));
+    assert!(repo.stack_ok(&["log"]).contains("feat-b (frozen)"));
+
+    repo.stack_ok(&["unfreeze", "feat-b"]);
+    repo.stack_ok(&["restack"]);
+    assert!(repo.is_ancestor("feat-a", "feat-b"));
+    assert!(repo.is_ancestor("feat-b", "feat-c"));
+}
+
+#[test]
+fn restack_from_trunk_moves_the_whole_stack_onto_the_remote_trunk() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.new_branch("feat-c");
+    // Someone else lands a change on the remote's main
+    repo.git(&["checkout", "-q", "main"]);
+    repo.commit_file("upstream.txt", "new", "Upstream change");
+    repo.git(&["push", "-q", "origin", "main"]);
+    repo.git(&["reset", "-q", "--hard", "HEAD~1"]);
+    repo.git(&["update-ref", "refs/remotes/origin/main", "main"]);
+    repo.git(&["checkout", "-q", "feat-b"]);
+
+    repo.stack_ok(&["restack", "--from-trunk"]);
+
+    assert_eq!(repo.current_branch(), "feat-b");
+    assert_eq!(repo.subjects("main~1..main"), ["Upstream change"]);
+    for (parent, child) in [
+        ("main", "feat-a"),
+        ("feat-a", "feat-b"),
+        ("feat-b", "feat-c"),
+    ] {
+        assert!(repo.is_ancestor(parent, child), "{} on {}", child, parent);
+    }
+    assert_eq!(
+        repo.subjects("main..feat-c"),
+        ["Add feat-c", "Add feat-b", "Add feat-a"]
+    );
+}
+
+#[test]
+fn amend_refuses_to_rewrite_trunk() {
+    let repo = TestRepo::new();
+    let before = repo.git(&["rev-parse", "main"]);
+    repo.write_file("oops.txt", "oops");
+    repo.git(&["add", "."]);
+
+    let out = repo.stack(&["amend"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("main is protected, so `stack amend` won't rewrite it"),
+        "{}",
+        stderr
+    );
+    assert_eq!(repo.git(&["rev-parse", "main"]), before);
+}
+
+#[test]
+fn restack_leaves_protected_branches_alone() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "--add", "stack.protected", "release/*"]);
+    repo.new_branch("release/1.0");
+    let before = repo.git(&["rev-parse", "release/1.0"]);
+    repo.git(&["checkout", "-q", "main"]);
+    repo.commit_file("trunk.txt", "trunk", "Trunk moves on");
+
+    let out = repo.stack(&["restack"]);
+    common::assert_success(&out, &["restack"]);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("skipping release/1.0: it is protected"),
+        "{}",
+        stderr
+    );
+    assert_eq!(repo.git(&["rev-parse", "release/1.0"]), before);
+
+    repo.git(&["checkout", "-q", "release/1.0"]);
+    let out = repo.stack(&["squash"]);
+    assert!(!out.status.success());
+}
+
+#[test]
+fn move_reparents_the_branch_and_brings_its_children() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.new_branch("feat-c");
+    repo.git(&["checkout", "-q", "feat-b"]);
+
+    repo.stack_ok(&["move", "--onto", "main"]);
+
+    assert_eq!(repo.current_branch(), "feat-b");
+    assert_eq!(repo.parent("feat-b").as_deref(), Some("main"));
+    assert_eq!(repo.subjects("main..feat-b"), ["Add feat-b"]);
+    assert_eq!(repo.subjects("main..feat-c"), ["Add feat-c", "Add feat-b"]);
+}
+
+#[test]
+fn move_without_a_recorded_base_only_takes_the_branchs_own_commits() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["config", "--unset", "branch.feat-b.stack-base"]);
+
+    repo.stack_ok(&["move", "--onto", "main"]);
+
+    assert_eq!(repo.parent("feat-b").as_deref(), Some("main"));
+    assert_eq!(repo.subjects("main..feat-b"), ["Add feat-b"]);
+}
+
+#[test]
+fn move_without_onto_picks_from_the_tree() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.git(&["checkout", "-q", "main"]);
+    repo.new_branch("other");
+
+    let out = repo.stack_with_input(&["move"], "2\n");
+    common::assert_success(&out, &["move"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("1) main (current parent)"), "{}", stdout);
+    assert!(stdout.contains("2)   feat-a"), "{}", stdout);
+    assert!(!stdout.contains(") other"), "{}", stdout);
+
+    assert_eq!(repo.parent("other").as_deref(), Some("feat-a"));
+    assert_eq!(repo.subjects("main..other"), ["Add other", "Add feat-a"]);
+}
+
+#[test]
+fn move_refuses_to_go_onto_its_own_descendant() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+
+    let out = repo.stack(&["move", "--onto", "feat-b"]);
+    assert!(!out.status.success());
+    assert_eq!(repo.parent("feat-a").as_deref(), Some("main"));
+}
+
+#[test]
+fn move_only_goes_onto_local_branches() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.git(&["tag", "v1", "main"]);
+
+    let out = repo.stack(&["move", "--onto", "v1"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("Branch 'v1' does not exist"), "{}", stderr);
+    assert_eq!(repo.parent("feat-a").as_deref(), Some("main"));
+}
+
+#[test]
+fn restack_offers_to_delete_a_branch_left_empty() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.new_branch("feat-c");
+    repo.stack_ok(&["submit", "--stack"]);
+    // feat-a picks up feat-b's change, leaving feat-b with nothing
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.write_file("feat-b.txt", "feat-b");
+    repo.git(&["add", "."]);
+
+    let out = repo.stack_with_input(&["amend"], "y\n");
+    common::assert_success(&out, &["amend"]);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("feat-b has no commits left; its changes are already in feat-a"),
+        "{}",
+        stderr
+    );
+
+    assert!(!repo.branch_exists("feat-b"));
+    assert_eq!(repo.parent("feat-c").as_deref(), Some("feat-a"));
+    assert_eq!(repo.subjects("feat-a..feat-c"), ["Add feat-c"]);
+    assert_eq!(repo.pr_state("feat-b").as_deref(), Some("CLOSED"));
+    assert!(repo.remote_git(&["branch", "--list", "feat-b"]).is_empty());
+}
+
+#[test]
+fn restack_keeps_an_empty_branch_when_told_to() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.write_file("feat-b.txt", "feat-b");
+    repo.git(&["add", "."]);
+
+    let out = repo.stack_with_input(&["amend"], "n\n");
+    common::assert_success(&out, &["amend"]);
+    assert!(repo.branch_exists("feat-b"));
+
+    // Branches that never had commits aren't reported
+    repo.stack_ok(&["new", "feat-empty"]);
+    let out = repo.stack(&["restack"]);
+    assert!(!String::from_utf8_lossy(&out.stderr).contains("feat-empty has no commits"));
+}
+
+#[test]
+fn amend_insert_adds_a_fixup_that_autosquash_folds_in() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    let reviewed = repo.git(&["rev-parse", "feat-a"]);
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.write_file("feat-a.txt", "fixed");
+
+    repo.stack_ok(&["amend", "--insert", "-a"]);
+
+    assert_eq!(
+        repo.subjects("main..feat-a"),
+        ["fixup! Add feat-a", "Add feat-a"]
+    );
+    assert!(repo.is_ancestor(&reviewed, "feat-a"));
+    assert!(repo.is_ancestor("feat-a", "feat-b"));
+
+    repo.git(&["checkout", "-q", "feat-b"]);
+    repo.stack_ok(&["autosquash"]);
+
+    assert_eq!(repo.subjects("main..feat-b"), ["Add feat-b", "Add feat-a"]);
+    assert_eq!(repo.subjects("main..feat-a"), ["Add feat-a"]);
+    assert_eq!(repo.git(&["show", "feat-a:feat-a.txt"]), "fixed");
+    assert_eq!(repo.current_branch(), "feat-b");
+}
+
+#[test]
+fn restack_output_json_reports_rebases_and_conflicts() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.commit_file("more.txt", "more", "More on feat-a");
+
+    let out = repo.stack(&["restack", "--output", "json"]);
+    common::assert_success(&out, &["restack", "--output", "json"]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "{\"branch\":\"feat-b\",\"event\":\"rebased\",\"onto\":\"feat-a\"}\n"
+    );
+
+    repo.commit_file("feat-b.txt", "clashes", "Clash with feat-b");
+    let out = repo.stack(&["restack", "--output", "json"]);
+    assert!(!out.status.success());
+    let event: serde_json::Value =
+        serde_json::from_str(String::from_utf8_lossy(&out.stdout).trim()).unwrap();
+    assert_eq!(event["event"], "conflict");
+    assert_eq!(event["branch"], "feat-b");
+    assert_eq!(event["onto"], "feat-a");
+}
+
+#[test]
+fn restack_stashes_untracked_files_in_the_way_and_puts_them_back() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.commit_file("more.txt", "more", "More on feat-a");
+    // Checking out feat-b would overwrite this
+    repo.write_file("feat-b.txt", "scratch");
+    repo.write_file("more.txt", "edited");
+
+    let out = repo.stack(&["restack"]);
+    common::assert_success(&out, &["restack"]);
+
+    assert!(repo.is_ancestor("feat-a", "feat-b"));
+    assert_eq!(repo.current_branch(), "feat-a");
+    let read = |name: &str| std::fs::read_to_string(repo.path.join(name)).unwrap();
+    assert_eq!(read("feat-b.txt"), "scratch");
+    assert_eq!(read("more.txt"), "edited");
+    assert!(repo.git(&["stash", "list"]).is_empty());
+}
+
+#[test]
+fn a_restack_stopped_on_conflicts_restores_the_stash_on_continue() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.commit_file("feat-b.txt", "clashes", "Clash with feat-b");
+    repo.write_file("notes.txt", "notes");
+
+    let out = repo.stack(&["restack"]);
+    assert!(!out.status.success());
+    assert!(!repo.path.join("notes.txt").exists());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("`stack continue` puts them back"),
+        "{}",
+        stderr
+    );
+
+    repo.write_file("feat-b.txt", "resolved");
+    repo.git(&["add", "feat-b.txt"]);
+    let out = repo.stack(&["continue"]);
+    common::assert_success(&out, &["continue"]);
+
+    assert_eq!(
+        std::fs::read_to_string(repo.path.join("notes.txt")).unwrap(),
+        "notes"
+    );
+    assert!(repo.git(&["stash", "list"]).is_empty());
+}
+
+#[test]
+fn continue_runs_the_rest_of_the_restack_and_returns_to_the_start() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.new_branch("feat-c");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.commit_file("feat-b.txt", "clashes", "Clash with feat-b");
+
+    let out = repo.stack(&["restack"]);
+    assert_eq!(out.status.code(), Some(5));
+    assert!(!repo.is_ancestor("feat-a", "feat-c"));
+
+    repo.write_file("feat-b.txt", "resolved");
+    repo.git(&["add", "feat-b.txt"]);
+    let out = repo.stack(&["continue"]);
+    common::assert_success(&out, &["continue"]);
+
+    assert!(repo.is_ancestor("feat-a", "feat-b"));
+    assert!(repo.is_ancestor("feat-a", "feat-c"));
+    assert_eq!(repo.current_branch(), "feat-a");
+}
+
+#[test]
+fn reorder_records_the_new_order_and_finishes_on_continue() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    // Not on main, so feat-b can't go there without a conflict
+    repo.commit_file("feat-a.txt", "changed", "Change feat-a.txt");
+
+    let editor = "printf 'feat-b\\nfeat-a\\n' >";
+    let out = repo.stack_with_env(&["reorder"], "", &[("GIT_EDITOR", editor)]);
+    assert_eq!(out.status.code(), Some(5));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("stack continue"), "{}", stderr);
+    assert_eq!(repo.parent("feat-b").as_deref(), Some("main"));
+    assert_eq!(repo.parent("feat-a").as_deref(), Some("feat-b"));
+
+    repo.git(&["rm", "-q", "feat-a.txt"]);
+    let out = repo.stack(&["continue"]);
+    common::assert_success(&out, &["continue"]);
+
+    assert!(repo.is_ancestor("main", "feat-b"));
+    assert!(repo.is_ancestor("feat-b", "feat-a"));
+    assert!(!repo.is_ancestor("feat-a", "feat-b"));
+    assert_eq!(repo.current_branch(), "feat-b");
+}
+
+#[test]
+fn reorder_refuses_frozen_branches() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["freeze", "feat-a"]);
+
+    let editor = "printf 'feat-b\\nfeat-a\\n' >";
+    let out = repo.stack_with_env(&["reorder"], "", &[("GIT_EDITOR", editor)]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("feat-a is frozen"));
+    assert_eq!(repo.parent("feat-b").as_deref(), Some("feat-a"));
+}
+
+#[test]
+fn restack_check_lists_conflicts_without_moving_anything() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.new_branch("feat-c");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.commit_file("feat-b.txt", "clashes", "Clash with feat-b");
+    let before = repo.git(&["rev-parse", "feat-b", "feat-c"]);
+
+    let out = repo.stack(&["restack", "--check"]);
+    assert!(!out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("feat-b onto feat-a: conflicts in feat-b.txt"),
+        "{}",
+        stdout
+    );
+    // feat-c goes onto feat-b as feat-b would come out, which is clean
+    assert!(stdout.contains("feat-c onto feat-b: clean"), "{}", stdout);
+    assert_eq!(repo.git(&["rev-parse", "feat-b", "feat-c"]), before);
+    assert_eq!(repo.current_branch(), "feat-a");
+
+    repo.git(&["checkout", "-q", "feat-c"]);
+    let out = repo.stack_ok(&["restack", "--check"]);
+    assert!(out.contains("Nothing to restack"), "{}", out);
+}
+
+#[test]
+fn restack_passes_rebase_flags_through_to_each_rebase() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.commit_file("feat-b.txt", "fixed", "fixup! Add feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.commit_file("more.txt", "more", "More on feat-a");
+    repo.stack_ok(&[
+        "restack",
+        "--",
+        "--autosquash",
+        "--committer-date-is-author-date",
+    ]);
+
+    assert_eq!(
+        repo.subjects("main..feat-b"),
+        ["Add feat-b", "More on feat-a", "Add feat-a"]
+    );
+    assert_eq!(repo.git(&["show", "feat-b:feat-b.txt"]), "fixed");
+
+    let out = repo.stack(&["restack", "--", "--exec", "make"]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn a_restack_that_fails_partway_returns_to_the_starting_branch() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.new_branch("feat-c");
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.commit_file("more.txt", "more", "More on feat-a");
+    // Let feat-b through, then refuse feat-c
+    repo.write_script(
+        ".git/hooks/pre-rebase",
+        "#!/bin/sh\ntest \"$2\" != feat-c\n",
+    );
+
+    let out = repo.stack(&["restack"]);
+    assert!(!out.status.success());
+    assert!(repo.is_ancestor("feat-a", "feat-b"));
+    assert_eq!(repo.current_branch(), "feat-a");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("Returned to feat-a"), "{}", stderr);
+}
+
+#[test]
+fn squash_runs_commit_hooks_unless_told_not_to() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.commit_file("more.txt", "more", "More on feat-a");
+    repo.write_script(".git/hooks/commit-msg", "#!/bin/sh\nexit 1\n");
+
+    assert!(!repo.stack(&["squash"]).status.success());
+    assert_eq!(
+        repo.subjects("main..feat-a"),
+        ["More on feat-a", "Add feat-a"]
+    );
+
+    repo.stack_ok(&["squash", "--no-verify"]);
+    assert_eq!(repo.subjects("main..feat-a"), ["Add feat-a"]);
+}
+
+#[test]
+fn absorb_runs_commit_hooks_unless_told_not_to() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.write_file("feat-a.txt", "feat-a fixed");
+    repo.git(&["add", "feat-a.txt"]);
+    repo.write_script(".git/hooks/commit-msg", "#!/bin/sh\nexit 1\n");
+    let tip = repo.git(&["rev-parse", "feat-b"]);
+
+    assert!(!repo.stack(&["absorb"]).status.success());
+    assert_eq!(repo.git(&["rev-parse", "feat-b"]), tip);
+    assert_eq!(repo.git(&["diff", "--cached", "--name-only"]), "feat-a.txt");
+
+    repo.stack_ok(&["absorb", "--no-verify"]);
+    assert_eq!(repo.subjects("main..feat-b"), ["Add feat-b", "Add feat-a"]);
+    assert_eq!(repo.git(&["show", "feat-a:feat-a.txt"]), "feat-a fixed");
+}
+
+#[test]
+fn absorb_leaves_an_amended_parents_old_commits_out_of_the_child() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    // Amended behind stack's back, so feat-b still has the old commit
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.git(&["commit", "-q", "--amend", "-m", "Add feat-a, amended"]);
+    repo.git(&["checkout", "-q", "feat-b"]);
+    let tip = repo.git(&["rev-parse", "feat-b"]);
+    repo.write_file("feat-a.txt", "feat-a fixed");
+    repo.git(&["add", "feat-a.txt"]);
+
+    let out = repo.stack_ok(&["absorb"]);
+
+    assert!(out.contains("feat-a.txt:1 stays staged"), "{}", out);
+    assert_eq!(repo.git(&["rev-parse", "feat-b"]), tip);
+    assert_eq!(repo.git(&["diff", "--cached", "--name-only"]), "feat-a.txt");
+}
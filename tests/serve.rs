@@ -0,0 +1,42 @@
+mod common;
+
+use common::TestRepo;
+use serde_json::Value;
+
+#[test]
+fn serve_answers_json_rpc_requests_line_by_line() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    let input = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"tree"}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"run","params":{"args":["freeze","feat-a"]}}"#,
+        r#"{"jsonrpc":"2.0","id":3,"method":"status"}"#,
+        r#"{"jsonrpc":"2.0","id":4,"method":"nope"}"#,
+        "not json",
+    ]
+    .join("\n");
+
+    let out = repo.stack_with_input(&["serve"], &input);
+    common::assert_success(&out, &["serve"]);
+    let responses: Vec<Value> = String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(responses.len(), 5);
+
+    let tree = &responses[0]["result"];
+    assert_eq!(tree["current"], "feat-b");
+    assert_eq!(tree["branches"][0]["name"], "feat-a");
+    assert_eq!(tree["branches"][0]["children"][0], "feat-b");
+    assert_eq!(tree["branches"][1]["parent"], "feat-a");
+    assert_eq!(responses[1]["result"]["exit_code"], 0);
+    assert_eq!(
+        repo.config("branch.feat-a.stack-frozen").as_deref(),
+        Some("true")
+    );
+    assert_eq!(responses[2]["result"]["branch"], "feat-b");
+    assert_eq!(responses[2]["result"]["behind"], 0);
+    assert_eq!(responses[3]["error"]["code"], -32601);
+    assert_eq!(responses[4]["error"]["code"], -32700);
+}
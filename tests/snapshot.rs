@@ -0,0 +1,33 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn restore_puts_branches_and_metadata_back_as_they_were() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    let before = repo.git(&["rev-parse", "feat-a", "feat-b"]);
+    let base = repo.config("branch.feat-b.stack-base");
+    repo.stack_ok(&["snapshot", "before-move"]);
+
+    repo.git(&["checkout", "-q", "feat-a"]);
+    repo.commit_file("more.txt", "more", "More on feat-a");
+    repo.stack_ok(&["restack"]);
+    repo.stack_ok(&["freeze", "feat-b"]);
+    repo.git(&["checkout", "-q", "main"]);
+    repo.git(&["branch", "-D", "feat-a"]);
+
+    let out = repo.stack_ok(&["snapshots"]);
+    assert!(out.contains("before-move"), "{}", out);
+    assert!(out.contains("feat-a, feat-b"), "{}", out);
+
+    repo.stack_ok(&["restore", "before-move"]);
+    assert_eq!(repo.git(&["rev-parse", "feat-a", "feat-b"]), before);
+    assert_eq!(repo.parent("feat-b").as_deref(), Some("feat-a"));
+    assert_eq!(repo.config("branch.feat-b.stack-base"), base);
+    assert_eq!(repo.config("branch.feat-b.stack-frozen"), None);
+
+    let out = repo.stack(&["restore", "nope"]);
+    assert!(!out.status.success());
+}
@@ -0,0 +1,618 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn submit_stack_pushes_every_branch_and_opens_prs_on_parents() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack"]);
+
+    for branch in ["feat-a", "feat-b"] {
+        assert_eq!(
+            repo.remote_git(&["rev-parse", branch]),
+            repo.git(&["rev-parse", branch])
+        );
+    }
+    assert_eq!(repo.pr_base("feat-a").as_deref(), Some("main"));
+    assert_eq!(repo.pr_base("feat-b").as_deref(), Some("feat-a"));
+}
+
+#[test]
+fn resubmit_updates_existing_prs_instead_of_creating_new_ones() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+    repo.commit_file("fix.txt", "fix", "Address review");
+    repo.stack_ok(&["submit"]);
+
+    let calls = repo.gh_calls();
+    assert_eq!(
+        calls.iter().filter(|c| c.starts_with("pr create")).count(),
+        1
+    );
+    assert!(calls.iter().any(|c| c.starts_with("pr edit feat-a")));
+    assert_eq!(
+        repo.remote_git(&["rev-parse", "feat-a"]),
+        repo.git(&["rev-parse", "feat-a"])
+    );
+}
+
+#[test]
+fn submit_push_only_pushes_without_touching_prs() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit", "--push-only"]);
+
+    assert_eq!(
+        repo.remote_git(&["rev-parse", "feat-a"]),
+        repo.git(&["rev-parse", "feat-a"])
+    );
+    assert!(!repo.gh_calls().iter().any(|c| c.starts_with("pr ")));
+}
+
+#[test]
+fn submit_no_push_updates_prs_without_pushing() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    let out = repo.stack(&["submit", "--no-push"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("Not on the remote yet: feat-a"),
+        "{}",
+        stderr
+    );
+
+    repo.stack_ok(&["submit"]);
+    let pushed = repo.git(&["rev-parse", "feat-a"]);
+    repo.commit_file("fix.txt", "fix", "Address review");
+    repo.stack_ok(&["submit", "--no-push"]);
+
+    assert_eq!(repo.remote_git(&["rev-parse", "feat-a"]), pushed);
+    let calls = repo.gh_calls();
+    assert_eq!(
+        calls
+            .iter()
+            .filter(|c| c.starts_with("pr edit feat-a --base"))
+            .count(),
+        1
+    );
+}
+
+/// Push a commit to `branch` on the remote as a teammate would, leaving the
+/// local branch and its remote-tracking ref where they were.
+fn teammate_pushes(repo: &TestRepo, branch: &str) {
+    let tracking = format!("refs/remotes/origin/{}", branch);
+    let seen = repo.git(&["rev-parse", &tracking]);
+    repo.git(&["checkout", "-q", "-b", "teammate", branch]);
+    repo.commit_file("teammate.txt", "theirs", "Teammate fix");
+    repo.git(&["push", "-q", "origin", &format!("teammate:{}", branch)]);
+    repo.git(&["checkout", "-q", branch]);
+    repo.git(&["branch", "-q", "-D", "teammate"]);
+    repo.git(&["update-ref", &tracking, &seen]);
+}
+
+#[test]
+fn submit_stops_when_someone_else_pushed_to_the_branch() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+    teammate_pushes(&repo, "feat-a");
+    repo.commit_file("mine.txt", "mine", "My fix");
+
+    // As from a script: stdin isn't a terminal, so nobody is asked
+    let out = repo.stack_with_env(&["submit"], "", &[("STACK_INTERACTIVE", "0")]);
+
+    assert_eq!(out.status.code(), Some(3));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("feat-a has diverged from origin/feat-a"),
+        "{}",
+        stdout
+    );
+    assert_eq!(
+        repo.remote_git(&["log", "-1", "--format=%s", "feat-a"]),
+        "Teammate fix"
+    );
+}
+
+#[test]
+fn submit_can_rebase_onto_the_remote_branch() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack"]);
+    repo.git(&["checkout", "-q", "feat-a"]);
+    teammate_pushes(&repo, "feat-a");
+    repo.commit_file("mine.txt", "mine", "My fix");
+
+    let out = repo.stack_with_input(&["submit"], "r\n");
+    common::assert_success(&out, &["submit"]);
+
+    assert_eq!(repo.current_branch(), "feat-a");
+    assert_eq!(
+        repo.subjects("main..feat-a"),
+        ["Teammate fix", "My fix", "Add feat-a"]
+    );
+    assert_eq!(
+        repo.remote_git(&["rev-parse", "feat-a"]),
+        repo.git(&["rev-parse", "feat-a"])
+    );
+    assert!(repo.is_ancestor("feat-a", "feat-b"));
+}
+
+#[test]
+fn submit_force_overwrites_the_remote_branch() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+    teammate_pushes(&repo, "feat-a");
+    repo.commit_file("mine.txt", "mine", "My fix");
+
+    repo.stack_ok(&["submit", "--force"]);
+
+    assert_eq!(
+        repo.remote_git(&["rev-parse", "feat-a"]),
+        repo.git(&["rev-parse", "feat-a"])
+    );
+}
+
+#[test]
+fn submit_adds_a_navigation_footer_to_every_pr_in_the_stack() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack"]);
+
+    let calls = repo.gh_calls().join("\n");
+    assert!(
+        calls.contains("This PR is part 1 of 2 in a stack:"),
+        "{}",
+        calls
+    );
+    assert!(
+        calls.contains("This PR is part 2 of 2 in a stack:"),
+        "{}",
+        calls
+    );
+    assert!(
+        calls.contains("2. **[#2](https://github.test/pr/2) feat-b** ← this PR"),
+        "{}",
+        calls
+    );
+    assert!(
+        calls.contains("Next: [#2](https://github.test/pr/2)"),
+        "{}",
+        calls
+    );
+    assert!(
+        calls.contains("Previous: [#1](https://github.test/pr/1)"),
+        "{}",
+        calls
+    );
+}
+
+#[test]
+fn submit_leaves_single_prs_and_disabled_footers_alone() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+    assert!(!repo.gh_calls().join("\n").contains("stack:footer"));
+
+    repo.new_branch("feat-b");
+    repo.git(&["config", "stack.pr-footer", "false"]);
+    repo.stack_ok(&["submit", "--stack"]);
+    assert!(!repo.gh_calls().join("\n").contains("stack:footer"));
+}
+
+#[test]
+fn pr_shows_the_branch_pr() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack"]);
+
+    let out = repo.stack_ok(&["pr"]);
+    assert!(out.contains("#2 https://github.test/pr/2"), "{}", out);
+    assert!(out.contains("State:     open"), "{}", out);
+
+    let out = repo.stack_ok(&["pr", "feat-a"]);
+    assert!(out.contains("#1 https://github.test/pr/1"), "{}", out);
+}
+
+#[test]
+fn pr_web_opens_the_browser() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+
+    let out = repo.stack_with_env(&["pr", "--web"], "", &[("BROWSER", "echo opening")]);
+    common::assert_success(&out, &["pr", "--web"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("opening https://github.test/pr/1"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn pr_without_a_pr_says_how_to_open_one() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+
+    let out = repo.stack(&["pr"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("No PR for feat-a"), "{}", stderr);
+}
+
+#[test]
+fn submit_draft_opens_draft_prs_that_publish_marks_ready() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.git(&["config", "stack.draft", "true"]);
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack"]);
+    assert!(repo.pr_is_draft("feat-a"));
+    assert!(repo.pr_is_draft("feat-b"));
+    assert!(repo.stack_ok(&["log"]).contains("#2 draft"));
+
+    repo.stack_ok(&["publish", "--stack"]);
+
+    assert!(!repo.pr_is_draft("feat-a"));
+    assert!(!repo.pr_is_draft("feat-b"));
+}
+
+#[test]
+fn submit_ready_overrides_the_draft_setting() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "stack.draft", "true"]);
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit", "--ready"]);
+    assert!(!repo.pr_is_draft("feat-a"));
+}
+
+#[test]
+fn publish_waits_for_the_stack_to_be_pushed_and_green() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.stack_ok(&["submit", "--stack", "--draft"]);
+    repo.set_pr_checks("feat-a", "SUCCESS,FAILURE");
+    repo.commit_file("more.txt", "more", "Unpushed work");
+
+    let out = repo.stack(&["publish", "--stack"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("feat-a has failing checks"), "{}", stderr);
+    assert!(
+        stderr.contains("feat-b has changes that aren't pushed"),
+        "{}",
+        stderr
+    );
+    assert!(repo.pr_is_draft("feat-a"));
+}
+
+/// The `stack/feat-a/<id>` branches `submit --per-commit` made, bottom-up.
+fn commit_branches(repo: &TestRepo) -> Vec<String> {
+    let mut branches: Vec<String> = repo
+        .git(&[
+            "for-each-ref",
+            "--format=%(refname:short)",
+            "refs/heads/stack/",
+        ])
+        .lines()
+        .map(str::to_string)
+        .collect();
+    branches.sort_by_key(|b| repo.subjects(&format!("main..{}", b)).len());
+    branches
+}
+
+#[test]
+fn submit_per_commit_opens_a_pr_for_each_commit_stacked_in_order() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.commit_file("two.txt", "two", "Second change");
+    repo.stack_ok(&["submit", "--per-commit"]);
+
+    let branches = commit_branches(&repo);
+    assert_eq!(branches.len(), 2, "{:?}", branches);
+    assert!(branches[0].starts_with("stack/feat-a/"));
+    assert_eq!(repo.pr_base(&branches[0]).as_deref(), Some("main"));
+    assert_eq!(
+        repo.pr_base(&branches[1]).as_deref(),
+        Some(branches[0].as_str())
+    );
+    assert_eq!(
+        repo.remote_git(&["log", "-1", "--format=%s", &branches[1]]),
+        "Second change"
+    );
+    assert!(
+        repo.gh_calls()
+            .iter()
+            .any(|c| c.starts_with("pr create") && c.contains("Second change"))
+    );
+
+    // The generated branches stay out of the stack itself
+    let log = repo.stack_ok(&["log"]);
+    assert!(!log.contains("stack/feat-a/"), "{}", log);
+}
+
+#[test]
+fn submit_per_commit_keeps_prs_across_amends_and_closes_dropped_commits() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.commit_file("two.txt", "two", "Second change");
+    repo.commit_file("three.txt", "three", "Third change");
+    repo.stack_ok(&["submit", "--per-commit"]);
+    let before = commit_branches(&repo);
+
+    // Drop the middle commit, then amend the last one
+    repo.git(&["rebase", "-q", "--onto", "HEAD~2", "HEAD~1"]);
+    repo.write_file("three.txt", "three, fixed");
+    repo.git(&["commit", "-q", "-a", "--amend", "--no-edit"]);
+    repo.stack_ok(&["submit", "--per-commit"]);
+
+    assert_eq!(
+        commit_branches(&repo),
+        vec![before[0].clone(), before[2].clone()]
+    );
+    assert_eq!(
+        repo.pr_base(&before[2]).as_deref(),
+        Some(before[0].as_str())
+    );
+    assert_eq!(
+        repo.remote_git(&["show", &format!("{}:three.txt", before[2])]),
+        "three, fixed"
+    );
+    assert_eq!(repo.pr_state(&before[1]).as_deref(), Some("CLOSED"));
+    assert_eq!(
+        repo.gh_calls()
+            .iter()
+            .filter(|c| c.starts_with("pr create"))
+            .count(),
+        3
+    );
+}
+
+#[test]
+fn submit_without_gh_login_says_how_to_log_in_before_pushing() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.log_out_gh();
+
+    let out = repo.stack(&["submit"]);
+
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("run `gh auth login`"), "{}", stderr);
+    assert!(stderr.contains("set GITHUB_TOKEN"), "{}", stderr);
+    assert!(
+        !repo
+            .remote_git(&["branch", "--list", "feat-a"])
+            .contains("feat-a")
+    );
+}
+
+#[test]
+fn submit_without_gh_or_a_token_suggests_installing_gh() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.uninstall_gh();
+
+    let out = repo.stack(&["submit"]);
+
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("install gh"), "{}", stderr);
+    assert!(stderr.contains("set GITHUB_TOKEN"), "{}", stderr);
+}
+
+#[test]
+fn submit_retries_transient_gh_failures() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.git(&["config", "stack.retry-backoff", "0"]);
+    repo.fail_gh(
+        "pr create",
+        &["HTTP 502: Bad Gateway", "connection reset by peer"],
+    );
+
+    let out = repo.stack(&["submit"]);
+
+    common::assert_success(&out, &["submit"]);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains(
+            "`gh pr create` failed (HTTP 502: Bad Gateway); retrying in 0s (2 of 3 attempts)"
+        ),
+        "{}",
+        stderr
+    );
+    assert_eq!(repo.pr_base("feat-a").as_deref(), Some("main"));
+}
+
+#[test]
+fn submit_does_not_retry_genuine_gh_errors() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.git(&["config", "stack.retry-backoff", "0"]);
+    repo.fail_gh("pr create", &["GraphQL: Head sha can't be blank"]);
+
+    let out = repo.stack(&["submit"]);
+
+    assert!(!out.status.success());
+    assert!(!String::from_utf8_lossy(&out.stderr).contains("retrying"));
+    let creates = repo
+        .gh_calls()
+        .iter()
+        .filter(|c| c.starts_with("pr create"))
+        .count();
+    assert_eq!(creates, 1);
+}
+
+#[test]
+fn submit_warns_about_branches_over_the_size_limit() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.commit_file("big.txt", "1\n2\n3\n4\n", "Add big");
+    repo.git(&["config", "stack.max-lines", "3"]);
+
+    let out = repo.stack(&["submit"]);
+    common::assert_success(&out, &["submit"]);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("feat-a: 5 lines changed (max 3)"),
+        "{}",
+        stderr
+    );
+    assert!(repo.pr_base("feat-a").is_some());
+}
+
+#[test]
+fn size_limits_from_stack_toml_can_block_submit() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.commit_file("two.txt", "two", "Add two");
+    repo.write_file(".stack.toml", "max-files = 1\nsize-limit = \"block\"\n");
+
+    let out = repo.stack(&["submit"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("feat-a: 2 files (max 1)"), "{}", stderr);
+    assert!(repo.pr_base("feat-a").is_none());
+
+    repo.stack_ok(&["submit", "--no-verify"]);
+    assert!(repo.pr_base("feat-a").is_some());
+}
+
+#[test]
+fn submit_keeps_a_typed_description_when_creating_the_pr_fails() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.fail_gh("pr create", &["GraphQL: Head sha can't be blank"]);
+
+    let editor = "printf 'Typed title\\n\\nTyped body\\n' >";
+    let out = repo.stack_with_env(&["submit"], "", &[("GIT_EDITOR", editor)]);
+    assert!(!out.status.success());
+    assert!(repo.path.join(".git/stack/drafts/feat-a").exists());
+
+    // No editor this time: the saved description is used as it is
+    let out = repo.stack_with_env(&["submit"], "", &[("GIT_EDITOR", "false")]);
+    common::assert_success(&out, &["submit"]);
+    let create = repo
+        .gh_calls()
+        .into_iter()
+        .rfind(|c| c.starts_with("pr create"))
+        .unwrap();
+    assert!(
+        create.contains("--title Typed title --body Typed body"),
+        "{}",
+        create
+    );
+    assert!(!repo.path.join(".git/stack/drafts/feat-a").exists());
+}
+
+#[test]
+fn submit_edit_description_updates_an_open_pr() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+
+    let out = repo.stack_with_env(
+        &["submit", "--edit-description"],
+        "",
+        &[("GIT_EDITOR", "printf 'Better title\\n\\nMore words\\n' >")],
+    );
+    common::assert_success(&out, &["submit", "--edit-description"]);
+
+    assert!(
+        repo.gh_calls()
+            .iter()
+            .any(|c| c.starts_with("pr edit") && c.contains("--title Better title")),
+        "{:?}",
+        repo.gh_calls()
+    );
+}
+
+#[test]
+fn submit_output_json_reports_pushes_and_new_prs_as_json_lines() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+
+    let out = repo.stack(&["submit", "--stack", "--output", "json"]);
+    common::assert_success(&out, &["submit", "--stack", "--output", "json"]);
+
+    let events: Vec<serde_json::Value> = String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("every stdout line is an event"))
+        .collect();
+    let pushed: Vec<&str> = events
+        .iter()
+        .filter(|e| e["event"] == "pushed")
+        .map(|e| e["branch"].as_str().unwrap())
+        .collect();
+    assert_eq!(pushed, ["feat-a", "feat-b"]);
+    let created = events
+        .iter()
+        .find(|e| e["event"] == "pr_created" && e["branch"] == "feat-b")
+        .expect("pr_created for feat-b");
+    assert_eq!(created["number"], 2);
+    assert_eq!(created["url"], "https://github.test/pr/2");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Creating PR for feat-b"));
+}
+
+#[test]
+fn submit_sets_the_upstream_that_status_compares_against() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+
+    assert_eq!(
+        repo.config("branch.feat-a.remote").as_deref(),
+        Some("origin")
+    );
+    assert_eq!(
+        repo.config("branch.feat-a.merge").as_deref(),
+        Some("refs/heads/feat-a")
+    );
+    repo.commit_file("more.txt", "more", "More on feat-a");
+    let out = repo.stack_ok(&["status"]);
+    assert!(
+        out.contains("Remote:   origin/feat-a (1 commit(s) not pushed)"),
+        "{}",
+        out
+    );
+
+    repo.git(&["branch", "-q", "--set-upstream-to", "origin/main"]);
+    let out = repo.stack_ok(&["status"]);
+    assert!(
+        out.contains("Remote:   origin/main (2 commit(s) not pushed)"),
+        "{}",
+        out
+    );
+}
+
+#[test]
+fn fetch_meta_skips_bad_entries_and_imports_the_rest() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.stack_ok(&["submit"]);
+    let commit = repo.remote_git(&["rev-parse", "main"]);
+    repo.remote_git(&["update-ref", "refs/stack-meta/bogus", &commit]);
+    repo.git(&["config", "--unset", "branch.feat-a.stack-parent"]);
+
+    let out = repo.stack(&["fetch-meta"]);
+    common::assert_success(&out, &["fetch-meta"]);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("skipping refs/stack-meta/bogus"),
+        "{}",
+        stderr
+    );
+    assert_eq!(repo.parent("feat-a").as_deref(), Some("main"));
+}
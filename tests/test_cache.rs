@@ -0,0 +1,62 @@
+mod common;
+
+use common::TestRepo;
+
+/// Branches the test command has run on so far, in order.
+fn tested(repo: &TestRepo) -> Vec<String> {
+    std::fs::read_to_string(repo.path.join(".git/tested"))
+        .unwrap_or_default()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn test_skips_branches_whose_head_already_passed() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.new_branch("feat-b");
+    repo.git(&[
+        "config",
+        "stack.test-command",
+        "echo $STACK_BRANCH >> .git/tested",
+    ]);
+
+    repo.stack_ok(&["test"]);
+    assert_eq!(tested(&repo), ["feat-a", "feat-b"]);
+
+    repo.commit_file("more.txt", "more", "More on b");
+    let out = repo.stack_ok(&["test"]);
+    assert_eq!(tested(&repo), ["feat-a", "feat-b", "feat-b"]);
+    assert!(out.contains("feat-a  ok (cached)"), "{}", out);
+    assert_eq!(repo.current_branch(), "feat-b");
+
+    repo.stack_ok(&["test", "--force"]);
+    assert_eq!(tested(&repo).len(), 5);
+}
+
+#[test]
+fn require_tests_gates_submit_on_a_passing_run() {
+    let repo = TestRepo::new();
+    repo.new_branch("feat-a");
+    repo.git(&["config", "stack.require-tests", "true"]);
+    repo.git(&["config", "stack.test-command", "test -f pass"]);
+
+    let out = repo.stack(&["submit"]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("feat-a hasn't been tested at its head"),
+        "{}",
+        stderr
+    );
+
+    assert!(!repo.stack(&["test"]).status.success());
+    let stderr = String::from_utf8_lossy(&repo.stack(&["submit"]).stderr).to_string();
+    assert!(stderr.contains("feat-a failed its tests"), "{}", stderr);
+
+    repo.commit_file("pass", "", "Make tests pass");
+    repo.stack_ok(&["test"]);
+    repo.stack_ok(&["submit"]);
+    assert_eq!(repo.pr_base("feat-a").as_deref(), Some("main"));
+}